@@ -1,4 +1,8 @@
+use crate::config::{Config, Endpoints};
+use crate::http::{self, ProviderError};
 use serde::Deserialize;
+use std::future::Future;
+use std::pin::Pin;
 
 #[derive(Debug, Deserialize)]
 struct DeezerSearchResponse {
@@ -7,9 +11,17 @@ struct DeezerSearchResponse {
 
 #[derive(Debug, Deserialize)]
 struct DeezerAlbum {
+    #[serde(default)]
+    title: String,
+    artist: Option<DeezerArtist>,
     cover_big: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+struct DeezerArtist {
+    name: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct LastFmResponse {
     results: Option<LastFmResults>,
@@ -27,6 +39,10 @@ struct LastFmAlbumMatches {
 
 #[derive(Debug, Deserialize)]
 struct LastFmAlbum {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    artist: String,
     image: Vec<LastFmImage>,
 }
 
@@ -45,61 +61,315 @@ struct MusicBrainzResponse {
 #[derive(Debug, Deserialize)]
 struct MusicBrainzRelease {
     id: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default, rename = "artist-credit")]
+    artist_credit: Vec<MusicBrainzCredit>,
 }
 
-/// Fetch album art from multiple sources with fallback chain
-pub async fn fetch_album_art(artist: &str, album: &str) -> Option<String> {
-    // Try sources in order of reliability and coverage
-    
-    // 1. Try Deezer (no API key required, good coverage, fast)
-    if let Some(url) = fetch_deezer_art(artist, album).await {
-        eprintln!("[Album Art] Found via Deezer: {}", url);
-        return Some(url);
+#[derive(Debug, Deserialize)]
+struct MusicBrainzCredit {
+    name: String,
+    #[serde(default)]
+    joinphrase: String,
+}
+
+/// Built-in providers, in the default lookup order
+pub const BUILTIN_PROVIDERS: &[&str] = &["deezer", "itunes", "musicbrainz", "lastfm"];
+
+/// A cover URL and the provider that found it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtResult {
+    pub url: String,
+    pub provider: String,
+    /// Chosen by hand for the album rather than found automatically
+    pub pinned: bool,
+}
+
+/// A cover a provider offers, with the artist and album of the release it belongs to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtCandidate {
+    pub url: String,
+    pub artist: String,
+    pub album: String,
+}
+
+/// Covers found, or why the service couldn't be asked
+pub type ArtFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<ArtCandidate>, ProviderError>> + Send + 'a>>;
+
+/// Providers that couldn't be asked, and why
+pub type ProviderFailures = Vec<(&'static str, ProviderError)>;
+
+/// "deezer: HTTP 500 Internal Server Error; itunes: rate limited (HTTP 503 Service Unavailable)"
+pub fn describe_failures(failures: &ProviderFailures) -> String {
+    failures.iter().map(|(provider, why)| format!("{}: {}", provider, why)).collect::<Vec<_>>().join("; ")
+}
+
+/// A service that can find cover images for an album
+pub trait ArtProvider: Send + Sync {
+    /// Name used in the config and shown with results, e.g. "deezer"
+    fn name(&self) -> &'static str;
+    /// Covers from the service's search results, in the order it ranked them
+    fn fetch<'a>(&'a self, artist: &'a str, album: &'a str) -> ArtFuture<'a>;
+}
+
+/// Deezer album search; no API key needed, good coverage, fast
+pub struct Deezer {
+    pub base_url: String,
+}
+
+impl ArtProvider for Deezer {
+    fn name(&self) -> &'static str {
+        "deezer"
     }
-    
-    // 2. Try iTunes (fast, good for mainstream)
-    if let Some(url) = fetch_itunes_art(artist, album).await {
-        eprintln!("[Album Art] Found via iTunes: {}", url);
-        return Some(url);
+
+    fn fetch<'a>(&'a self, artist: &'a str, album: &'a str) -> ArtFuture<'a> {
+        Box::pin(fetch_deezer_art(&self.base_url, artist, album))
     }
-    
-    // 3. Try MusicBrainz + Cover Art Archive (comprehensive but slower)
-    if let Some(url) = fetch_musicbrainz_art(artist, album).await {
-        eprintln!("[Album Art] Found via MusicBrainz: {}", url);
-        return Some(url);
+}
+
+/// iTunes Search API; fast, good for mainstream releases
+pub struct Itunes {
+    pub base_url: String,
+}
+
+impl ArtProvider for Itunes {
+    fn name(&self) -> &'static str {
+        "itunes"
     }
-    
-    // 4. Try Last.fm (requires API key, but good coverage)
-    if let Some(url) = fetch_lastfm_art(artist, album).await {
-        eprintln!("[Album Art] Found via Last.fm: {}", url);
-        return Some(url);
+
+    fn fetch<'a>(&'a self, artist: &'a str, album: &'a str) -> ArtFuture<'a> {
+        Box::pin(crate::itunes_art::fetch_itunes_art(&self.base_url, artist, album))
     }
-    
-    eprintln!("[Album Art] No cover found for: {} - {}", artist, album);
-    None
+}
+
+/// MusicBrainz release search plus the Cover Art Archive; comprehensive but slower
+pub struct MusicBrainz {
+    pub base_url: String,
+    /// Where the release covers are checked and linked
+    pub cover_base_url: String,
+}
+
+impl ArtProvider for MusicBrainz {
+    fn name(&self) -> &'static str {
+        "musicbrainz"
+    }
+
+    fn fetch<'a>(&'a self, artist: &'a str, album: &'a str) -> ArtFuture<'a> {
+        Box::pin(fetch_musicbrainz_art(&self.base_url, &self.cover_base_url, artist, album))
+    }
+}
+
+/// Last.fm album search; only answers when it has an API key
+pub struct LastFm {
+    pub base_url: String,
+    /// From LASTFM_API_KEY when built with `provider_by_name`
+    pub api_key: Option<String>,
+}
+
+impl ArtProvider for LastFm {
+    fn name(&self) -> &'static str {
+        "lastfm"
+    }
+
+    fn fetch<'a>(&'a self, artist: &'a str, album: &'a str) -> ArtFuture<'a> {
+        Box::pin(fetch_lastfm_art(&self.base_url, self.api_key.as_deref(), artist, album))
+    }
+}
+
+/// A built-in provider talking to the services at `endpoints`
+pub fn provider_by_name(name: &str, endpoints: &Endpoints) -> Option<Box<dyn ArtProvider>> {
+    let provider: Box<dyn ArtProvider> = match name.to_lowercase().as_str() {
+        "deezer" => Box::new(Deezer { base_url: endpoints.deezer.clone() }),
+        "itunes" => Box::new(Itunes { base_url: endpoints.itunes.clone() }),
+        "musicbrainz" => Box::new(MusicBrainz {
+            base_url: endpoints.musicbrainz.clone(),
+            cover_base_url: endpoints.coverartarchive.clone(),
+        }),
+        "lastfm" => Box::new(LastFm {
+            base_url: endpoints.lastfm.clone(),
+            api_key: std::env::var("LASTFM_API_KEY").ok(),
+        }),
+        _ => return None,
+    };
+    Some(provider)
+}
+
+/// Enabled providers in configured order; built-in ones the config doesn't mention come last
+pub fn providers_from_config(config: &Config) -> Vec<Box<dyn ArtProvider>> {
+    let mut providers: Vec<Box<dyn ArtProvider>> = Vec::new();
+    for entry in &config.art_providers {
+        match provider_by_name(&entry.name, &config.endpoints) {
+            Some(provider) if entry.enabled => providers.push(provider),
+            Some(_) => {}
+            None => eprintln!("[Album Art] Unknown provider in config: {}", entry.name),
+        }
+    }
+    for name in BUILTIN_PROVIDERS {
+        if !config.art_providers.iter().any(|p| p.name.eq_ignore_ascii_case(name)) {
+            providers.extend(provider_by_name(name, &config.endpoints));
+        }
+    }
+    providers
+}
+
+// Comparable form of a name: lowercase words without punctuation, a leading "the" or
+// bracketed extras like "(Deluxe Edition)", so "The Wall [Remastered]" matches "wall"
+fn match_key(name: &str) -> String {
+    let mut depth = 0;
+    let mut unbracketed = String::new();
+    for c in name.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = (depth - 1).max(0),
+            // "Don't" is one word
+            '\'' | '’' => {}
+            _ if depth == 0 => unbracketed.push(c),
+            _ => {}
+        }
+    }
+    let words = |s: &str| -> Vec<String> {
+        s.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(|w| w.to_lowercase()).collect()
+    };
+    let mut key = words(&unbracketed);
+    // A name that is all brackets keeps them
+    if key.is_empty() {
+        key = words(name);
+    }
+    if key.len() > 1 && key[0] == "the" {
+        key.remove(0);
+    }
+    key.join(" ")
+}
+
+/// How alike two names are, from 0.0 to 1.0: one minus the edit distance of their match keys,
+/// relative to the longer key
+pub fn similarity(a: &str, b: &str) -> f32 {
+    let (a, b): (Vec<char>, Vec<char>) = (match_key(a).chars().collect(), match_key(b).chars().collect());
+    if a == b {
+        return 1.0;
+    }
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    1.0 - row[b.len()] as f32 / a.len().max(b.len()) as f32
+}
+
+/// How well a candidate fits the album we want: the weaker of the artist and album similarities.
+/// An artist missing on either side isn't held against it.
+pub fn match_score(artist: &str, album: &str, candidate: &ArtCandidate) -> f32 {
+    let album_score = similarity(album, &candidate.album);
+    if artist.trim().is_empty() || candidate.artist.trim().is_empty() {
+        return album_score;
+    }
+    album_score.min(similarity(artist, &candidate.artist))
+}
+
+/// The best scoring candidate, if it scores at least `min_score`
+pub fn best_match<'c>(artist: &str, album: &str, candidates: &'c [ArtCandidate], min_score: f32) -> Option<(&'c ArtCandidate, f32)> {
+    candidates.iter()
+        .map(|c| (c, match_score(artist, album, c)))
+        .filter(|(_, score)| *score >= min_score)
+        // The earlier of equally good candidates, as the service ranked it higher
+        .fold(None, |best: Option<(&ArtCandidate, f32)>, (c, score)| match best {
+            Some((_, best_score)) if best_score >= score => best,
+            _ => Some((c, score)),
+        })
+}
+
+/// Ask each provider in turn until one offers a cover matching at least `min_score`.
+/// `Ok(None)` means every provider answered without a match; when some couldn't be asked
+/// and none matched, the failures are returned instead.
+pub async fn fetch_from(providers: &[Box<dyn ArtProvider>], artist: &str, album: &str, min_score: f32) -> Result<Option<ArtResult>, ProviderFailures> {
+    let mut failures = Vec::new();
+    for provider in providers {
+        let candidates = match provider.fetch(artist, album).await {
+            Ok(candidates) => candidates,
+            Err(e) => {
+                eprintln!("[Album Art] {} failed: {}", provider.name(), e);
+                failures.push((provider.name(), e));
+                continue;
+            }
+        };
+        match best_match(artist, album, &candidates, min_score) {
+            Some((found, score)) => {
+                eprintln!("[Album Art] Found via {} ({} - {}, score {:.2}): {}", provider.name(), found.artist, found.album, score, found.url);
+                return Ok(Some(ArtResult { url: found.url.clone(), provider: provider.name().to_string(), pinned: false }));
+            }
+            None if !candidates.is_empty() => {
+                eprintln!("[Album Art] {} had {} covers, none matching {} - {} well enough", provider.name(), candidates.len(), artist, album);
+            }
+            None => {}
+        }
+    }
+    if failures.is_empty() {
+        eprintln!("[Album Art] No cover found for: {} - {}", artist, album);
+        Ok(None)
+    } else {
+        eprintln!("[Album Art] No cover found for: {} - {} ({})", artist, album, describe_failures(&failures));
+        Err(failures)
+    }
+}
+
+/// Every provider's candidates with their match scores, best first, for choosing a cover by hand
+pub async fn scored_candidates(providers: &[Box<dyn ArtProvider>], artist: &str, album: &str) -> Vec<(&'static str, ArtCandidate, f32)> {
+    let mut scored = Vec::new();
+    for provider in providers {
+        let candidates = provider.fetch(artist, album).await.unwrap_or_else(|e| {
+            eprintln!("[Album Art] {} failed: {}", provider.name(), e);
+            Vec::new()
+        });
+        for candidate in candidates {
+            let score = match_score(artist, album, &candidate);
+            scored.push((provider.name(), candidate, score));
+        }
+    }
+    scored.sort_by(|a, b| b.2.total_cmp(&a.2));
+    scored
+}
+
+/// Fetch album art from the providers configured in CONFIG_PATH
+pub async fn fetch_album_art(artist: &str, album: &str) -> Result<Option<ArtResult>, ProviderFailures> {
+    let config = Config::load();
+    let providers = providers_from_config(&config);
+    fetch_from(&providers, artist, album, config.art_match_threshold).await
 }
 
 /// Fetch from Deezer API (no authentication required)
-async fn fetch_deezer_art(artist: &str, album: &str) -> Option<String> {
+async fn fetch_deezer_art(base_url: &str, artist: &str, album: &str) -> Result<Vec<ArtCandidate>, ProviderError> {
     let query = format!("{} {}", artist, album);
     let url = format!(
-        "https://api.deezer.com/search/album?q={}",
+        "{}/search/album?q={}",
+        base_url.trim_end_matches('/'),
         urlencoding::encode(&query)
     );
     
-    let resp = reqwest::get(&url).await.ok()?;
-    let json: DeezerSearchResponse = resp.json().await.ok()?;
+    let json: DeezerSearchResponse = http::get_json(&http::DEEZER, &url).await?;
     
-    json.data.first()?.cover_big.clone()
+    Ok(json.data.into_iter()
+        .filter_map(|found| Some(ArtCandidate {
+            url: found.cover_big?,
+            artist: found.artist.map(|a| a.name).unwrap_or_default(),
+            album: found.title,
+        }))
+        .collect())
 }
 
 /// Fetch from MusicBrainz + Cover Art Archive
-async fn fetch_musicbrainz_art(artist: &str, album: &str) -> Option<String> {
-    let client = reqwest::Client::builder()
-        .user_agent("PSPPlaylistMaker/0.1.0 (github.com/user/psp_playlist_maker)")
-        .timeout(std::time::Duration::from_secs(5))
-        .build()
-        .ok()?;
+async fn fetch_musicbrainz_art(base_url: &str, cover_base_url: &str, artist: &str, album: &str) -> Result<Vec<ArtCandidate>, ProviderError> {
+    // The last query error; reported only if no query got an answer
+    let mut error = None;
+    let mut answered = false;
     
     // Try different query formats - MusicBrainz is picky about syntax
     let queries = vec![
@@ -110,102 +380,78 @@ async fn fetch_musicbrainz_art(artist: &str, album: &str) -> Option<String> {
     
     for query in queries {
         let url = format!(
-            "https://musicbrainz.org/ws/2/release/?query={}&fmt=json&limit=5",
+            "{}/ws/2/release/?query={}&fmt=json&limit=5",
+            base_url.trim_end_matches('/'),
             urlencoding::encode(&query)
         );
         
         eprintln!("[MusicBrainz] Trying query: {}", query);
         
-        let resp = match client.get(&url).send().await {
-            Ok(r) => r,
-            Err(e) => {
-                eprintln!("[MusicBrainz] Request failed: {}", e);
-                continue;
-            }
-        };
-        
-        let json: MusicBrainzResponse = match resp.json().await {
+        let json: MusicBrainzResponse = match http::get_json(&http::MUSICBRAINZ, &url).await {
             Ok(j) => j,
             Err(e) => {
-                eprintln!("[MusicBrainz] JSON parse failed: {}", e);
+                eprintln!("[MusicBrainz] Query failed: {}", e);
+                error = Some(e);
                 continue;
             }
         };
+        answered = true;
         
-        if let Some(releases) = json.releases {
-            // Try each release until we find one with cover art
-            for release in releases {
-                let cover_url = format!("https://coverartarchive.org/release/{}/front-500", release.id);
-                
-                // Check if the cover exists (HEAD request is fast)
-                match client.head(&cover_url).send().await {
-                    Ok(resp) if resp.status().is_success() => {
-                        eprintln!("[MusicBrainz] Found cover for release: {}", release.id);
-                        return Some(cover_url);
-                    }
-                    Ok(resp) => {
-                        eprintln!("[MusicBrainz] No cover for release {}: status {}", release.id, resp.status());
-                    }
-                    Err(e) => {
-                        eprintln!("[MusicBrainz] Cover check failed for {}: {}", release.id, e);
-                    }
+        // Every release of this query that has cover art
+        let mut candidates = Vec::new();
+        for release in json.releases.unwrap_or_default() {
+            let cover_url = format!("{}/release/{}/front-500", cover_base_url.trim_end_matches('/'), release.id);
+            
+            // Check if the cover exists (HEAD request is fast)
+            match http::send(&http::COVERARTARCHIVE, reqwest::Method::HEAD, &cover_url).await {
+                Ok(resp) if resp.status().is_success() => {
+                    eprintln!("[MusicBrainz] Found cover for release: {}", release.id);
+                    let artist = release.artist_credit.iter().map(|c| format!("{}{}", c.name, c.joinphrase)).collect();
+                    candidates.push(ArtCandidate { url: cover_url, artist, album: release.title });
+                }
+                Ok(resp) => {
+                    eprintln!("[MusicBrainz] No cover for release {}: status {}", release.id, resp.status());
+                }
+                Err(e) => {
+                    eprintln!("[MusicBrainz] Cover check failed for {}: {}", release.id, e);
                 }
             }
         }
-        
-        // MusicBrainz rate limiting: wait 1 second between queries
-        tokio::time::sleep(std::time::Duration::from_millis(1000)).await;
+        if !candidates.is_empty() {
+            return Ok(candidates);
+        }
     }
     
-    None
+    match error {
+        Some(e) if !answered => Err(e),
+        _ => Ok(Vec::new()),
+    }
 }
 
-/// Fetch from Last.fm API
-async fn fetch_lastfm_art(_artist: &str, album: &str) -> Option<String> {
+/// Fetch from Last.fm API; album search only, the artist is matched against the results
+async fn fetch_lastfm_art(base_url: &str, api_key: Option<&str>, artist: &str, album: &str) -> Result<Vec<ArtCandidate>, ProviderError> {
     // Note: You need to get a free API key from https://www.last.fm/api/account/create
-    // For now, we'll make it optional - if no key is set, this just returns None
-    let api_key = std::env::var("LASTFM_API_KEY").ok()?;
+    // For now, we'll make it optional - if no key is set, this finds nothing
+    let Some(api_key) = api_key else {
+        return Ok(Vec::new());
+    };
     
     let url = format!(
-        "https://ws.audioscrobbler.com/2.0/?method=album.search&album={}&api_key={}&format=json",
+        "{}/2.0/?method=album.search&album={}&artist={}&api_key={}&format=json",
+        base_url.trim_end_matches('/'),
         urlencoding::encode(album),
+        urlencoding::encode(artist),
         api_key
     );
     
-    let resp = reqwest::get(&url).await.ok()?;
-    let json: LastFmResponse = resp.json().await.ok()?;
-    
-    let albums = json.results?.albummatches?.album;
-    if let Some(first_album) = albums.first() {
-        // Get the largest image
-        for img in &first_album.image {
-            if img.size == "extralarge" && !img.text.is_empty() {
-                return Some(img.text.clone());
-            }
-        }
-    }
-    
-    None
-}
-
-/// Fetch from iTunes API (original implementation)
-async fn fetch_itunes_art(artist: &str, album: &str) -> Option<String> {
-    let query = format!("{} {}", artist, album);
-    let url = format!(
-        "https://itunes.apple.com/search?term={}&entity=album&limit=1",
-        urlencoding::encode(&query)
-    );
-    
-    let resp = reqwest::get(&url).await.ok()?;
-    let json: serde_json::Value = resp.json().await.ok()?;
-    let results = json["results"].as_array()?;
-    
-    if let Some(album) = results.first() {
-        if let Some(art_url) = album["artworkUrl100"].as_str() {
-            // Use higher-res version
-            return Some(art_url.replace("100x100bb", "600x600bb"));
-        }
-    }
+    let json: LastFmResponse = http::get_json(&http::LASTFM, &url).await?;
     
-    None
+    let albums = json.results.and_then(|r| r.albummatches).map(|m| m.album).unwrap_or_default();
+    Ok(albums.into_iter()
+        .filter_map(|found| {
+            // Get the largest image
+            let image = found.image.into_iter().find(|img| img.size == "extralarge" && !img.text.is_empty())?;
+            Some(ArtCandidate { url: image.text, artist: found.artist, album: found.name })
+        })
+        .collect())
 }
@@ -0,0 +1,175 @@
+use crate::album_art::{self, ArtResult, ProviderFailures};
+use crate::art_embed::CoverSource;
+use crate::config::Config;
+use crate::db;
+use crate::http::{self, ProviderError};
+use rusqlite::{Connection, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Cache key form of an artist or album name: "  The  Beatles " and "the beatles" share an entry
+pub fn normalize(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+/// Album art lookups and downloaded covers, kept in the index database between sessions.
+/// Found covers stay until refreshed; albums nothing was found for are looked up again after a while.
+pub struct ArtCache {
+    conn: Connection,
+    missing_ttl: Duration,
+}
+
+impl ArtCache {
+    pub fn open(db_path: &str, config: &Config) -> Result<Self> {
+        let missing_ttl = Duration::from_secs(config.missing_art_ttl_days * 24 * 60 * 60);
+        Ok(Self::with_connection(db::init_db(db_path)?, missing_ttl))
+    }
+
+    pub fn with_connection(conn: Connection, missing_ttl: Duration) -> Self {
+        ArtCache { conn, missing_ttl }
+    }
+
+    /// `Some(None)` when the album recently had no art anywhere, `None` when it needs looking up
+    pub fn lookup(&self, artist: &str, album: &str) -> Option<Option<ArtResult>> {
+        let (found, looked_up_at) = match db::load_art_lookup(&self.conn, &normalize(artist), &normalize(album)) {
+            Ok(entry) => entry?,
+            Err(e) => {
+                eprintln!("[Art Cache] Lookup failed: {}", e);
+                return None;
+            }
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        if found.is_none() && now.saturating_sub(looked_up_at) >= self.missing_ttl.as_secs() {
+            return None;
+        }
+        Some(found)
+    }
+
+    pub fn store(&self, artist: &str, album: &str, found: Option<&ArtResult>) -> Result<()> {
+        db::save_art_lookup(&self.conn, &normalize(artist), &normalize(album), found)
+    }
+
+    /// Drop the album's lookup and cover so the next request asks the providers again
+    pub fn forget(&self, artist: &str, album: &str) -> Result<()> {
+        db::forget_art_lookup(&self.conn, &normalize(artist), &normalize(album))
+    }
+
+    /// The cover chosen by hand for the album, if any
+    pub fn pinned(&self, artist: &str, album: &str) -> Option<ArtResult> {
+        db::load_art_pin(&self.conn, &normalize(artist), &normalize(album))
+            .map_err(|e| eprintln!("[Art Cache] Pin lookup failed: {}", e))
+            .ok()
+            .flatten()
+    }
+
+    /// Show `art` for the album from now on, whatever the lookups find
+    pub fn pin(&self, artist: &str, album: &str, art: &ArtResult) -> Result<()> {
+        db::save_art_pin(&self.conn, &normalize(artist), &normalize(album), art)
+    }
+
+    pub fn unpin(&self, artist: &str, album: &str) -> Result<()> {
+        db::delete_art_pin(&self.conn, &normalize(artist), &normalize(album))
+    }
+
+    /// Image bytes behind a cover URL, downloaded on first use and kept; local covers are read each time
+    pub fn image(&self, url: &str) -> Option<Vec<u8>> {
+        let source = CoverSource::from_url(url);
+        if source.is_local() {
+            return source.load().ok();
+        }
+        if let Ok(Some(bytes)) = db::load_art_image(&self.conn, url) {
+            return Some(bytes);
+        }
+        let bytes = source.load().ok()?;
+        if let Err(e) = db::save_art_image(&self.conn, url, &bytes) {
+            eprintln!("[Art Cache] Could not keep image {}: {}", url, e);
+        }
+        Some(bytes)
+    }
+}
+
+fn open_or_warn(db_path: &str, config: &Config) -> Option<ArtCache> {
+    ArtCache::open(db_path, config)
+        .map_err(|e| eprintln!("[Art Cache] Not caching, {} unavailable: {}", db_path, e))
+        .ok()
+}
+
+/// The cover pinned for an album in the cache at `db_path`, if any
+pub fn pinned_art(db_path: &str, artist: &str, album: &str) -> Option<ArtResult> {
+    open_or_warn(db_path, &Config::load())?.pinned(artist, album)
+}
+
+/// Album art for an album: a pinned cover, else art in its `tracks` or their folders, then the configured
+/// providers, answered from the cache in `db_path` when it can be. Local art isn't cached, so new covers
+/// show up at once; neither are lookups that failed, so they are tried again next time.
+/// In offline mode the providers aren't asked, and every one is reported as offline.
+/// Blocks; call it off the UI thread and outside any async runtime.
+pub fn fetch_album_art_cached(db_path: &str, artist: &str, album: &str, tracks: &[PathBuf]) -> Result<Option<ArtResult>, ProviderFailures> {
+    let config = Config::load();
+    let cache = open_or_warn(db_path, &config);
+    if let Some(pinned) = cache.as_ref().and_then(|c| c.pinned(artist, album)) {
+        return Ok(Some(pinned));
+    }
+    if let Some(found) = crate::local_art::find_local_art(tracks, &config.sidecar_art_names) {
+        return Ok(Some(found));
+    }
+    if let Some(hit) = cache.as_ref().and_then(|c| c.lookup(artist, album)) {
+        return Ok(hit);
+    }
+    let providers = album_art::providers_from_config(&config);
+    if http::is_offline() {
+        return Err(providers.iter().map(|p| (p.name(), ProviderError::Offline(http::OFFLINE_MODE.to_string()))).collect());
+    }
+    let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let found = rt.block_on(album_art::fetch_from(&providers, artist, album, config.art_match_threshold))?;
+    if let Some(cache) = &cache {
+        if let Err(e) = cache.store(artist, album, found.as_ref()) {
+            eprintln!("[Art Cache] Could not keep lookup: {}", e);
+        }
+    }
+    Ok(found)
+}
+
+/// A cover offered for choosing by hand
+#[derive(Debug, Clone)]
+pub struct CoverChoice {
+    pub art: ArtResult,
+    /// "Artist - Album" as the provider names the release; empty for local art
+    pub release: String,
+    /// How well the release matches the album; local art has none
+    pub score: Option<f32>,
+    pub image: Option<Vec<u8>>,
+}
+
+/// Every cover on offer for an album, images included: local art first, then up to `per_provider`
+/// covers from each configured provider, best match first; only local art in offline mode. Nothing is cached.
+/// Blocks, like `fetch_album_art_cached`.
+pub fn gather_choices(artist: &str, album: &str, tracks: &[PathBuf], per_provider: usize) -> Vec<CoverChoice> {
+    let config = Config::load();
+    let mut choices: Vec<CoverChoice> = crate::local_art::local_covers(tracks, &config.sidecar_art_names)
+        .into_iter()
+        .map(|art| CoverChoice { art, release: String::new(), score: None, image: None })
+        .collect();
+    let providers = if http::is_offline() { Vec::new() } else { album_art::providers_from_config(&config) };
+    if let Ok(rt) = tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        let mut taken: HashMap<&str, usize> = HashMap::new();
+        for (provider, candidate, score) in rt.block_on(album_art::scored_candidates(&providers, artist, album)) {
+            let count = taken.entry(provider).or_default();
+            if *count >= per_provider || choices.iter().any(|c| c.art.url == candidate.url) {
+                continue;
+            }
+            *count += 1;
+            choices.push(CoverChoice {
+                art: ArtResult { url: candidate.url, provider: provider.to_string(), pinned: false },
+                release: format!("{} - {}", candidate.artist, candidate.album),
+                score: Some(score),
+                image: None,
+            });
+        }
+    }
+    for choice in &mut choices {
+        choice.image = CoverSource::from_url(&choice.art.url).load().ok();
+    }
+    choices
+}
@@ -0,0 +1,160 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use lofty::picture::{MimeType, Picture, PictureType};
+use lofty::prelude::*;
+use lofty::probe::Probe;
+use lofty::tag::Tag;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Largest cover side the PSP reliably shows; bigger art is scaled down
+pub const MAX_DIMENSION: u32 = 300;
+/// Covers are re-encoded at lower quality until they fit in this many bytes
+pub const MAX_BYTES: usize = 64 * 1024;
+
+// Qualities tried in turn until the JPEG is small enough
+const QUALITIES: [u8; 6] = [90, 80, 70, 60, 50, 40];
+
+/// Where a cover comes from
+#[derive(Debug, Clone)]
+pub enum CoverSource {
+    /// The first picture already embedded in an audio file
+    Embedded(PathBuf),
+    /// An image file on disk
+    File(PathBuf),
+    /// An image URL, e.g. an album art provider result
+    Url(String),
+}
+
+// URL forms of local covers, so they can stand in for provider results
+const EMBEDDED_SCHEME: &str = "embedded:";
+const FILE_SCHEME: &str = "file://";
+
+impl CoverSource {
+    /// Read a cover URL: "embedded:" and "file://" URLs (see `url`) are local, anything else is fetched
+    pub fn from_url(url: &str) -> Self {
+        if let Some(path) = url.strip_prefix(EMBEDDED_SCHEME) {
+            CoverSource::Embedded(PathBuf::from(path))
+        } else if let Some(path) = url.strip_prefix(FILE_SCHEME) {
+            CoverSource::File(PathBuf::from(path))
+        } else {
+            CoverSource::Url(url.to_string())
+        }
+    }
+
+    /// URL form of the source, understood by `from_url`
+    pub fn url(&self) -> String {
+        match self {
+            CoverSource::Embedded(path) => format!("{}{}", EMBEDDED_SCHEME, path.display()),
+            CoverSource::File(path) => format!("{}{}", FILE_SCHEME, path.display()),
+            CoverSource::Url(url) => url.clone(),
+        }
+    }
+
+    /// Loading needs no network access
+    pub fn is_local(&self) -> bool {
+        !matches!(self, CoverSource::Url(_))
+    }
+
+    /// Raw image bytes, in whatever format the source holds
+    pub fn load(&self) -> io::Result<Vec<u8>> {
+        match self {
+            CoverSource::Embedded(path) => crate::embedded_art::extract_embedded_art(&path.to_string_lossy())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no embedded art in {}", path.display()))),
+            CoverSource::File(path) => fs::read(path),
+            CoverSource::Url(url) => crate::http::download(url).map_err(io::Error::other),
+        }
+    }
+}
+
+/// Convert any image the image crate reads into a baseline (non-progressive) JPEG the PSP shows:
+/// at most MAX_DIMENSION pixels a side, RGB, and no bigger than MAX_BYTES where quality allows
+pub fn to_psp_jpeg(image_bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let image = image::load_from_memory(image_bytes)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let image = if image.width() > MAX_DIMENSION || image.height() > MAX_DIMENSION {
+        image.resize(MAX_DIMENSION, MAX_DIMENSION, FilterType::Lanczos3)
+    } else {
+        image
+    };
+    let rgb = image.to_rgb8();
+    let mut jpeg = Vec::new();
+    for quality in QUALITIES {
+        jpeg.clear();
+        JpegEncoder::new_with_quality(&mut jpeg, quality)
+            .encode_image(&rgb)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        if jpeg.len() <= MAX_BYTES {
+            break;
+        }
+    }
+    Ok(jpeg)
+}
+
+/// Copy `path` into `backup_dir` before it is changed, numbering the copy when the name is taken
+pub fn backup_file(path: &Path, backup_dir: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(backup_dir)?;
+    let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+    let mut dest = backup_dir.join(name);
+    let mut n = 2;
+    while dest.exists() {
+        let stem = Path::new(name).file_stem().unwrap_or_default().to_string_lossy();
+        dest = match Path::new(name).extension() {
+            Some(ext) => backup_dir.join(format!("{} ({}).{}", stem, n, ext.to_string_lossy())),
+            None => backup_dir.join(format!("{} ({})", stem, n)),
+        };
+        n += 1;
+    }
+    fs::copy(path, &dest)?;
+    Ok(dest)
+}
+
+/// Write `jpeg` as the front cover of the track at `path`, replacing any front cover and
+/// putting it first, since the PSP shows the first picture. MP3 tags are saved as ID3v2.3.
+pub fn embed_cover(path: &Path, jpeg: &[u8]) -> io::Result<()> {
+    let to_io = |e: lofty::error::LoftyError| io::Error::other(e.to_string());
+    let tagged = Probe::open(path).map_err(to_io)?.guess_file_type()?.read().map_err(to_io)?;
+    let mut tag = tagged.primary_tag().cloned().unwrap_or_else(|| Tag::new(tagged.primary_tag_type()));
+    let others: Vec<Picture> = tag.pictures().iter().filter(|p| p.pic_type() != PictureType::CoverFront).cloned().collect();
+    while !tag.pictures().is_empty() {
+        tag.remove_picture(0);
+    }
+    tag.push_picture(Picture::new_unchecked(PictureType::CoverFront, Some(MimeType::Jpeg), None, jpeg.to_vec()));
+    for picture in others {
+        tag.push_picture(picture);
+    }
+    crate::tag_writer::save_for_psp(tag, path)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EmbedReport {
+    pub embedded: Vec<PathBuf>,
+    /// Backup copies, made before each file was changed
+    pub backups: Vec<PathBuf>,
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+/// Convert `cover` once and embed it into every track in `paths`, backing each file up
+/// into `backup_dir` first when given. A track whose backup fails is left untouched.
+pub fn embed_batch(paths: &[PathBuf], cover: &CoverSource, backup_dir: Option<&Path>) -> io::Result<EmbedReport> {
+    let jpeg = to_psp_jpeg(&cover.load()?)?;
+    let mut report = EmbedReport::default();
+    for path in paths {
+        if let Some(dir) = backup_dir {
+            match backup_file(path, dir) {
+                Ok(backup) => report.backups.push(backup),
+                Err(e) => {
+                    report.failed.push((path.clone(), format!("backup failed: {}", e)));
+                    continue;
+                }
+            }
+        }
+        match embed_cover(path, &jpeg) {
+            Ok(()) => report.embedded.push(path.clone()),
+            Err(e) => report.failed.push((path.clone(), e.to_string())),
+        }
+    }
+    eprintln!("[Art] Embedded a {} byte cover into {} of {} tracks", jpeg.len(), report.embedded.len(), paths.len());
+    Ok(report)
+}
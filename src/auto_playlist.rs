@@ -0,0 +1,163 @@
+use crate::fat32;
+use crate::music::Track;
+use crate::ordering::{self, SortKey};
+use crate::playlist;
+use crate::template;
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// How tracks are grouped into playlists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    Album,
+    Artist,
+    Genre,
+    /// Top-level folder under MUSIC
+    Directory,
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "album" => Ok(GroupBy::Album),
+            "artist" => Ok(GroupBy::Artist),
+            "genre" => Ok(GroupBy::Genre),
+            "dir" | "directory" | "folder" => Ok(GroupBy::Directory),
+            other => Err(format!("unknown grouping '{}' (use album, artist, genre or dir)", other)),
+        }
+    }
+}
+
+pub const DEFAULT_TEMPLATE: &str = "{name}.m3u8";
+
+#[derive(Debug, Clone)]
+pub struct AutoPlaylistOptions {
+    pub group_by: GroupBy,
+    /// File name template; fields: name, artist, album, genre, dir, count
+    pub template: String,
+    /// Groups with fewer tracks are skipped
+    pub min_tracks: usize,
+}
+
+/// A playlist the batch would write
+#[derive(Debug, Clone)]
+pub struct PlannedPlaylist {
+    pub file_name: String,
+    pub group: String,
+    pub entries: Vec<usize>,
+}
+
+/// Result of planning a batch
+#[derive(Debug, Clone, Default)]
+pub struct AutoPlaylistPlan {
+    pub playlists: Vec<PlannedPlaylist>,
+    /// Groups under the minimum track count, with their sizes
+    pub skipped: Vec<(String, usize)>,
+}
+
+// Fields of a group that the file name template can use
+#[derive(Default)]
+struct GroupFields {
+    name: String,
+    artist: String,
+    album: String,
+    genre: String,
+    dir: String,
+}
+
+fn group_fields(track: &Track, group_by: GroupBy) -> Option<GroupFields> {
+    let fields = match group_by {
+        GroupBy::Album if !track.album.is_empty() => GroupFields {
+            name: format!("{} - {}", track.artist, track.album),
+            artist: track.artist.clone(),
+            album: track.album.clone(),
+            ..Default::default()
+        },
+        GroupBy::Artist if !track.artist.is_empty() => GroupFields {
+            name: track.artist.clone(),
+            artist: track.artist.clone(),
+            ..Default::default()
+        },
+        GroupBy::Genre if !track.genre.is_empty() => GroupFields {
+            name: track.genre.clone(),
+            genre: track.genre.clone(),
+            ..Default::default()
+        },
+        GroupBy::Directory => {
+            // "/MUSIC/<dir>/..." -> "<dir>"; files directly in MUSIC have no folder
+            let psp_path = playlist::to_psp_path(&track.path);
+            let mut parts = psp_path.trim_start_matches('/').split('/').skip(1);
+            let dir = parts.next().filter(|_| parts.next().is_some())?.to_string();
+            GroupFields { name: dir.clone(), dir, ..Default::default() }
+        }
+        _ => return None,
+    };
+    Some(fields)
+}
+
+/// Group the library and name each playlist, without writing anything
+pub fn plan(tracks: &[Track], options: &AutoPlaylistOptions) -> AutoPlaylistPlan {
+    let mut groups: BTreeMap<String, (GroupFields, Vec<usize>)> = BTreeMap::new();
+    for (idx, track) in tracks.iter().enumerate() {
+        if let Some(fields) = group_fields(track, options.group_by) {
+            groups.entry(fields.name.to_lowercase())
+                .or_insert_with(|| (fields, Vec::new()))
+                .1
+                .push(idx);
+        }
+    }
+
+    let mut plan = AutoPlaylistPlan::default();
+    let mut used_names = HashSet::new();
+    for (fields, mut entries) in groups.into_values() {
+        if entries.len() < options.min_tracks {
+            plan.skipped.push((fields.name, entries.len()));
+            continue;
+        }
+        match options.group_by {
+            GroupBy::Directory => entries.sort_by(|a, b| tracks[*a].path.cmp(&tracks[*b].path)),
+            _ => ordering::sort_entries(&mut entries, tracks, &[SortKey::Artist, SortKey::Album, SortKey::Disc, SortKey::Track]),
+        }
+        let count = entries.len().to_string();
+        let rendered = template::render(&options.template, |field| {
+            let value = match field {
+                "name" => &fields.name,
+                "artist" => &fields.artist,
+                "album" => &fields.album,
+                "genre" => &fields.genre,
+                "dir" => &fields.dir,
+                "count" => &count,
+                _ => return None,
+            };
+            Some(template::component(value))
+        });
+        // Two groups may render to the same name (e.g. "AC/DC" vs "AC-DC")
+        let rendered = fat32::sanitize_component(&rendered);
+        let mut file_name = rendered.clone();
+        let mut n = 2;
+        while !used_names.insert(file_name.to_lowercase()) {
+            file_name = match rendered.rsplit_once('.') {
+                Some((stem, ext)) => format!("{} ({}).{}", stem, n, ext),
+                None => format!("{} ({})", rendered, n),
+            };
+            n += 1;
+        }
+        plan.playlists.push(PlannedPlaylist { file_name, group: fields.name, entries });
+    }
+    plan
+}
+
+/// Write every planned playlist into `out_dir`; returns how many were written
+pub fn write_all(plan: &AutoPlaylistPlan, tracks: &[Track], out_dir: &Path) -> io::Result<usize> {
+    std::fs::create_dir_all(out_dir)?;
+    for planned in &plan.playlists {
+        let playlist_tracks: Vec<&Track> = planned.entries.iter().map(|i| &tracks[*i]).collect();
+        let path = out_dir.join(&planned.file_name);
+        playlist::write_m3u8(&path.to_string_lossy(), &playlist_tracks)?;
+    }
+    Ok(plan.playlists.len())
+}
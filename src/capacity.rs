@@ -0,0 +1,114 @@
+use crate::compat;
+use crate::music::Track;
+use crate::sync::{SyncAction, SyncReport};
+use crate::transcode::Encoder;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// A playlist or album wanted on the Memory Stick
+#[derive(Debug, Clone)]
+pub struct Selection {
+    pub label: String,
+    /// Indices into the track list
+    pub tracks: Vec<usize>,
+}
+
+/// Space one track takes once it is on the stick
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrackCost {
+    /// 0 when the track is already there
+    pub bytes: u64,
+    /// Whether the size is a projected transcode
+    pub converted: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlannedSelection {
+    pub label: String,
+    pub tracks: usize,
+    /// Space still needed; tracks already placed by an earlier selection count once
+    pub bytes: u64,
+    pub converted: usize,
+    pub fits: bool,
+    /// How much more room it would take to fit; 0 when it fits
+    pub short_by: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct CapacityPlan {
+    pub free_bytes: u64,
+    pub total_bytes: Option<u64>,
+    pub selections: Vec<PlannedSelection>,
+}
+
+impl CapacityPlan {
+    /// Space taken by the selections that fit
+    pub fn needed(&self) -> u64 {
+        self.selections.iter().filter(|s| s.fits).map(|s| s.bytes).sum()
+    }
+
+    /// Free space left once everything that fits is on the stick
+    pub fn remaining(&self) -> u64 {
+        self.free_bytes.saturating_sub(self.needed())
+    }
+
+    pub fn overflowing(&self) -> impl Iterator<Item = &PlannedSelection> {
+        self.selections.iter().filter(|s| !s.fits)
+    }
+}
+
+/// Fit selections into `free_bytes` in the order given. A selection that doesn't fit is
+/// left out whole and later, smaller ones still get their chance.
+pub fn plan(selections: &[Selection], free_bytes: u64, total_bytes: Option<u64>, cost: impl Fn(usize) -> TrackCost) -> CapacityPlan {
+    let mut placed: HashSet<usize> = HashSet::new();
+    let mut remaining = free_bytes;
+    let mut planned = Vec::with_capacity(selections.len());
+    for selection in selections {
+        let mut seen = HashSet::new();
+        let new_tracks: Vec<usize> = selection.tracks.iter()
+            .copied()
+            .filter(|i| !placed.contains(i) && seen.insert(*i))
+            .collect();
+        let costs: Vec<TrackCost> = new_tracks.iter().map(|i| cost(*i)).collect();
+        let bytes: u64 = costs.iter().map(|c| c.bytes).sum();
+        let fits = bytes <= remaining;
+        if fits {
+            remaining -= bytes;
+            placed.extend(new_tracks);
+        }
+        planned.push(PlannedSelection {
+            label: selection.label.clone(),
+            tracks: selection.tracks.len(),
+            bytes,
+            converted: costs.iter().filter(|c| c.converted).count(),
+            fits,
+            short_by: if fits { 0 } else { bytes - remaining },
+        });
+    }
+    CapacityPlan { free_bytes, total_bytes, selections: planned }
+}
+
+/// Estimate from the index alone: the encoder's projected size for tracks the PSP can't play
+/// when converting (the file size when the duration is unknown), nothing for other tracks
+/// already under `device_root`, else the file size
+pub fn estimate_cost(track: &Track, device_root: Option<&Path>, encoder: Option<&dyn Encoder>) -> TrackCost {
+    if let Some(encoder) = encoder.filter(|_| !compat::is_compatible(track)) {
+        // A converted copy is written even when the original is already on the stick
+        let bytes = track.duration_secs.map(|d| encoder.estimate_size(d)).unwrap_or(track.size_bytes);
+        return TrackCost { bytes, converted: true };
+    }
+    if device_root.is_some_and(|root| Path::new(&track.path).starts_with(root)) {
+        return TrackCost::default();
+    }
+    TrackCost { bytes: track.size_bytes, converted: false }
+}
+
+/// Costs from a dry-run sync plan, which already knows what is on the stick and what gets converted
+pub fn costs_from_sync(report: &SyncReport) -> HashMap<usize, TrackCost> {
+    report.items.iter()
+        .map(|item| {
+            let bytes = if matches!(item.action, SyncAction::Skip) { 0 } else { item.size };
+            (item.track, TrackCost { bytes, converted: item.transcode && bytes > 0 })
+        })
+        .collect()
+}
@@ -0,0 +1,153 @@
+use crate::music::Track;
+use lofty::config::ParseOptions;
+use lofty::file::{AudioFile, FileType, TaggedFile, TaggedFileExt};
+use lofty::iff::wav::{WavFile, WavFormat};
+use lofty::mp4::{Mp4Codec, Mp4File};
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+
+/// Why the PSP won't play a track
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// The file type isn't one the PSP opens at all
+    Format(String),
+    /// A supported file type holding audio the PSP can't decode, e.g. ALAC in .m4a
+    Codec { codec: String, ext: String },
+    SampleRate(u32),
+    Bitrate(u32),
+    Channels(u8),
+    Drm,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::Format(ext) if ext.is_empty() => write!(f, "file has no extension"),
+            Issue::Format(ext) => write!(f, ".{} files aren't supported", ext),
+            Issue::Codec { codec, ext } => write!(f, "{} audio in a .{} file", codec.to_uppercase(), ext),
+            Issue::SampleRate(rate) => write!(f, "{} Hz sample rate", rate),
+            Issue::Bitrate(kbps) => write!(f, "{} kbps bitrate", kbps),
+            Issue::Channels(n) => write!(f, "{} channels (stereo at most)", n),
+            Issue::Drm => write!(f, "DRM protected"),
+        }
+    }
+}
+
+// What the PSP accepts inside each file type it opens
+struct Limits {
+    codecs: &'static [&'static str],
+    sample_rates: &'static [u32],
+    max_kbps: u32,
+}
+
+fn limits_for(ext: &str) -> Option<Limits> {
+    let limits = match ext {
+        "mp3" => Limits {
+            codecs: &["mp3"],
+            sample_rates: &[16000, 22050, 24000, 32000, 44100, 48000],
+            max_kbps: 320,
+        },
+        "m4a" | "mp4" | "aac" | "3gp" => Limits {
+            codecs: &["aac"],
+            sample_rates: &[24000, 32000, 44100, 48000],
+            max_kbps: 320,
+        },
+        "wav" => Limits { codecs: &["pcm"], sample_rates: &[44100], max_kbps: 1411 },
+        // Not readable here; trust the file type
+        "wma" | "oma" | "omg" | "aa3" => Limits { codecs: &[], sample_rates: &[], max_kbps: 0 },
+        _ => return None,
+    };
+    Some(limits)
+}
+
+/// Whether the PSP opens files with this (lowercase) extension at all
+pub fn is_supported_extension(ext: &str) -> bool {
+    limits_for(ext).is_some()
+}
+
+/// Everything that stops the PSP from playing a track; empty when it plays.
+/// Checks only what the index knows, so tracks scanned by older versions are judged by extension.
+pub fn check(track: &Track) -> Vec<Issue> {
+    let ext = Path::new(&track.path).extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let Some(limits) = limits_for(&ext) else {
+        return vec![Issue::Format(ext)];
+    };
+    let mut issues = Vec::new();
+    if track.drm {
+        issues.push(Issue::Drm);
+    }
+    if limits.codecs.is_empty() {
+        return issues;
+    }
+    if !track.codec.is_empty() && !limits.codecs.contains(&track.codec.as_str()) {
+        issues.push(Issue::Codec { codec: track.codec.clone(), ext });
+        return issues;
+    }
+    if let Some(rate) = track.sample_rate.filter(|r| !limits.sample_rates.contains(r)) {
+        issues.push(Issue::SampleRate(rate));
+    }
+    if let Some(kbps) = track.bitrate_kbps.filter(|k| *k > limits.max_kbps) {
+        issues.push(Issue::Bitrate(kbps));
+    }
+    if let Some(channels) = track.channels.filter(|c| *c > 2) {
+        issues.push(Issue::Channels(channels));
+    }
+    issues
+}
+
+pub fn is_compatible(track: &Track) -> bool {
+    check(track).is_empty()
+}
+
+/// Issues joined for display, e.g. "FLAC audio in a .m4a file; 96000 Hz sample rate"
+pub fn describe(issues: &[Issue]) -> String {
+    issues.iter().map(|i| i.to_string()).collect::<Vec<_>>().join("; ")
+}
+
+/// Codec name ("mp3", "aac", "flac", "pcm", ...) and whether the audio is DRM protected
+pub(crate) fn detect_codec(path: &str, file: &TaggedFile) -> (String, bool) {
+    let codec = match file.file_type() {
+        FileType::Mpeg => "mp3",
+        FileType::Aac => "aac",
+        FileType::Flac => "flac",
+        FileType::Vorbis => "vorbis",
+        FileType::Opus => "opus",
+        FileType::Speex => "speex",
+        FileType::Ape => "ape",
+        FileType::WavPack => "wavpack",
+        FileType::Mpc => "musepack",
+        FileType::Aiff => "aiff",
+        // The codec inside MP4 and WAV needs the format-specific properties
+        FileType::Mp4 => return mp4_codec(path),
+        FileType::Wav => return (wav_codec(path), false),
+        _ => "",
+    };
+    (codec.to_string(), false)
+}
+
+fn mp4_codec(path: &str) -> (String, bool) {
+    let Some(mp4) = File::open(path).ok().and_then(|mut f| Mp4File::read_from(&mut f, ParseOptions::new().read_tags(false)).ok()) else {
+        return (String::new(), false);
+    };
+    let properties = mp4.properties();
+    let codec = match properties.codec() {
+        Mp4Codec::AAC => "aac",
+        Mp4Codec::ALAC => "alac",
+        Mp4Codec::MP3 => "mp3",
+        Mp4Codec::FLAC => "flac",
+        _ => "",
+    };
+    (codec.to_string(), properties.is_drm_protected())
+}
+
+fn wav_codec(path: &str) -> String {
+    let Some(wav) = File::open(path).ok().and_then(|mut f| WavFile::read_from(&mut f, ParseOptions::new().read_tags(false)).ok()) else {
+        return String::new();
+    };
+    match wav.properties().format() {
+        WavFormat::PCM => "pcm".to_string(),
+        WavFormat::IEEE_FLOAT => "float".to_string(),
+        WavFormat::Other(tag) => format!("wav-{:04x}", tag),
+    }
+}
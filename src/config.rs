@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Settings file, read from the working directory like music_index.db
+pub const CONFIG_PATH: &str = "psp_playlist_maker.json";
+
+/// An album art provider's place in the lookup order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    /// "deezer", "itunes", "musicbrainz" or "lastfm"
+    pub name: String,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// Base URLs of the web services we look things up in; point them at a local stand-in to test offline
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Endpoints {
+    pub deezer: String,
+    pub itunes: String,
+    /// Release search for art and recording search for metadata
+    pub musicbrainz: String,
+    /// Cover images for MusicBrainz releases
+    pub coverartarchive: String,
+    pub lastfm: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Endpoints {
+            deezer: "https://api.deezer.com".to_string(),
+            itunes: "https://itunes.apple.com".to_string(),
+            musicbrainz: "https://musicbrainz.org".to_string(),
+            coverartarchive: "https://coverartarchive.org".to_string(),
+            lastfm: "https://ws.audioscrobbler.com".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Providers in the order they are asked; built-in providers missing here are asked last
+    pub art_providers: Vec<ProviderConfig>,
+    pub endpoints: Endpoints,
+    /// Days before an album whose art wasn't found anywhere is looked up again
+    pub missing_art_ttl_days: u64,
+    /// Cover image names looked for in a track's folder before asking the art providers
+    pub sidecar_art_names: Vec<String>,
+    /// Lowest artist/album similarity (0.0 to 1.0) a provider's cover needs to be used
+    pub art_match_threshold: f32,
+    /// Start without network access: art and metadata come only from local files and the cache
+    pub offline: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let art_providers = crate::album_art::BUILTIN_PROVIDERS.iter()
+            .map(|name| ProviderConfig { name: name.to_string(), enabled: true })
+            .collect();
+        let sidecar_art_names = crate::local_art::DEFAULT_SIDECAR_NAMES.iter().map(|n| n.to_string()).collect();
+        Config {
+            art_providers,
+            endpoints: Endpoints::default(),
+            missing_art_ttl_days: 7,
+            sidecar_art_names,
+            art_match_threshold: 0.6,
+            offline: false,
+        }
+    }
+}
+
+impl Config {
+    /// Settings from CONFIG_PATH; defaults when the file is missing or broken
+    pub fn load() -> Self {
+        match Self::load_from(Path::new(CONFIG_PATH)) {
+            Ok(config) => config,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Config::default(),
+            Err(e) => {
+                eprintln!("[Config] Ignoring {}: {}", CONFIG_PATH, e);
+                Config::default()
+            }
+        }
+    }
+
+    pub fn load_from(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        serde_json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
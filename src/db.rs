@@ -1,25 +1,104 @@
-use rusqlite::{Connection, Result};
+use rusqlite::{params, Connection, Result};
+use crate::album_art::ArtResult;
 use crate::music::Track;
+use crate::tag_writer::{TagEdit, TagValues};
 
 pub fn init_db(path: &str) -> Result<Connection> {
     let conn = Connection::open(path)?;
+    // Background lookups and `art fetch` workers each write through a connection of their own
+    conn.busy_timeout(std::time::Duration::from_secs(10))?;
     conn.execute(
         "CREATE TABLE IF NOT EXISTS tracks (
             id INTEGER PRIMARY KEY,
             path TEXT NOT NULL,
             artist TEXT,
             album TEXT,
-            title TEXT
+            title TEXT,
+            track_number INTEGER,
+            disc_number INTEGER,
+            duration_secs INTEGER,
+            genre TEXT,
+            size_bytes INTEGER,
+            codec TEXT,
+            sample_rate INTEGER,
+            bitrate_kbps INTEGER,
+            channels INTEGER,
+            drm INTEGER
+        )",
+        [],
+    )?;
+    // Indexes created by older versions lack the newer columns
+    add_missing_column(&conn, "tracks", "track_number", "INTEGER")?;
+    add_missing_column(&conn, "tracks", "disc_number", "INTEGER")?;
+    add_missing_column(&conn, "tracks", "duration_secs", "INTEGER")?;
+    add_missing_column(&conn, "tracks", "genre", "TEXT")?;
+    add_missing_column(&conn, "tracks", "size_bytes", "INTEGER")?;
+    add_missing_column(&conn, "tracks", "codec", "TEXT")?;
+    add_missing_column(&conn, "tracks", "sample_rate", "INTEGER")?;
+    add_missing_column(&conn, "tracks", "bitrate_kbps", "INTEGER")?;
+    add_missing_column(&conn, "tracks", "channels", "INTEGER")?;
+    add_missing_column(&conn, "tracks", "drm", "INTEGER")?;
+    // Tag writes, with the old values kept for undo; tags are stored as JSON
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tag_edits (
+            id INTEGER PRIMARY KEY,
+            path TEXT NOT NULL,
+            edited_at INTEGER NOT NULL,
+            before TEXT NOT NULL,
+            after TEXT NOT NULL,
+            undone INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )?;
+    // Album art lookups by normalized artist/album; a NULL url records that nothing was found
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS art_lookups (
+            artist_key TEXT NOT NULL,
+            album_key TEXT NOT NULL,
+            url TEXT,
+            provider TEXT,
+            looked_up_at INTEGER NOT NULL,
+            PRIMARY KEY (artist_key, album_key)
+        )",
+        [],
+    )?;
+    // Covers chosen by hand, used instead of any lookup
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS art_pins (
+            artist_key TEXT NOT NULL,
+            album_key TEXT NOT NULL,
+            url TEXT NOT NULL,
+            provider TEXT NOT NULL,
+            pinned_at INTEGER NOT NULL,
+            PRIMARY KEY (artist_key, album_key)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS art_images (
+            url TEXT PRIMARY KEY,
+            bytes BLOB NOT NULL,
+            fetched_at INTEGER NOT NULL
         )",
         [],
     )?;
     Ok(conn)
 }
 
+fn add_missing_column(conn: &Connection, table: &str, column: &str, decl: &str) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns = stmt.query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>>>()?;
+    if !columns.iter().any(|c| c == column) {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl), [])?;
+    }
+    Ok(())
+}
+
 pub fn insert_track(conn: &Connection, track: &Track) -> Result<()> {
     conn.execute(
-        "INSERT INTO tracks (path, artist, album, title) VALUES (?1, ?2, ?3, ?4)",
-        [&track.path, &track.artist, &track.album, &track.title],
+        "INSERT INTO tracks (path, artist, album, title, track_number, disc_number, duration_secs, genre, size_bytes, codec, sample_rate, bitrate_kbps, channels, drm) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        params![&track.path, &track.artist, &track.album, &track.title, track.track_number, track.disc_number, track.duration_secs, &track.genre, track.size_bytes as i64, &track.codec, track.sample_rate, track.bitrate_kbps, track.channels, track.drm],
     )?;
     Ok(())
 }
@@ -30,12 +109,12 @@ pub fn save_library(conn: &mut Connection, library: &MusicLibrary) -> Result<()>
     let tx = conn.transaction()?;
     {
         let mut stmt = tx.prepare(
-            "INSERT INTO tracks (path, artist, album, title) VALUES (?1, ?2, ?3, ?4)"
+            "INSERT INTO tracks (path, artist, album, title, track_number, disc_number, duration_secs, genre, size_bytes, codec, sample_rate, bitrate_kbps, channels, drm) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)"
         )?;
         for track in &library.tracks {
             let mut track = track.clone();
             track.path = track.path.replace(" ", "%20");
-            stmt.execute([&track.path, &track.artist, &track.album, &track.title])?;
+            stmt.execute(params![&track.path, &track.artist, &track.album, &track.title, track.track_number, track.disc_number, track.duration_secs, &track.genre, track.size_bytes as i64, &track.codec, track.sample_rate, track.bitrate_kbps, track.channels, track.drm])?;
         }
     }
     tx.commit()?;
@@ -43,14 +122,175 @@ pub fn save_library(conn: &mut Connection, library: &MusicLibrary) -> Result<()>
 }
 
 pub fn load_tracks(conn: &Connection) -> Result<Vec<Track>> {
-    let mut stmt = conn.prepare("SELECT path, artist, album, title FROM tracks")?;
+    let mut stmt = conn.prepare("SELECT path, artist, album, title, track_number, disc_number, duration_secs, genre, size_bytes, codec, sample_rate, bitrate_kbps, channels, drm FROM tracks")?;
     let tracks = stmt.query_map([], |row| {
         Ok(Track {
             path: row.get::<_, String>(0)?.replace("%20", " "),
             artist: row.get(1)?,
             album: row.get(2)?,
             title: row.get(3)?,
+            track_number: row.get(4)?,
+            disc_number: row.get(5)?,
+            duration_secs: row.get(6)?,
+            genre: row.get::<_, Option<String>>(7)?.unwrap_or_default(),
+            size_bytes: row.get::<_, Option<i64>>(8)?.unwrap_or(0) as u64,
+            codec: row.get::<_, Option<String>>(9)?.unwrap_or_default(),
+            sample_rate: row.get(10)?,
+            bitrate_kbps: row.get(11)?,
+            channels: row.get(12)?,
+            drm: row.get::<_, Option<bool>>(13)?.unwrap_or(false),
         })
     })?;
     tracks.collect()
 }
+
+/// Update the indexed title/artist/album of a track, matched by path
+pub fn update_track_tags(conn: &Connection, track: &Track) -> Result<()> {
+    conn.execute(
+        "UPDATE tracks SET artist = ?1, album = ?2, title = ?3 WHERE path = ?4",
+        params![&track.artist, &track.album, &track.title, track.path.replace(" ", "%20")],
+    )?;
+    Ok(())
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn to_json(values: &TagValues) -> Result<String> {
+    serde_json::to_string(values).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+// Index columns follow the written tags; a cleared title/artist/album keeps the indexed value,
+// which a scan would have taken from the file and folder names
+fn update_indexed_tags(conn: &Connection, path: &str, values: &TagValues) -> Result<()> {
+    conn.execute(
+        "UPDATE tracks SET title = COALESCE(NULLIF(?1, ''), title), artist = COALESCE(NULLIF(?2, ''), artist),
+            album = COALESCE(NULLIF(?3, ''), album), genre = ?4, track_number = ?5, disc_number = ?6 WHERE path = ?7",
+        params![&values.title, &values.artist, &values.album, &values.genre, values.track_number, values.disc_number, path.replace(" ", "%20")],
+    )?;
+    Ok(())
+}
+
+/// Log a tag write and update the track's index row in one transaction; returns the edit's id
+pub fn record_tag_edit(conn: &mut Connection, path: &str, before: &TagValues, after: &TagValues) -> Result<i64> {
+    let edited_at = now_secs();
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO tag_edits (path, edited_at, before, after) VALUES (?1, ?2, ?3, ?4)",
+        params![path, edited_at as i64, to_json(before)?, to_json(after)?],
+    )?;
+    let id = tx.last_insert_rowid();
+    update_indexed_tags(&tx, path, after)?;
+    tx.commit()?;
+    Ok(id)
+}
+
+/// Mark an edit undone and put its old values back into the index, in one transaction
+pub fn mark_tag_edit_undone(conn: &mut Connection, edit: &TagEdit) -> Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute("UPDATE tag_edits SET undone = 1 WHERE id = ?1", params![edit.id])?;
+    update_indexed_tags(&tx, &edit.path, &edit.before)?;
+    tx.commit()
+}
+
+fn tag_edit_from_row(row: &rusqlite::Row) -> Result<TagEdit> {
+    let json = |i: usize| -> Result<TagValues> {
+        let text: String = row.get(i)?;
+        serde_json::from_str(&text).map_err(|e| rusqlite::Error::FromSqlConversionFailure(i, rusqlite::types::Type::Text, Box::new(e)))
+    };
+    Ok(TagEdit {
+        id: row.get(0)?,
+        path: row.get(1)?,
+        edited_at: row.get::<_, i64>(2)? as u64,
+        before: json(3)?,
+        after: json(4)?,
+        undone: row.get(5)?,
+    })
+}
+
+pub fn load_tag_edit(conn: &Connection, id: i64) -> Result<Option<TagEdit>> {
+    let mut stmt = conn.prepare("SELECT id, path, edited_at, before, after, undone FROM tag_edits WHERE id = ?1")?;
+    let mut rows = stmt.query_map(params![id], tag_edit_from_row)?;
+    rows.next().transpose()
+}
+
+/// Most recent edits first, optionally only those of one file; all of them without a `limit`
+pub fn load_tag_edits(conn: &Connection, path: Option<&str>, limit: Option<usize>) -> Result<Vec<TagEdit>> {
+    let mut sql = "SELECT id, path, edited_at, before, after, undone FROM tag_edits
+         WHERE ?1 IS NULL OR path = ?1 ORDER BY id DESC".to_string();
+    if let Some(limit) = limit {
+        sql.push_str(&format!(" LIMIT {}", limit.min(i64::MAX as usize)));
+    }
+    let mut stmt = conn.prepare(&sql)?;
+    let edits = stmt.query_map(params![path], tag_edit_from_row)?;
+    edits.collect()
+}
+
+/// A cached art lookup and when it was made (seconds since the Unix epoch); `None` if never looked up
+pub fn load_art_lookup(conn: &Connection, artist_key: &str, album_key: &str) -> Result<Option<(Option<ArtResult>, u64)>> {
+    let mut stmt = conn.prepare("SELECT url, provider, looked_up_at FROM art_lookups WHERE artist_key = ?1 AND album_key = ?2")?;
+    let mut rows = stmt.query_map(params![artist_key, album_key], |row| {
+        let url: Option<String> = row.get(0)?;
+        let provider: Option<String> = row.get(1)?;
+        let found = url.map(|url| ArtResult { url, provider: provider.unwrap_or_default(), pinned: false });
+        Ok((found, row.get::<_, i64>(2)? as u64))
+    })?;
+    rows.next().transpose()
+}
+
+pub fn save_art_lookup(conn: &Connection, artist_key: &str, album_key: &str, found: Option<&ArtResult>) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO art_lookups (artist_key, album_key, url, provider, looked_up_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![artist_key, album_key, found.map(|f| &f.url), found.map(|f| &f.provider), now_secs() as i64],
+    )?;
+    Ok(())
+}
+
+/// Drop an album's lookup and its downloaded image
+pub fn forget_art_lookup(conn: &Connection, artist_key: &str, album_key: &str) -> Result<()> {
+    conn.execute(
+        "DELETE FROM art_images WHERE url IN (SELECT url FROM art_lookups WHERE artist_key = ?1 AND album_key = ?2)",
+        params![artist_key, album_key],
+    )?;
+    conn.execute("DELETE FROM art_lookups WHERE artist_key = ?1 AND album_key = ?2", params![artist_key, album_key])?;
+    Ok(())
+}
+
+pub fn load_art_image(conn: &Connection, url: &str) -> Result<Option<Vec<u8>>> {
+    let mut stmt = conn.prepare("SELECT bytes FROM art_images WHERE url = ?1")?;
+    let mut rows = stmt.query_map(params![url], |row| row.get(0))?;
+    rows.next().transpose()
+}
+
+pub fn save_art_image(conn: &Connection, url: &str, bytes: &[u8]) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO art_images (url, bytes, fetched_at) VALUES (?1, ?2, ?3)",
+        params![url, bytes, now_secs() as i64],
+    )?;
+    Ok(())
+}
+
+pub fn load_art_pin(conn: &Connection, artist_key: &str, album_key: &str) -> Result<Option<ArtResult>> {
+    let mut stmt = conn.prepare("SELECT url, provider FROM art_pins WHERE artist_key = ?1 AND album_key = ?2")?;
+    let mut rows = stmt.query_map(params![artist_key, album_key], |row| {
+        Ok(ArtResult { url: row.get(0)?, provider: row.get(1)?, pinned: true })
+    })?;
+    rows.next().transpose()
+}
+
+pub fn save_art_pin(conn: &Connection, artist_key: &str, album_key: &str, art: &ArtResult) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO art_pins (artist_key, album_key, url, provider, pinned_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![artist_key, album_key, &art.url, &art.provider, now_secs() as i64],
+    )?;
+    Ok(())
+}
+
+pub fn delete_art_pin(conn: &Connection, artist_key: &str, album_key: &str) -> Result<()> {
+    conn.execute("DELETE FROM art_pins WHERE artist_key = ?1 AND album_key = ?2", params![artist_key, album_key])?;
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A mounted Memory Stick that looks like a PSP: a `PSP/` and a `MUSIC/` directory at its root
+#[derive(Debug, Clone)]
+pub struct PspDevice {
+    pub label: String,
+    pub root: PathBuf,
+    pub music_dir: PathBuf,
+    pub free_bytes: Option<u64>,
+    pub total_bytes: Option<u64>,
+}
+
+/// Finds PSP devices by reading the system mount table.
+///
+/// The mount table and the root directory mount points are resolved against can
+/// be replaced, so a fake table and a fake filesystem tree can stand in for the system.
+pub struct DeviceScanner {
+    mount_table: PathBuf,
+    root: PathBuf,
+    // Probe the usual mount locations when there is no mount table; only for the real system
+    probe_without_table: bool,
+}
+
+impl Default for DeviceScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeviceScanner {
+    /// Scanner for the running system
+    pub fn new() -> Self {
+        DeviceScanner { mount_table: PathBuf::from("/proc/mounts"), root: PathBuf::from("/"), probe_without_table: true }
+    }
+
+    /// Scanner reading `mount_table` and resolving mount points (and `/dev/disk/by-label`) under `root`
+    pub fn with_mount_table(mount_table: impl Into<PathBuf>, root: impl Into<PathBuf>) -> Self {
+        DeviceScanner { mount_table: mount_table.into(), root: root.into(), probe_without_table: false }
+    }
+
+    pub fn scan(&self) -> Vec<PspDevice> {
+        let labels = self.read_labels();
+        let mounts = match fs::read_to_string(&self.mount_table) {
+            Ok(table) => parse_mount_table(&table),
+            // No mount table (macOS, Windows): probe the usual mount locations instead
+            Err(_) if self.probe_without_table => fallback_mounts(),
+            Err(e) => {
+                eprintln!("[Device] Can't read {}: {}", self.mount_table.display(), e);
+                Vec::new()
+            }
+        };
+        let mut devices: Vec<PspDevice> = Vec::new();
+        for (source, mount_point) in mounts {
+            let root = self.resolve(&mount_point);
+            let (Some(_), Some(music_dir)) = (find_child_dir(&root, "PSP"), find_child_dir(&root, "MUSIC")) else {
+                continue;
+            };
+            if devices.iter().any(|d| d.root == root) {
+                continue;
+            }
+            let label = fs::canonicalize(self.resolve(&source)).ok()
+                .and_then(|dev| labels.get(&dev).cloned())
+                .or_else(|| mount_point.file_name().map(|n| n.to_string_lossy().to_string()))
+                .unwrap_or_else(|| mount_point.display().to_string());
+            devices.push(PspDevice {
+                label,
+                free_bytes: free_space(&root),
+                total_bytes: fs4::total_space(&root).ok(),
+                music_dir,
+                root,
+            });
+        }
+        devices
+    }
+
+    fn resolve(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix("/") {
+            Ok(relative) => self.root.join(relative),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+
+    // Volume labels by device, from the /dev/disk/by-label symlinks
+    fn read_labels(&self) -> HashMap<PathBuf, String> {
+        let mut labels = HashMap::new();
+        let Ok(entries) = fs::read_dir(self.resolve(Path::new("/dev/disk/by-label"))) else {
+            return labels;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            if let Ok(device) = fs::canonicalize(entry.path()) {
+                labels.insert(device, unescape_label(&entry.file_name().to_string_lossy()));
+            }
+        }
+        labels
+    }
+}
+
+/// Detected PSP devices on this system
+pub fn detect_devices() -> Vec<PspDevice> {
+    DeviceScanner::new().scan()
+}
+
+/// Space available to us on the filesystem holding `path`
+pub fn free_space(path: &Path) -> Option<u64> {
+    fs4::available_space(path).ok()
+}
+
+/// (source, mount point) pairs from a /proc/mounts style table
+pub fn parse_mount_table(table: &str) -> Vec<(PathBuf, PathBuf)> {
+    table.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let source = fields.next()?;
+            let mount_point = fields.next()?;
+            Some((PathBuf::from(unescape_mount(source)), PathBuf::from(unescape_mount(mount_point))))
+        })
+        .collect()
+}
+
+// The mount table escapes space, tab, newline and backslash as octal, e.g. "\040"
+fn unescape_mount(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).filter(|d| d.iter().all(|b| (b'0'..=b'7').contains(b)));
+        if let (b'\\', Some(digits)) = (bytes[i], octal) {
+            out.push(digits.iter().fold(0u8, |code, d| code.wrapping_mul(8).wrapping_add(d - b'0')));
+            i += 4;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+// udev escapes label characters as hex bytes of their UTF-8, e.g. "MY\x20PSP" or "M\xc3\xbcsik"
+fn unescape_label(name: &str) -> String {
+    let bytes = name.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 2..i + 4)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if let (Some(b"\\x"), Some(code)) = (bytes.get(i..i + 2), hex) {
+            out.push(code);
+            i += 4;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// Subdirectory of `parent` named `name`, ignoring case: FAT treats "MUSIC", "Music" and "music" alike
+pub fn find_child_dir(parent: &Path, name: &str) -> Option<PathBuf> {
+    fs::read_dir(parent).ok()?
+        .filter_map(|e| e.ok())
+        .find(|e| e.file_name().to_string_lossy().eq_ignore_ascii_case(name) && e.path().is_dir())
+        .map(|e| e.path())
+}
+
+fn fallback_mounts() -> Vec<(PathBuf, PathBuf)> {
+    let mut candidates = Vec::new();
+    if cfg!(windows) {
+        for letter in b'A'..=b'Z' {
+            let drive = PathBuf::from(format!("{}:\\", letter as char));
+            candidates.push((drive.clone(), drive));
+        }
+    }
+    // /Volumes/<name>, /media/<name>, /media/<user>/<name>, /run/media/<user>/<name>
+    for base in ["/Volumes", "/media", "/run/media"] {
+        for entry in fs::read_dir(base).into_iter().flatten().filter_map(|e| e.ok()) {
+            let path = entry.path();
+            candidates.push((path.clone(), path.clone()));
+            for child in fs::read_dir(&path).into_iter().flatten().filter_map(|e| e.ok()) {
+                candidates.push((child.path(), child.path()));
+            }
+        }
+    }
+    candidates
+}
@@ -5,8 +5,28 @@ use std::sync::Mutex;
 use std::fs;
 use std::time::SystemTime;
 
+/// Tag fields read from an audio file
+#[derive(Debug, Clone, Default)]
+pub struct EmbeddedMetadata {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub genre: String,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub duration_secs: Option<u32>,
+    pub codec: String,
+    pub sample_rate: Option<u32>,
+    pub bitrate_kbps: Option<u32>,
+    pub channels: Option<u8>,
+    pub drm: bool,
+}
+
+// (path, mtime) -> metadata read from that version of the file
+type MetadataCache = HashMap<(String, u64), Option<EmbeddedMetadata>>;
+
 lazy_static::lazy_static! {
-    static ref METADATA_CACHE: Mutex<HashMap<(String, u64), Option<(String, String, String)>>> = Mutex::new(HashMap::new());
+    static ref METADATA_CACHE: Mutex<MetadataCache> = Mutex::new(HashMap::new());
 }
 
 pub fn extract_embedded_art(path: &str) -> Option<Vec<u8>> {
@@ -20,7 +40,7 @@ pub fn extract_embedded_art(path: &str) -> Option<Vec<u8>> {
     }
 }
 
-pub fn extract_metadata(path: &str) -> Option<(String, String, String)> {
+pub fn extract_metadata(path: &str) -> Option<EmbeddedMetadata> {
     let mtime = fs::metadata(path).and_then(|m| m.modified()).ok()
         .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
         .map(|d| d.as_secs()).unwrap_or(0);
@@ -31,12 +51,30 @@ pub fn extract_metadata(path: &str) -> Option<(String, String, String)> {
             return cached.clone();
         }
     }
-    let tagged_file = Probe::open(path).ok()?.read().ok()?;
-    let tag = tagged_file.primary_tag().or_else(|| tagged_file.first_tag())?;
-    let title = tag.get_string(&ItemKey::TrackTitle).unwrap_or_default().to_string();
-    let artist = tag.get_string(&ItemKey::TrackArtist).unwrap_or_default().to_string();
-    let album = tag.get_string(&ItemKey::AlbumTitle).unwrap_or_default().to_string();
-    let result = Some((title, artist, album));
+    // Go by content, not extension, so a mislabeled file shows its real codec
+    let tagged_file = Probe::open(path).ok()?.guess_file_type().ok()?.read().ok()?;
+    let properties = tagged_file.properties();
+    let duration = properties.duration().as_secs();
+    let (codec, drm) = crate::compat::detect_codec(path, &tagged_file);
+    let mut metadata = EmbeddedMetadata {
+        duration_secs: (duration > 0).then_some(duration as u32),
+        codec,
+        sample_rate: properties.sample_rate(),
+        bitrate_kbps: properties.audio_bitrate(),
+        channels: properties.channels(),
+        drm,
+        ..Default::default()
+    };
+    // Untagged files still have audio properties worth indexing
+    if let Some(tag) = tagged_file.primary_tag().or_else(|| tagged_file.first_tag()) {
+        metadata.title = tag.get_string(&ItemKey::TrackTitle).unwrap_or_default().to_string();
+        metadata.artist = tag.get_string(&ItemKey::TrackArtist).unwrap_or_default().to_string();
+        metadata.album = tag.get_string(&ItemKey::AlbumTitle).unwrap_or_default().to_string();
+        metadata.genre = tag.get_string(&ItemKey::Genre).unwrap_or_default().to_string();
+        metadata.track_number = tag.track();
+        metadata.disc_number = tag.disk();
+    }
+    let result = Some(metadata);
     let mut cache = METADATA_CACHE.lock().unwrap();
     cache.insert(key, result.clone());
     result
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+/// Longest long file name FAT32 allows, in UTF-16 units
+pub const MAX_COMPONENT: usize = 255;
+/// Longest path from the device root (e.g. "/MUSIC/...") that copies and the PSP handle reliably
+pub const MAX_PATH: usize = 255;
+// Components are never truncated below this while shortening a long path
+const MIN_COMPONENT: usize = 8;
+
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn utf16_len(s: &str) -> usize {
+    s.encode_utf16().count()
+}
+
+// Longest prefix of `s` that fits in `max` UTF-16 units
+fn truncate_utf16(s: &str, max: usize) -> &str {
+    let mut units = 0;
+    for (i, c) in s.char_indices() {
+        units += c.len_utf16();
+        if units > max {
+            return &s[..i];
+        }
+    }
+    s
+}
+
+fn split_ext(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => name.split_at(dot),
+        _ => (name, ""),
+    }
+}
+
+// Cut a name down to `max` units, keeping its extension
+fn shorten(name: &str, max: usize) -> String {
+    if utf16_len(name) <= max {
+        return name.to_string();
+    }
+    let (stem, ext) = split_ext(name);
+    let keep = max.saturating_sub(utf16_len(ext)).max(1);
+    let stem = truncate_utf16(stem, keep).trim_end_matches([' ', '.']);
+    format!("{}{}", stem, ext)
+}
+
+/// Map one file or directory name to a FAT32-legal one.
+///
+/// `"*/:<>?\|` and control characters map to fixed replacements, leading spaces and
+/// trailing dots/spaces are dropped, device names get a `_` suffix and the result is
+/// cut to [`MAX_COMPONENT`]. Legal names come back unchanged.
+pub fn sanitize_component(name: &str) -> String {
+    let mapped: String = name.chars()
+        .map(|c| match c {
+            '<' => '(',
+            '>' => ')',
+            ':' | '/' | '\\' | '|' => '-',
+            '"' => '\'',
+            '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let mut name = mapped.trim_start_matches(' ').trim_end_matches(['.', ' ']).to_string();
+    if name.is_empty() {
+        name = "_".to_string();
+    }
+    let stem = split_ext(&name).0;
+    if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        name.insert(stem.len(), '_');
+    }
+    shorten(&name, MAX_COMPONENT)
+}
+
+/// Sanitize every component of a `/`-separated path relative to the device root and
+/// shorten the longest components until the whole path fits in [`MAX_PATH`].
+/// Applying it to its own output changes nothing.
+pub fn sanitize_path(path: &str) -> String {
+    let leading = if path.starts_with('/') { "/" } else { "" };
+    let mut parts: Vec<String> = path.split('/')
+        .filter(|p| !p.is_empty())
+        .map(sanitize_component)
+        .collect();
+    let total = |parts: &[String]| utf16_len(leading) + parts.iter().map(|p| utf16_len(p)).sum::<usize>() + parts.len().saturating_sub(1);
+    while total(&parts) > MAX_PATH {
+        let over = total(&parts) - MAX_PATH;
+        // The first component is the top-level folder (MUSIC) and must keep its name
+        let mut shrinkable: Vec<(usize, usize)> = parts.iter().enumerate().skip(1)
+            .map(|(i, p)| (i, utf16_len(p)))
+            .filter(|(_, len)| *len > MIN_COMPONENT)
+            .collect();
+        shrinkable.sort_by_key(|(_, len)| std::cmp::Reverse(*len));
+        let Some(&(longest, len)) = shrinkable.first() else {
+            break;
+        };
+        // Cut the longest down to the next longest at most, so long names shrink evenly
+        let next = shrinkable.get(1).map(|(_, len)| *len).unwrap_or(0);
+        let target = len.saturating_sub(over).max(next).max(MIN_COMPONENT).min(len - 1);
+        let shortened = shorten(&parts[longest], target);
+        // An extension longer than the target leaves nothing to cut; cut through it instead
+        parts[longest] = if utf16_len(&shortened) < len {
+            shortened
+        } else {
+            truncate_utf16(&parts[longest], target).trim_end_matches([' ', '.']).to_string()
+        };
+    }
+    format!("{}{}", leading, parts.join("/"))
+}
+
+/// A path the sanitizer had to change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathChange {
+    pub original: String,
+    pub sanitized: String,
+}
+
+/// Sanitizes many paths for one device, keeping results unique and recording every change.
+///
+/// FAT compares names case-insensitively, so two originals that end up with the same
+/// name (e.g. "AC/DC" and "AC:DC", or two long titles cut to the same prefix) get
+/// "~2", "~3"... before the extension, in the order they were first seen.
+#[derive(Debug, Default)]
+pub struct Fat32Sanitizer {
+    by_original: HashMap<String, String>,
+    // lowercase sanitized path -> original that owns it
+    owners: HashMap<String, String>,
+    changes: Vec<PathChange>,
+}
+
+impl Fat32Sanitizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sanitized form of a `/`-separated path relative to the device root
+    pub fn sanitize(&mut self, original: &str) -> String {
+        if let Some(done) = self.by_original.get(original) {
+            return done.clone();
+        }
+        let base = sanitize_path(original);
+        let mut sanitized = base.clone();
+        let mut n = 2;
+        while self.owners.get(&sanitized.to_lowercase()).is_some_and(|owner| owner != original) {
+            let (dir, name) = base.rsplit_once('/').unwrap_or(("", &base));
+            let (stem, ext) = split_ext(name);
+            let suffix = format!("~{}", n);
+            // Keep within both the component and the path limit so the result stays stable;
+            // path_room is what the path leaves for stem + suffix
+            let path_room = MAX_PATH.saturating_sub(utf16_len(&base) - utf16_len(stem));
+            let stem_max = MAX_COMPONENT.saturating_sub(utf16_len(ext))
+                .min(path_room)
+                .saturating_sub(utf16_len(&suffix));
+            let stem = truncate_utf16(stem, stem_max);
+            sanitized = if base.contains('/') {
+                format!("{}/{}{}{}", dir, stem, suffix, ext)
+            } else {
+                format!("{}{}{}", stem, suffix, ext)
+            };
+            n += 1;
+        }
+        self.owners.insert(sanitized.to_lowercase(), original.to_string());
+        self.by_original.insert(original.to_string(), sanitized.clone());
+        if sanitized != original {
+            self.changes.push(PathChange { original: original.to_string(), sanitized: sanitized.clone() });
+        }
+        sanitized
+    }
+
+    /// Every path that came out different from its original, in order
+    pub fn changes(&self) -> &[PathChange] {
+        &self.changes
+    }
+}
@@ -0,0 +1,210 @@
+use crate::music::Track;
+use crate::ordering::{self, SortKey};
+use std::collections::HashMap;
+
+/// Tracks a generated playlist may draw from
+#[derive(Debug, Clone)]
+pub enum TrackSource {
+    Library,
+    Artist(String),
+    Genre(String),
+    /// Entries of an existing playlist (indices into the track list)
+    Entries(Vec<usize>),
+}
+
+impl TrackSource {
+    /// Indices of the tracks in this source
+    pub fn entries(&self, tracks: &[Track]) -> Vec<usize> {
+        let matching = |pred: &dyn Fn(&Track) -> bool| -> Vec<usize> {
+            tracks.iter().enumerate().filter(|(_, t)| pred(t)).map(|(i, _)| i).collect()
+        };
+        match self {
+            TrackSource::Library => (0..tracks.len()).collect(),
+            TrackSource::Artist(artist) => matching(&|t| t.artist.eq_ignore_ascii_case(artist)),
+            TrackSource::Genre(genre) => matching(&|t| t.genre.eq_ignore_ascii_case(genre)),
+            TrackSource::Entries(entries) => entries.iter().copied().filter(|i| *i < tracks.len()).collect(),
+        }
+    }
+}
+
+/// What the generated playlist should add up to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    DurationSecs(u64),
+    Bytes(u64),
+}
+
+impl Target {
+    fn amount(&self) -> u64 {
+        match self {
+            Target::DurationSecs(secs) => *secs,
+            Target::Bytes(bytes) => *bytes,
+        }
+    }
+
+    fn measure(&self, track: &Track) -> Option<u64> {
+        match self {
+            Target::DurationSecs(_) => track.duration_secs.map(u64::from),
+            Target::Bytes(_) => Some(track.size_bytes).filter(|b| *b > 0),
+        }
+    }
+
+    fn format(&self, amount: u64) -> String {
+        match self {
+            Target::DurationSecs(_) => format_duration(amount),
+            Target::Bytes(_) => format_size(amount),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GenerateOptions {
+    pub target: Target,
+    /// Add or skip whole albums instead of single tracks
+    pub keep_albums: bool,
+    pub seed: u64,
+}
+
+/// Outcome of a generation run
+#[derive(Debug, Clone)]
+pub struct GenerateReport {
+    pub entries: Vec<usize>,
+    pub target: Target,
+    pub achieved: u64,
+    /// Source tracks left out because their duration/size is unknown
+    pub skipped_unknown: usize,
+}
+
+impl GenerateReport {
+    /// Achieved amount as a percentage of the target
+    pub fn percent(&self) -> f64 {
+        match self.target.amount() {
+            0 => 100.0,
+            amount => self.achieved as f64 * 100.0 / amount as f64,
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} tracks, {} of {} ({:.1}%)",
+            self.entries.len(),
+            self.target.format(self.achieved),
+            self.target.format(self.target.amount()),
+            self.percent()
+        )
+    }
+}
+
+/// Pick tracks from `source` to get as close to the target as possible.
+///
+/// Units (tracks, or albums with `keep_albums`) are tried in a seeded random order.
+/// Byte targets are never exceeded since they usually describe free space; a
+/// duration target may be overshot by the last unit when that lands closer to it.
+pub fn generate(tracks: &[Track], source: &TrackSource, options: &GenerateOptions) -> GenerateReport {
+    let target = options.target;
+    let mut skipped_unknown = 0;
+    let mut units: Vec<(Vec<usize>, u64)> = Vec::new();
+
+    if options.keep_albums {
+        let mut albums: HashMap<(String, String), Vec<usize>> = HashMap::new();
+        for idx in source.entries(tracks) {
+            let track = &tracks[idx];
+            albums.entry((track.artist.to_lowercase(), track.album.to_lowercase())).or_default().push(idx);
+        }
+        let mut keys: Vec<_> = albums.keys().cloned().collect();
+        keys.sort();
+        for key in keys {
+            let mut entries = albums.remove(&key).unwrap_or_default();
+            let sizes: Option<Vec<u64>> = entries.iter().map(|i| target.measure(&tracks[*i])).collect();
+            match sizes {
+                Some(sizes) => {
+                    ordering::sort_entries(&mut entries, tracks, &[SortKey::Disc, SortKey::Track]);
+                    units.push((entries, sizes.iter().sum()));
+                }
+                None => skipped_unknown += entries.len(),
+            }
+        }
+    } else {
+        for idx in source.entries(tracks) {
+            match target.measure(&tracks[idx]) {
+                Some(size) => units.push((vec![idx], size)),
+                None => skipped_unknown += 1,
+            }
+        }
+    }
+
+    let mut order: Vec<usize> = (0..units.len()).collect();
+    ordering::shuffle_entries(&mut order, options.seed);
+
+    let goal = target.amount();
+    let mut achieved = 0;
+    let mut chosen = Vec::new();
+    let mut leftovers = Vec::new();
+    for unit in order {
+        if achieved + units[unit].1 <= goal {
+            achieved += units[unit].1;
+            chosen.push(unit);
+        } else {
+            leftovers.push(unit);
+        }
+    }
+
+    // Overshooting "about 45 minutes" is fine if it lands closer than stopping short
+    if let Target::DurationSecs(_) = target {
+        let gap = goal - achieved;
+        if let Some(best) = leftovers.iter().copied().min_by_key(|u| units[*u].1) {
+            let over = achieved + units[best].1 - goal;
+            if over < gap {
+                achieved += units[best].1;
+                chosen.push(best);
+            }
+        }
+    }
+
+    GenerateReport {
+        entries: chosen.into_iter().flat_map(|u| units[u].0.clone()).collect(),
+        target,
+        achieved,
+        skipped_unknown,
+    }
+}
+
+/// Parse a size like "2G", "700MB" or "1048576" into bytes (binary units)
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim().to_uppercase();
+    let digits_end = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+    let (number, unit) = s.split_at(digits_end);
+    let number: f64 = number.parse().ok()?;
+    let multiplier = match unit.trim().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 1u64,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return None,
+    };
+    Some((number * multiplier as f64) as u64)
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+pub fn format_duration(secs: u64) -> String {
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs % 3600 / 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
@@ -1,11 +1,25 @@
 use eframe::egui::{self, Context, TextureHandle};
+use crate::album_art::{self, ArtResult, ProviderFailures};
+use crate::art_cache::{self, ArtCache, CoverChoice};
+use crate::art_embed;
+use crate::capacity::{self, CapacityPlan};
+use crate::compat;
 use crate::db;
+use crate::device::{self, PspDevice};
+use crate::generator::{self, TrackSource};
+use crate::http::{self, ProviderError};
 use crate::music::Track;
-use std::collections::HashMap;
+use crate::ordering::{self, SortKey};
+use crate::relink::{self, RelinkReport};
+use crate::tag_writer;
+use crate::transcode::{self, Transcoder};
+use crate::verify::{self, EntryStatus, VerifyReport};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, OnceLock};
 
 // Cached metadata to avoid repeated disk reads
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 struct CachedMetadata {
     title: String,
     artist: String,
@@ -19,31 +33,294 @@ struct Playlist {
     track_indices: Vec<usize>, // Indices into the main tracks vector
 }
 
+// A reversible edit to playlists or library tags
+enum EditCommand {
+    CreatePlaylist { index: usize, playlist: Playlist },
+    DeletePlaylist { index: usize, playlist: Playlist },
+    RenamePlaylist { index: usize, old: String, new: String },
+    // Entry add/remove/reorder all replace the playlist's entry list
+    SetEntries { playlist: usize, before: Vec<usize>, after: Vec<usize>, label: String },
+    EditTags { track: usize, before: CachedMetadata, after: CachedMetadata },
+}
+
+impl EditCommand {
+    fn describe(&self) -> String {
+        match self {
+            EditCommand::CreatePlaylist { playlist, .. } => format!("create playlist '{}'", playlist.name),
+            EditCommand::DeletePlaylist { playlist, .. } => format!("delete playlist '{}'", playlist.name),
+            EditCommand::RenamePlaylist { old, new, .. } => format!("rename '{}' to '{}'", old, new),
+            EditCommand::SetEntries { label, .. } => label.clone(),
+            EditCommand::EditTags { after, .. } => format!("edit tags of '{}'", after.title),
+        }
+    }
+}
+
+// Undo/redo stacks of executed commands
+#[derive(Default)]
+struct History {
+    undo: Vec<EditCommand>,
+    redo: Vec<EditCommand>,
+}
+
+// Sort orders offered in the playlist manager, as key chains
+const SORT_PRESETS: &[(&str, &[SortKey])] = &[
+    ("Artist", &[SortKey::Artist, SortKey::Album, SortKey::Disc, SortKey::Track]),
+    ("Album", &[SortKey::Album, SortKey::Disc, SortKey::Track]),
+    ("Disc/track", &[SortKey::Disc, SortKey::Track]),
+    ("Title", &[SortKey::Title]),
+    ("Duration", &[SortKey::Duration]),
+];
+
+// Reordering operation requested from the playlist manager
+enum OrderOp {
+    Sort(&'static str, &'static [SortKey]),
+    Shuffle,
+    SmartShuffle,
+}
+
+// Settings of the duration/size playlist generator form
+struct GeneratorForm {
+    // Label of the chosen source, matched against the choices built each frame
+    source: String,
+    by_size: bool,
+    minutes: u32,
+    megabytes: u32,
+    keep_albums: bool,
+    report: Option<String>,
+}
+
+impl Default for GeneratorForm {
+    fn default() -> Self {
+        Self {
+            source: "Whole library".to_string(),
+            by_size: false,
+            minutes: 45,
+            megabytes: 1024,
+            keep_albums: false,
+            report: None,
+        }
+    }
+}
+
+// Options applied when adding several tracks to a playlist at once
+#[derive(Clone, Copy)]
+struct AddOptions {
+    skip_duplicates: bool,
+    keep_disc_order: bool,
+}
+
+impl Default for AddOptions {
+    fn default() -> Self {
+        Self { skip_duplicates: true, keep_disc_order: true }
+    }
+}
+
+// What export does with tracks the PSP can't play
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum IncompatibleExport {
+    Keep,
+    Skip,
+    /// Convert into transcode_cache/ and export that copy instead; the library is left alone
+    Convert,
+}
+
+impl IncompatibleExport {
+    fn label(self) -> &'static str {
+        match self {
+            IncompatibleExport::Keep => "Keep as is",
+            IncompatibleExport::Skip => "Leave out",
+            IncompatibleExport::Convert => "Convert",
+        }
+    }
+}
+
+// What the capacity planner shows: the choices to tick and, with a PSP of known free space,
+// its label and the plan for the ticked ones
+struct CapacityView {
+    choices: Vec<(String, Vec<usize>)>,
+    plan: Option<(String, CapacityPlan)>,
+}
+
+// Relink proposals for a playlist file, waiting for the user to confirm them
+struct RelinkReview {
+    path: std::path::PathBuf,
+    report: RelinkReport,
+    // Same order as report.proposals; confident matches start checked
+    accepted: Vec<bool>,
+    outcome: Option<String>,
+}
+
+// Covers on offer for an album; `choices` is None while they are being gathered
+struct ArtPicker {
+    key: (String, String),
+    choices: Option<Vec<(CoverChoice, Option<TextureHandle>)>>,
+}
+
+// Covers gathered for the picker, decoded off the UI thread
+type PickerChoices = ((String, String), Vec<(CoverChoice, Option<egui::ColorImage>)>);
+
+// An album's art lookup, answered by a background thread
+type ArtLookup = ((String, String), Result<Option<ArtResult>, ProviderFailures>);
+
+// Progress of a playlist export running in the background
+enum ExportUpdate {
+    // About to convert the track after the first `done` of `total`
+    Converting { done: usize, total: usize, track: String },
+    Finished(String),
+}
+
+// Download or read the cover and write it into every file; returns the status to show
+fn embed_cover_into(paths: &[std::path::PathBuf], url: Option<String>, backup_dir: Option<&std::path::Path>) -> String {
+    let cover = match url {
+        Some(url) => art_embed::CoverSource::from_url(&url),
+        None => match paths.iter().find(|p| crate::embedded_art::extract_embedded_art(&p.to_string_lossy()).is_some()) {
+            Some(path) => art_embed::CoverSource::Embedded(path.clone()),
+            None => return "No cover to embed: none found online or in the files.".to_string(),
+        },
+    };
+    match art_embed::embed_batch(paths, &cover, backup_dir) {
+        Ok(report) => {
+            for (path, why) in &report.failed {
+                eprintln!("[Art] Failed to embed into {:?}: {}", path, why);
+            }
+            let mut status = format!("Embedded cover into {} of {} tracks.", report.embedded.len(), paths.len());
+            if !report.backups.is_empty() {
+                status.push_str(" Originals are in art_backups/.");
+            }
+            status
+        }
+        Err(e) => format!("Couldn't prepare the cover: {}", e),
+    }
+}
+
+fn decode_image(bytes: &[u8]) -> Option<egui::ColorImage> {
+    let image_buffer = image::load_from_memory(bytes).ok()?.to_rgba8();
+    let size = [image_buffer.width() as usize, image_buffer.height() as usize];
+    let pixels = image_buffer.as_flat_samples();
+    Some(egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_slice()))
+}
+
+// Where a cover came from, as shown next to it
+fn cover_source(provider: &str) -> String {
+    match provider {
+        "embedded" => "the files' own tags".to_string(),
+        "folder" => "the album folder".to_string(),
+        "file" => "a chosen image file".to_string(),
+        provider => provider.to_string(),
+    }
+}
+
+// Shown instead of a cover when the providers couldn't be asked; the details go in the hover text
+fn art_failure_text(failures: &ProviderFailures) -> String {
+    let rate_limited: Vec<&str> = failures.iter()
+        .filter(|(_, e)| matches!(e, ProviderError::RateLimited(_)))
+        .map(|(provider, _)| *provider)
+        .collect();
+    if http::is_offline() {
+        "Offline mode: only covers in the files, their folders or saved earlier are shown.".to_string()
+    } else if failures.iter().all(|(_, e)| matches!(e, ProviderError::Offline(_))) {
+        "Offline: the art services can't be reached.".to_string()
+    } else if !rate_limited.is_empty() {
+        format!("Rate limited by {}. Try again later.", rate_limited.join(", "))
+    } else {
+        "Album art lookup failed.".to_string()
+    }
+}
+
+/// Render the playlists of an "add to playlist" context menu; returns the one picked
+fn playlist_target_menu(ui: &mut egui::Ui, playlists: &[Playlist], options: &mut AddOptions) -> Option<usize> {
+    let mut chosen = None;
+    if playlists.is_empty() {
+        ui.colored_label(egui::Color32::GRAY, "No playlists yet. Create one in the Playlist Manager.");
+    }
+    for (idx, playlist) in playlists.iter().enumerate() {
+        if ui.button(format!("📋 {}", playlist.name)).clicked() {
+            chosen = Some(idx);
+            ui.close_menu();
+        }
+    }
+    ui.separator();
+    ui.checkbox(&mut options.skip_duplicates, "Skip duplicates");
+    ui.checkbox(&mut options.keep_disc_order, "Keep disc/track order");
+    chosen
+}
+
 pub struct TrackViewerApp {
     tracks: Vec<Track>,
     metadata_cache: Vec<CachedMetadata>, // Same index as tracks
     selected_track: Option<usize>,
+    // Multi-selection in the songs column (shift/ctrl-click)
+    selected_tracks: BTreeSet<usize>,
+    selection_anchor: Option<usize>,
     selected_artist_idx: Option<usize>,
     selected_album_idx: Option<usize>,
     hovered_index: Option<usize>,
     music_dir: String,
+    // PSPs found in the mount table, offered next to the folder picker
+    devices: Vec<PspDevice>,
     is_scanning: bool,
     debug: bool,
     image_cache: HashMap<String, TextureHandle>,
     // Cache album art URLs to avoid repeated API calls
-    album_art_cache: HashMap<(String, String), Option<String>>, // (artist, album) -> url
+    album_art_cache: HashMap<(String, String), Option<ArtResult>>, // (artist, album) -> url and provider
+    // Why providers couldn't be asked, for albums the last lookup found nothing for
+    art_failures: HashMap<(String, String), ProviderFailures>,
+    // Lookups and cover images kept in music_index.db across sessions
+    art_store: Option<ArtCache>,
     // Track which song's details window is currently open
     last_details_track: Option<usize>,
     // Track if we're currently fetching album art
     fetching_art_for: HashMap<(String, String), bool>, // (artist, album) -> is_fetching
     // Channel for receiving album art URLs from background threads
-    art_receiver: Receiver<((String, String), Option<String>)>,
-    art_sender: Sender<((String, String), Option<String>)>,
+    art_receiver: Receiver<ArtLookup>,
+    art_sender: Sender<ArtLookup>,
     // Playlist management
     playlists: Vec<Playlist>,
     show_playlist_manager: bool,
     new_playlist_name: String,
     selected_playlist_idx: Option<usize>,
+    add_options: AddOptions,
+    history: History,
+    // Playlist awaiting delete confirmation
+    confirm_delete: Option<usize>,
+    // Playlist being renamed, with the edited name
+    renaming: Option<(usize, String)>,
+    // Tag draft for the track shown in Track Details
+    tag_draft: Option<(usize, CachedMetadata)>,
+    // Seed for playlist shuffles as the user typed it; empty for a new random seed each time
+    shuffle_seed: String,
+    // Why the typed seed couldn't be used for the last shuffle
+    seed_error: Option<String>,
+    generator_form: GeneratorForm,
+    // Last device check: playlist name and what was found
+    verify_result: Option<(String, VerifyReport)>,
+    relink_review: Option<RelinkReview>,
+    // How exported playlists treat tracks the PSP can't play
+    export_incompatible: IncompatibleExport,
+    // Converter for exports and size projections, set up on first use
+    converter: OnceLock<Arc<Transcoder>>,
+    // Set while an export converts and writes in the background
+    exporting: bool,
+    export_status: Option<String>,
+    export_receiver: Receiver<ExportUpdate>,
+    export_sender: Sender<ExportUpdate>,
+    // Playlists and albums ticked in the capacity planner, by label, in the order ticked
+    capacity_picks: Vec<String>,
+    // Worked out when the planner is drawn; cleared whenever picks, playlists, tags, device or export mode change
+    capacity_view: Option<CapacityView>,
+    // Why the last tag edit wasn't fully saved, shown in Track Details
+    tag_status: Option<String>,
+    // Copy files into art_backups/ before embedding cover art
+    embed_backup: bool,
+    // Outcome of the last cover art embed, or what the running one is doing
+    art_embed_status: Option<String>,
+    // Set while a cover is written into files in the background
+    embedding: bool,
+    embed_receiver: Receiver<String>,
+    embed_sender: Sender<String>,
+    art_picker: Option<ArtPicker>,
+    picker_receiver: Receiver<PickerChoices>,
+    picker_sender: Sender<PickerChoices>,
 }
 
 impl TrackViewerApp {
@@ -57,31 +334,45 @@ impl TrackViewerApp {
         // Build metadata cache once at startup
         let metadata_cache: Vec<CachedMetadata> = tracks.iter()
             .map(|track| {
-                let (title, artist, album) = crate::embedded_art::extract_metadata(&track.path)
-                    .unwrap_or((track.title.clone(), track.artist.clone(), track.album.clone()));
+                let embedded = crate::embedded_art::extract_metadata(&track.path).unwrap_or_default();
                 CachedMetadata {
-                    title: if title.is_empty() { track.title.clone() } else { title },
-                    artist: if artist.is_empty() { track.artist.clone() } else { artist },
-                    album: if album.is_empty() { track.album.clone() } else { album },
+                    title: if embedded.title.is_empty() { track.title.clone() } else { embedded.title },
+                    artist: if embedded.artist.is_empty() { track.artist.clone() } else { embedded.artist },
+                    album: if embedded.album.is_empty() { track.album.clone() } else { embedded.album },
                 }
             })
             .collect();
         
+        let devices = device::detect_devices();
+        let art_store = ArtCache::open(db_path, &crate::config::Config::load())
+            .map_err(|e| eprintln!("[GUI] Cover art won't be cached: {}", e))
+            .ok();
+        
         // Create channel for background album art fetching
         let (art_sender, art_receiver) = std::sync::mpsc::channel();
+        let (picker_sender, picker_receiver) = std::sync::mpsc::channel();
+        let (export_sender, export_receiver) = std::sync::mpsc::channel();
+        let (embed_sender, embed_receiver) = std::sync::mpsc::channel();
         
         Self {
             tracks,
             metadata_cache,
             selected_track: None,
+            selected_tracks: BTreeSet::new(),
+            selection_anchor: None,
             selected_artist_idx: None,
             selected_album_idx: None,
             hovered_index: None,
-            music_dir: "/mnt/psp/MUSIC".to_string(),
+            music_dir: devices.first()
+                .map(|d| d.music_dir.display().to_string())
+                .unwrap_or_else(|| "/mnt/psp/MUSIC".to_string()),
+            devices,
             is_scanning: false,
             debug,
             image_cache: HashMap::new(),
             album_art_cache: HashMap::new(),
+            art_failures: HashMap::new(),
+            art_store,
             last_details_track: None,
             fetching_art_for: HashMap::new(),
             art_receiver,
@@ -90,6 +381,33 @@ impl TrackViewerApp {
             show_playlist_manager: false,
             new_playlist_name: String::new(),
             selected_playlist_idx: None,
+            add_options: AddOptions::default(),
+            history: History::default(),
+            confirm_delete: None,
+            renaming: None,
+            tag_draft: None,
+            shuffle_seed: String::new(),
+            seed_error: None,
+            generator_form: GeneratorForm::default(),
+            verify_result: None,
+            relink_review: None,
+            export_incompatible: IncompatibleExport::Keep,
+            converter: OnceLock::new(),
+            exporting: false,
+            export_status: None,
+            export_receiver,
+            export_sender,
+            capacity_picks: Vec::new(),
+            capacity_view: None,
+            embed_backup: true,
+            art_embed_status: None,
+            tag_status: None,
+            embedding: false,
+            embed_receiver,
+            embed_sender,
+            art_picker: None,
+            picker_receiver,
+            picker_sender,
         }
     }
 
@@ -97,24 +415,87 @@ impl TrackViewerApp {
         if let Some(tex) = self.image_cache.get(url) {
             return Some(tex.clone());
         }
-        if let Ok(response) = reqwest::blocking::get(url) {
-            if let Ok(bytes) = response.bytes() {
-                if let Ok(image) = image::load_from_memory(&bytes) {
-                    let image_buffer = image.to_rgba8();
-                    let size = [image_buffer.width() as usize, image_buffer.height() as usize];
-                    let pixels = image_buffer.as_flat_samples();
-                    let color_image = egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_slice());
-                    let texture = ctx.load_texture(url, color_image, Default::default());
-                    self.image_cache.insert(url.to_string(), texture.clone());
-                    return Some(texture);
-                }
+        let bytes = match &self.art_store {
+            Some(store) => store.image(url),
+            None => art_embed::CoverSource::from_url(url).load().ok(),
+        };
+        let color_image = decode_image(&bytes?)?;
+        let texture = ctx.load_texture(url, color_image, Default::default());
+        self.image_cache.insert(url.to_string(), texture.clone());
+        Some(texture)
+    }
+
+    /// Forget an album's cover everywhere it is cached, so the providers are asked again
+    fn refresh_album_art(&mut self, artist: &str, album: &str) {
+        let key = (artist.to_string(), album.to_string());
+        if let Some(Some(found)) = self.album_art_cache.remove(&key) {
+            self.image_cache.remove(&found.url);
+        }
+        self.art_failures.remove(&key);
+        if let Some(store) = &self.art_store {
+            if let Err(e) = store.forget(artist, album) {
+                eprintln!("[GUI] Could not clear cached art for {} - {}: {}", artist, album, e);
             }
         }
-        None
+        // Looked up again when Track Details next draws
+        self.last_details_track = None;
+    }
+
+    /// Gather every cover on offer for the album of a track, for the user to pick from
+    fn open_art_picker(&mut self, idx: usize) {
+        let Some(meta) = self.metadata_cache.get(idx) else {
+            return;
+        };
+        let key = (meta.artist.clone(), meta.album.clone());
+        let paths: Vec<std::path::PathBuf> = self.album_tracks(idx).iter()
+            .map(|i| std::path::PathBuf::from(&self.tracks[*i].path))
+            .collect();
+        self.art_picker = Some(ArtPicker { key: key.clone(), choices: None });
+        let sender = self.picker_sender.clone();
+        std::thread::spawn(move || {
+            let choices = art_cache::gather_choices(&key.0, &key.1, &paths, 3)
+                .into_iter()
+                .map(|choice| {
+                    let image = choice.image.as_deref().and_then(decode_image);
+                    (choice, image)
+                })
+                .collect();
+            let _ = sender.send((key, choices));
+        });
     }
 
-    fn clear_image_cache(&mut self, _ctx: &Context) {
-        self.image_cache.clear();
+    /// Show `art` for an album from now on, here and when embedding, and remember it across sessions
+    fn pin_album_art(&mut self, key: (String, String), mut art: ArtResult) {
+        art.pinned = true;
+        if let Some(store) = &self.art_store {
+            if let Err(e) = store.pin(&key.0, &key.1, &art) {
+                eprintln!("[GUI] Could not save the chosen cover for {} - {}: {}", key.0, key.1, e);
+            }
+        }
+        self.art_failures.remove(&key);
+        self.album_art_cache.insert(key, Some(art));
+        self.art_picker = None;
+    }
+
+    /// Go offline or back online for the whole app; albums that couldn't be looked up are tried again
+    fn set_offline(&mut self, offline: bool) {
+        http::set_offline(offline);
+        if !offline {
+            for (key, _) in self.art_failures.drain() {
+                self.album_art_cache.remove(&key);
+            }
+            self.last_details_track = None;
+        }
+    }
+
+    /// Drop an album's chosen cover and look its art up again
+    fn unpin_album_art(&mut self, artist: &str, album: &str) {
+        if let Some(store) = &self.art_store {
+            if let Err(e) = store.unpin(artist, album) {
+                eprintln!("[GUI] Could not unpin the cover of {} - {}: {}", artist, album, e);
+            }
+        }
+        self.refresh_album_art(artist, album);
     }
 
     fn get_artists(&self) -> Vec<String> {
@@ -151,8 +532,21 @@ impl TrackViewerApp {
     }
 
     fn count_tracks_for_album(&self, artist: Option<&String>, album: &str) -> usize {
+        self.track_indices_for_album(artist, album).len()
+    }
+
+    fn track_indices_for_artist(&self, artist: &str) -> Vec<usize> {
         self.metadata_cache.iter()
-            .filter(|meta| {
+            .enumerate()
+            .filter(|(_, meta)| meta.artist == artist)
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    fn track_indices_for_album(&self, artist: Option<&String>, album: &str) -> Vec<usize> {
+        self.metadata_cache.iter()
+            .enumerate()
+            .filter(|(_, meta)| {
                 let track_album = if meta.album.is_empty() { 
                     "(Unknown Album)".to_string() 
                 } else { 
@@ -161,52 +555,510 @@ impl TrackViewerApp {
                 let artist_matches = artist.is_none_or(|a| &meta.artist == a);
                 artist_matches && track_album == album
             })
-            .count()
+            .map(|(idx, _)| idx)
+            .collect()
     }
-    
-    fn export_playlist(&self, playlist_idx: usize) {
-        if let Some(playlist) = self.playlists.get(playlist_idx) {
-            // Collect the actual tracks
-            let playlist_tracks: Vec<&Track> = playlist.track_indices.iter()
-                .filter_map(|idx| self.tracks.get(*idx))
-                .collect();
-            
-            if playlist_tracks.is_empty() {
-                eprintln!("[Playlist] Cannot export empty playlist");
-                return;
+
+    /// Run a command and record it for undo
+    fn execute(&mut self, command: EditCommand) {
+        self.apply(&command, false);
+        self.history.undo.push(command);
+        self.history.redo.clear();
+    }
+
+    fn undo(&mut self) {
+        if let Some(command) = self.history.undo.pop() {
+            eprintln!("[History] Undo {}", command.describe());
+            self.apply(&command, true);
+            self.history.redo.push(command);
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(command) = self.history.redo.pop() {
+            eprintln!("[History] Redo {}", command.describe());
+            self.apply(&command, false);
+            self.history.undo.push(command);
+        }
+    }
+
+    /// Apply a command forwards, or its inverse when `reverse` is set
+    fn apply(&mut self, command: &EditCommand, reverse: bool) {
+        // These hold playlist and track indices the command may shift; a stale one would hit the wrong item
+        self.confirm_delete = None;
+        self.renaming = None;
+        self.tag_draft = None;
+        self.capacity_view = None;
+        match command {
+            EditCommand::CreatePlaylist { index, playlist } if !reverse => self.insert_playlist(*index, playlist.clone()),
+            EditCommand::DeletePlaylist { index, playlist } if reverse => self.insert_playlist(*index, playlist.clone()),
+            EditCommand::CreatePlaylist { index, .. } | EditCommand::DeletePlaylist { index, .. } => {
+                self.remove_playlist(*index);
             }
-            
-            // Use file dialog to choose save location
-            let default_name = format!("{}.m3u8", playlist.name);
-            if let Some(path) = rfd::FileDialog::new()
-                .set_file_name(&default_name)
-                .add_filter("M3U8 Playlist", &["m3u8"])
-                .save_file()
-            {
-                match crate::playlist::write_m3u8(path.to_str().unwrap(), &playlist_tracks) {
-                    Ok(_) => {
-                        eprintln!("[Playlist] Exported {} tracks to: {:?}", playlist_tracks.len(), path);
+            EditCommand::RenamePlaylist { index, old, new } => {
+                if let Some(playlist) = self.playlists.get_mut(*index) {
+                    playlist.name = if reverse { old.clone() } else { new.clone() };
+                }
+            }
+            EditCommand::SetEntries { playlist, before, after, .. } => {
+                if let Some(playlist) = self.playlists.get_mut(*playlist) {
+                    playlist.track_indices = if reverse { before.clone() } else { after.clone() };
+                }
+            }
+            EditCommand::EditTags { track, before, after } => {
+                let meta = if reverse { before } else { after };
+                self.set_track_tags(*track, meta.clone());
+            }
+        }
+    }
+
+    fn insert_playlist(&mut self, index: usize, playlist: Playlist) {
+        let index = index.min(self.playlists.len());
+        self.playlists.insert(index, playlist);
+        if let Some(selected) = self.selected_playlist_idx.filter(|s| *s >= index) {
+            self.selected_playlist_idx = Some(selected + 1);
+        }
+    }
+
+    fn remove_playlist(&mut self, index: usize) {
+        if index >= self.playlists.len() {
+            return;
+        }
+        self.playlists.remove(index);
+        self.selected_playlist_idx = match self.selected_playlist_idx {
+            Some(selected) if selected == index => None,
+            Some(selected) if selected > index => Some(selected - 1),
+            other => other,
+        };
+        self.renaming = None;
+    }
+
+    fn set_track_tags(&mut self, idx: usize, meta: CachedMetadata) {
+        let Some(track) = self.tracks.get_mut(idx) else {
+            return;
+        };
+        track.title = meta.title.clone();
+        track.artist = meta.artist.clone();
+        track.album = meta.album.clone();
+        self.metadata_cache[idx] = meta;
+        // Write through to the file; the tag edit log keeps the old values
+        let path = std::path::Path::new(&track.path);
+        let values = tag_writer::read_tags(path).map(|mut values| {
+            values.title = track.title.clone();
+            values.artist = track.artist.clone();
+            values.album = track.album.clone();
+            values
+        });
+        self.tag_status = match db::init_db("music_index.db") {
+            Ok(mut conn) => match values.and_then(|values| tag_writer::apply_edit(&mut conn, path, &values)) {
+                Ok(_) => None,
+                Err(e) => {
+                    eprintln!("[Tags] Failed to write tags to {:?}, updating the index only: {}", path, e);
+                    if let Err(e) = db::update_track_tags(&conn, track) {
+                        eprintln!("[History] Failed to update index: {}", e);
                     }
+                    Some(format!("Tags not written to the file: {}", e))
+                }
+            },
+            Err(e) => {
+                // Without the index there is no edit log, but the file still gets the new tags
+                eprintln!("[Tags] Index unavailable, writing tags of {:?} to the file only: {}", path, e);
+                match values.and_then(|values| tag_writer::write_tags(path, &values)) {
+                    Ok(()) => Some(format!("Tags written to the file, but the index is unavailable: {}", e)),
+                    Err(write_error) => Some(format!("Tags not saved: index unavailable ({}), file not written ({})", e, write_error)),
+                }
+            }
+        };
+    }
+
+    /// Replace a playlist's entries through the undo history
+    fn set_playlist_entries(&mut self, playlist_idx: usize, after: Vec<usize>, label: String) {
+        let Some(playlist) = self.playlists.get(playlist_idx) else {
+            return;
+        };
+        if playlist.track_indices == after {
+            return;
+        }
+        let before = playlist.track_indices.clone();
+        self.execute(EditCommand::SetEntries { playlist: playlist_idx, before, after, label });
+    }
+
+    /// Sources offered by the generator form, labelled for display
+    fn generator_sources(&self) -> Vec<(String, TrackSource)> {
+        let mut sources = vec![("Whole library".to_string(), TrackSource::Library)];
+        let artists = self.get_artists();
+        if let Some(artist) = self.selected_artist_idx.and_then(|i| artists.get(i)) {
+            sources.push((format!("Artist: {}", artist), TrackSource::Artist(artist.clone())));
+        }
+        let mut genres: Vec<&String> = self.tracks.iter().map(|t| &t.genre).filter(|g| !g.is_empty()).collect();
+        genres.sort();
+        genres.dedup();
+        for genre in genres {
+            sources.push((format!("Genre: {}", genre), TrackSource::Genre(genre.clone())));
+        }
+        for playlist in &self.playlists {
+            sources.push((format!("Playlist: {}", playlist.name), TrackSource::Entries(playlist.track_indices.clone())));
+        }
+        sources
+    }
+
+    /// Generate a playlist from the form settings and add it through the undo history
+    fn generate_playlist(&mut self, source: TrackSource) {
+        let seed = match self.typed_seed() {
+            Ok(seed) => seed,
+            Err(e) => {
+                self.generator_form.report = Some(e);
+                return;
+            }
+        };
+        let form = &self.generator_form;
+        let target = if form.by_size {
+            generator::Target::Bytes(form.megabytes as u64 * 1024 * 1024)
+        } else {
+            generator::Target::DurationSecs(form.minutes as u64 * 60)
+        };
+        let options = generator::GenerateOptions { target, keep_albums: form.keep_albums, seed };
+        let report = generator::generate(&self.tracks, &source, &options);
+        let name = if form.by_size {
+            format!("{} MB mix", form.megabytes)
+        } else {
+            format!("{} min mix", form.minutes)
+        };
+        let mut summary = format!("'{}' (seed {}): {}", name, seed, report.summary());
+        if report.skipped_unknown > 0 {
+            summary.push_str(&format!(", {} tracks skipped (unknown length/size)", report.skipped_unknown));
+        }
+        self.generator_form.report = Some(summary);
+        let index = self.playlists.len();
+        self.execute(EditCommand::CreatePlaylist { index, playlist: Playlist { name, track_indices: report.entries } });
+        self.selected_playlist_idx = Some(index);
+    }
+
+    /// The typed shuffle seed, or a new random one when the field is empty
+    fn typed_seed(&self) -> Result<u64, String> {
+        let typed = self.shuffle_seed.trim();
+        if typed.is_empty() {
+            return Ok(ordering::random_seed());
+        }
+        typed.parse().map_err(|_| format!("Seed must be a whole number, not '{}'", typed))
+    }
+
+    /// Reorder a playlist through the undo history
+    fn order_playlist(&mut self, playlist_idx: usize, op: OrderOp) {
+        let Some(playlist) = self.playlists.get(playlist_idx) else {
+            return;
+        };
+        let mut entries = playlist.track_indices.clone();
+        let label = match op {
+            OrderOp::Sort(name, keys) => {
+                ordering::sort_entries(&mut entries, &self.tracks, keys);
+                format!("sort '{}' by {}", playlist.name, name.to_lowercase())
+            }
+            OrderOp::Shuffle | OrderOp::SmartShuffle => {
+                let seed = match self.typed_seed() {
+                    Ok(seed) => seed,
                     Err(e) => {
-                        eprintln!("[Playlist] Export failed: {}", e);
+                        self.seed_error = Some(e);
+                        return;
                     }
+                };
+                self.seed_error = None;
+                if matches!(op, OrderOp::SmartShuffle) {
+                    ordering::smart_shuffle_entries(&mut entries, &self.tracks, seed);
+                } else {
+                    ordering::shuffle_entries(&mut entries, seed);
                 }
+                format!("shuffle '{}' (seed {})", playlist.name, seed)
             }
+        };
+        self.set_playlist_entries(playlist_idx, entries, label);
+    }
+
+    /// Append tracks to a playlist, honouring the current add options
+    fn add_tracks_to_playlist(&mut self, playlist_idx: usize, mut indices: Vec<usize>) {
+        let options = self.add_options;
+        if options.keep_disc_order {
+            let keys = [SortKey::Artist, SortKey::Album, SortKey::Disc, SortKey::Track];
+            ordering::sort_entries(&mut indices, &self.tracks, &keys);
+        }
+        let Some(playlist) = self.playlists.get(playlist_idx) else {
+            return;
+        };
+        let mut entries = playlist.track_indices.clone();
+        let mut added = 0;
+        for idx in indices {
+            if options.skip_duplicates && entries.contains(&idx) {
+                continue;
+            }
+            entries.push(idx);
+            added += 1;
+        }
+        eprintln!("[Playlist] Added {} track(s) to '{}'", added, playlist.name);
+        let label = format!("add {} track(s) to '{}'", added, playlist.name);
+        self.set_playlist_entries(playlist_idx, entries, label);
+    }
+    
+    /// Check the entries an export would write against the device holding the current MUSIC folder
+    fn verify_playlist(&mut self, playlist_idx: usize) {
+        let Some(playlist) = self.playlists.get(playlist_idx) else {
+            return;
+        };
+        let music_dir = std::path::Path::new(&self.music_dir);
+        let device_root = music_dir.parent().unwrap_or(music_dir);
+        let entries: Vec<String> = playlist.track_indices.iter()
+            .filter_map(|idx| self.tracks.get(*idx))
+            .map(|track| crate::playlist::to_psp_path(&track.path))
+            .collect();
+        let report = verify::verify_entries(device_root, music_dir, &entries);
+        eprintln!("[Verify] '{}': {} of {} entries found on {}", playlist.name, report.ok_count(), entries.len(), device_root.display());
+        self.verify_result = Some((playlist.name.clone(), report));
+    }
+
+    /// Pick a playlist on the device and look for moved files behind its broken entries
+    fn start_relink(&mut self) {
+        let music_dir = std::path::Path::new(&self.music_dir);
+        let Some(path) = rfd::FileDialog::new()
+            .set_directory(music_dir)
+            .add_filter("M3U8 Playlist", &["m3u8", "m3u"])
+            .pick_file()
+        else {
+            return;
+        };
+        let device_root = music_dir.parent().unwrap_or(music_dir);
+        match relink::relink_playlist(device_root, &path, &self.tracks) {
+            Ok(report) => {
+                eprintln!("[Relink] {:?}: {} proposals, {} unmatched", path, report.proposals.len(), report.unmatched.len());
+                let accepted = report.proposals.iter().map(|p| p.confidence >= relink::DEFAULT_MIN_CONFIDENCE).collect();
+                self.relink_review = Some(RelinkReview { path, report, accepted, outcome: None });
+            }
+            Err(e) => eprintln!("[Relink] Failed to read {:?}: {}", path, e),
+        }
+    }
+
+    fn converter(&self) -> &Arc<Transcoder> {
+        self.converter.get_or_init(|| Arc::new(Transcoder::new(transcode::default_encoder(transcode::DEFAULT_BITRATE_KBPS), "transcode_cache")))
+    }
+
+    /// Playlists and albums the capacity planner can fit onto the device
+    fn capacity_choices(&self) -> Vec<(String, Vec<usize>)> {
+        let mut choices: Vec<(String, Vec<usize>)> = self.playlists.iter()
+            .map(|p| (format!("Playlist: {}", p.name), p.track_indices.clone()))
+            .collect();
+        let mut albums: BTreeMap<(&str, &str), Vec<usize>> = BTreeMap::new();
+        for (idx, meta) in self.metadata_cache.iter().enumerate() {
+            if !meta.album.is_empty() {
+                albums.entry((meta.artist.as_str(), meta.album.as_str())).or_default().push(idx);
+            }
+        }
+        choices.extend(albums.into_iter().map(|((artist, album), tracks)| (format!("Album: {} — {}", artist, album), tracks)));
+        choices
+    }
+
+    /// Fit the ticked choices onto the device holding the current MUSIC folder (else the first one found),
+    /// treating tracks the PSP can't play the way export would
+    fn capacity_plan(&self, choices: &[(String, Vec<usize>)]) -> Option<(String, CapacityPlan)> {
+        let device = self.devices.iter()
+            .find(|d| d.music_dir.display().to_string() == self.music_dir)
+            .or_else(|| self.devices.first())?;
+        let free = device.free_bytes?;
+        let selections: Vec<capacity::Selection> = self.capacity_picks.iter()
+            .filter_map(|label| choices.iter().find(|(l, _)| l == label))
+            .map(|(label, tracks)| {
+                let tracks = tracks.iter()
+                    .copied()
+                    .filter(|i| self.export_incompatible != IncompatibleExport::Skip || self.tracks.get(*i).is_some_and(compat::is_compatible))
+                    .collect();
+                capacity::Selection { label: label.clone(), tracks }
+            })
+            .collect();
+        let encoder = (self.export_incompatible == IncompatibleExport::Convert).then(|| self.converter().encoder());
+        let plan = capacity::plan(&selections, free, device.total_bytes, |i| {
+            self.tracks.get(i)
+                .map(|track| capacity::estimate_cost(track, Some(&device.root), encoder))
+                .unwrap_or_default()
+        });
+        Some((device.label.clone(), plan))
+    }
+
+    /// The track and the other tracks of its album
+    fn album_tracks(&self, idx: usize) -> Vec<usize> {
+        let Some(meta) = self.metadata_cache.get(idx) else {
+            return Vec::new();
+        };
+        let others = (0..self.tracks.len())
+            .filter(|i| *i != idx && self.metadata_cache.get(*i).is_some_and(|m| m.artist == meta.artist && m.album == meta.album));
+        std::iter::once(idx).chain(others).collect()
+    }
+
+    /// Write the cover at `url` (else the first one embedded in the files) into a track or its
+    /// whole album on a worker thread; the outcome comes back as `art_embed_status`
+    fn embed_art(&mut self, ctx: &Context, idx: usize, whole_album: bool, url: Option<String>) {
+        if idx >= self.tracks.len() || self.embedding {
+            return;
+        }
+        let targets: Vec<usize> = if whole_album { self.album_tracks(idx) } else { vec![idx] };
+        let paths: Vec<std::path::PathBuf> = targets.iter().map(|i| std::path::PathBuf::from(&self.tracks[*i].path)).collect();
+        let backup_dir = self.embed_backup.then(|| std::path::PathBuf::from("art_backups"));
+        let sender = self.embed_sender.clone();
+        let ctx = ctx.clone();
+        self.embedding = true;
+        self.art_embed_status = Some(format!("Embedding cover into {} tracks...", paths.len()));
+        std::thread::spawn(move || {
+            let _ = sender.send(embed_cover_into(&paths, url, backup_dir.as_deref()));
+            ctx.request_repaint();
+        });
+    }
+
+    /// Ask where to save a playlist, then convert (if asked to) and write it on a worker thread
+    fn export_playlist(&mut self, ctx: &Context, playlist_idx: usize) {
+        let Some(playlist) = self.playlists.get(playlist_idx) else {
+            return;
+        };
+        // The tracks to write, each marked when it has to be converted first
+        let mut export_tracks: Vec<(Track, bool)> = Vec::new();
+        for track in playlist.track_indices.iter().filter_map(|idx| self.tracks.get(*idx)) {
+            let issues = compat::check(track);
+            if issues.is_empty() {
+                export_tracks.push((track.clone(), false));
+                continue;
+            }
+            eprintln!("[Playlist] Won't play on the PSP: {} ({})", track.path, compat::describe(&issues));
+            match self.export_incompatible {
+                IncompatibleExport::Skip => {}
+                IncompatibleExport::Convert => export_tracks.push((track.clone(), true)),
+                IncompatibleExport::Keep => export_tracks.push((track.clone(), false)),
+            }
+        }
+        if export_tracks.is_empty() {
+            eprintln!("[Playlist] Cannot export empty playlist");
+            return;
         }
+
+        // Use file dialog to choose save location
+        let default_name = format!("{}.m3u8", playlist.name);
+        let Some(path) = rfd::FileDialog::new()
+            .set_file_name(&default_name)
+            .add_filter("M3U8 Playlist", &["m3u8"])
+            .save_file()
+        else {
+            return;
+        };
+
+        let transcoder = export_tracks.iter().any(|(_, convert)| *convert).then(|| self.converter().clone());
+        let sender = self.export_sender.clone();
+        let ctx = ctx.clone();
+        self.exporting = true;
+        self.export_status = None;
+        std::thread::spawn(move || {
+            let total = export_tracks.iter().filter(|(_, convert)| *convert).count();
+            let mut done = 0;
+            let mut failed = 0;
+            let mut playlist_tracks = Vec::with_capacity(export_tracks.len());
+            for (mut track, convert) in export_tracks {
+                if let Some(transcoder) = transcoder.as_ref().filter(|_| convert) {
+                    let _ = sender.send(ExportUpdate::Converting { done, total, track: track.title.clone() });
+                    ctx.request_repaint();
+                    match transcoder.transcode(std::path::Path::new(&track.path)) {
+                        // Outside a MUSIC folder, the entry points at MUSIC/<file name>
+                        Ok(converted) => track.path = converted.display().to_string(),
+                        Err(e) => {
+                            eprintln!("[Playlist] Failed to convert {}: {}", track.path, e);
+                            failed += 1;
+                        }
+                    }
+                    done += 1;
+                }
+                playlist_tracks.push(track);
+            }
+            let refs: Vec<&Track> = playlist_tracks.iter().collect();
+            let status = match crate::playlist::write_m3u8(&path.to_string_lossy(), &refs) {
+                Ok(_) => {
+                    eprintln!("[Playlist] Exported {} tracks to: {:?}", refs.len(), path);
+                    let mut status = format!("Exported {} tracks to {}.", refs.len(), path.display());
+                    if done > failed {
+                        status.push_str(&format!(" {} converted copies are in transcode_cache/; copy them into MUSIC on the Memory Stick.", done - failed));
+                    }
+                    if failed > 0 {
+                        status.push_str(&format!(" {} couldn't be converted and were kept as they are.", failed));
+                    }
+                    status
+                }
+                Err(e) => {
+                    eprintln!("[Playlist] Export failed: {}", e);
+                    format!("Export failed: {}", e)
+                }
+            };
+            let _ = sender.send(ExportUpdate::Finished(status));
+            ctx.request_repaint();
+        });
     }
 }
 
 impl eframe::App for TrackViewerApp {
         fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+            // Undo/redo shortcuts, unless a text field has focus (it has its own undo)
+            if !ctx.wants_keyboard_input() {
+                let (undo, redo) = ctx.input_mut(|i| {
+                    // Most specific shortcut first: Cmd+Shift+Z also matches Cmd+Z
+                    let redo = i.consume_shortcut(&egui::KeyboardShortcut::new(egui::Modifiers::COMMAND | egui::Modifiers::SHIFT, egui::Key::Z))
+                        || i.consume_shortcut(&egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Y));
+                    let undo = i.consume_shortcut(&egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z));
+                    (undo, redo)
+                });
+                if undo {
+                    self.undo();
+                }
+                if redo {
+                    self.redo();
+                }
+            }
+            
             // Check for album art results from background threads
-            while let Ok((key, url)) = self.art_receiver.try_recv() {
-                self.album_art_cache.insert(key.clone(), url.clone());
-                self.fetching_art_for.remove(&key);
-                if let Some(ref url_str) = url {
-                    eprintln!("[GUI] Received album art: {}", url_str);
+            while let Ok((key, result)) = self.art_receiver.try_recv() {
+                let found = match result {
+                    Ok(found) => found,
+                    Err(failures) => {
+                        self.art_failures.insert(key.clone(), failures);
+                        None
+                    }
+                };
+                if let Some(found) = &found {
+                    eprintln!("[GUI] Received album art from {}: {}", found.provider, found.url);
                 }
+                self.album_art_cache.insert(key.clone(), found);
+                self.fetching_art_for.remove(&key);
                 ctx.request_repaint(); // Trigger UI update
             }
+            while let Ok(status) = self.embed_receiver.try_recv() {
+                self.embedding = false;
+                self.art_embed_status = Some(status);
+            }
+            while let Ok(update) = self.export_receiver.try_recv() {
+                self.export_status = Some(match update {
+                    ExportUpdate::Converting { done, total, track } => format!("Converting {} of {}: {}", done + 1, total, track),
+                    ExportUpdate::Finished(status) => {
+                        self.exporting = false;
+                        status
+                    }
+                });
+            }
+            while let Ok((key, gathered)) = self.picker_receiver.try_recv() {
+                let Some(picker) = self.art_picker.as_mut().filter(|p| p.key == key) else {
+                    continue;
+                };
+                let choices = gathered.into_iter()
+                    .map(|(choice, image)| {
+                        let texture = image.map(|image| {
+                            self.image_cache.entry(choice.art.url.clone())
+                                .or_insert_with(|| ctx.load_texture(&choice.art.url, image, Default::default()))
+                                .clone()
+                        });
+                        (choice, texture)
+                    })
+                    .collect();
+                picker.choices = Some(choices);
+                ctx.request_repaint();
+            }
             
             egui::CentralPanel::default().show(ctx, |ui| {
                 ui.heading("Indexed Tracks");
@@ -215,8 +1067,36 @@ impl eframe::App for TrackViewerApp {
                     if ui.button("Choose MUSIC folder...").clicked() {
                         if let Some(dir) = rfd::FileDialog::new().set_directory(&self.music_dir).pick_folder() {
                             self.music_dir = dir.display().to_string();
+                            self.capacity_view = None;
                         }
                     }
+                    let device_label = |d: &PspDevice| {
+                        let space = |bytes: Option<u64>| bytes.map(generator::format_size).unwrap_or_else(|| "?".to_string());
+                        format!("🎮 {} ({} free of {})", d.label, space(d.free_bytes), space(d.total_bytes))
+                    };
+                    let current_device = self.devices.iter()
+                        .find(|d| d.music_dir.display().to_string() == self.music_dir)
+                        .map(device_label)
+                        .unwrap_or_else(|| "PSP devices".to_string());
+                    egui::ComboBox::from_id_source("device_picker")
+                        .selected_text(current_device)
+                        .show_ui(ui, |ui| {
+                            if self.devices.is_empty() {
+                                ui.colored_label(egui::Color32::GRAY, "No PSP detected");
+                            }
+                            for d in &self.devices {
+                                let music_dir = d.music_dir.display().to_string();
+                                let selected = music_dir == self.music_dir;
+                                if ui.selectable_label(selected, device_label(d)).clicked() {
+                                    self.music_dir = music_dir;
+                                    self.capacity_view = None;
+                                }
+                            }
+                        });
+                    if ui.small_button("🔄").on_hover_text("Look for connected PSPs again").clicked() {
+                        self.devices = device::detect_devices();
+                        self.capacity_view = None;
+                    }
                     ui.label(format!("Current folder: {}", self.music_dir));
                     
                     ui.separator();
@@ -226,6 +1106,36 @@ impl eframe::App for TrackViewerApp {
                         self.show_playlist_manager = !self.show_playlist_manager;
                     }
                     ui.label(format!("({} playlists)", self.playlists.len()));
+                    
+                    ui.separator();
+                    
+                    let undo_hint = self.history.undo.last().map(|c| format!("Undo {} (Ctrl+Z)", c.describe()));
+                    if ui.add_enabled(undo_hint.is_some(), egui::Button::new("↶ Undo"))
+                        .on_hover_text(undo_hint.unwrap_or_default())
+                        .clicked()
+                    {
+                        self.undo();
+                    }
+                    let redo_hint = self.history.redo.last().map(|c| format!("Redo {} (Ctrl+Shift+Z)", c.describe()));
+                    if ui.add_enabled(redo_hint.is_some(), egui::Button::new("↷ Redo"))
+                        .on_hover_text(redo_hint.unwrap_or_default())
+                        .clicked()
+                    {
+                        self.redo();
+                    }
+                    
+                    ui.separator();
+                    
+                    let mut offline = http::is_offline();
+                    if ui.toggle_value(&mut offline, "✈ Offline")
+                        .on_hover_text("Don't go online: show only covers in the files, their folders or saved earlier")
+                        .changed()
+                    {
+                        self.set_offline(offline);
+                    }
+                    if offline {
+                        ui.colored_label(egui::Color32::YELLOW, "Offline mode");
+                    }
                 });
                 ui.horizontal(|ui| {
                     ui.colored_label(egui::Color32::YELLOW, "To scan or rescan your PSP music library, please use the CLI: psp_playlist_maker scan <music_dir>");
@@ -258,10 +1168,21 @@ impl eframe::App for TrackViewerApp {
                                         for (i, artist) in artists.iter().enumerate() {
                                             let track_count = self.count_tracks_for_artist(artist);
                                             let label = format!("{} ({})", artist, track_count);
-                                            if ui.selectable_label(self.selected_artist_idx == Some(i), label).clicked() {
+                                            let response = ui.selectable_label(self.selected_artist_idx == Some(i), label);
+                                            if response.clicked() {
                                                 self.selected_artist_idx = Some(i);
                                                 self.selected_album_idx = None;
                                                 self.selected_track = None;
+                                                self.selected_tracks.clear();
+                                            }
+                                            let mut target = None;
+                                            response.context_menu(|ui| {
+                                                ui.label(format!("Add all by {} to:", artist));
+                                                target = playlist_target_menu(ui, &self.playlists, &mut self.add_options);
+                                            });
+                                            if let Some(pl_idx) = target {
+                                                let indices = self.track_indices_for_artist(artist);
+                                                self.add_tracks_to_playlist(pl_idx, indices);
                                             }
                                         }
                                     });
@@ -297,9 +1218,20 @@ impl eframe::App for TrackViewerApp {
                                         for (i, album) in albums.iter().enumerate() {
                                             let track_count = self.count_tracks_for_album(selected_artist, album);
                                             let label = format!("{} ({})", album, track_count);
-                                            if ui.selectable_label(self.selected_album_idx == Some(i), label).clicked() {
+                                            let response = ui.selectable_label(self.selected_album_idx == Some(i), label);
+                                            if response.clicked() {
                                                 self.selected_album_idx = Some(i);
                                                 self.selected_track = None;
+                                                self.selected_tracks.clear();
+                                            }
+                                            let mut target = None;
+                                            response.context_menu(|ui| {
+                                                ui.label(format!("Add album {} to:", album));
+                                                target = playlist_target_menu(ui, &self.playlists, &mut self.add_options);
+                                            });
+                                            if let Some(pl_idx) = target {
+                                                let indices = self.track_indices_for_album(selected_artist, album);
+                                                self.add_tracks_to_playlist(pl_idx, indices);
                                             }
                                         }
                                     });
@@ -342,13 +1274,17 @@ impl eframe::App for TrackViewerApp {
                                     ui.heading(title);
                                     ui.add_space(4.0);
                                     
+                                    // Row order, for shift-click range selection
+                                    let row_order: Vec<usize> = filtered_tracks.iter().map(|(idx, _, _)| *idx).collect();
+                                    let mut pending_add: Option<(usize, Vec<usize>)> = None;
+                                    
                                     let scroll_height = ui.available_height();
                                     egui::ScrollArea::vertical()
                                         .id_source("songs_scroll")
                                         .auto_shrink([false, false])
                                         .max_height(scroll_height)
                                         .show(ui, |ui| {
-                                            for (orig_idx, _track, meta) in filtered_tracks {
+                                            for (orig_idx, track, meta) in filtered_tracks {
                                                 ui.horizontal(|ui| {
                                                     let display = if !meta.title.is_empty() {
                                                         meta.title.clone()
@@ -356,25 +1292,68 @@ impl eframe::App for TrackViewerApp {
                                                         "(Untitled)".to_string()
                                                     };
                                                     
-                                                    if ui.selectable_label(self.selected_track == Some(orig_idx), display).clicked() {
+                                                    let response = ui.selectable_label(self.selected_tracks.contains(&orig_idx), display);
+                                                    let issues = compat::check(track);
+                                                    if !issues.is_empty() {
+                                                        ui.colored_label(egui::Color32::YELLOW, "⚠")
+                                                            .on_hover_text(format!("Won't play on the PSP: {}", compat::describe(&issues)));
+                                                    }
+                                                    if response.clicked() {
+                                                        let modifiers = ui.input(|i| i.modifiers);
+                                                        let anchor_pos = self.selection_anchor
+                                                            .and_then(|a| row_order.iter().position(|idx| *idx == a));
+                                                        let clicked_pos = row_order.iter().position(|idx| *idx == orig_idx);
+                                                        match (modifiers.shift, anchor_pos, clicked_pos) {
+                                                            (true, Some(a), Some(c)) => {
+                                                                if !modifiers.command {
+                                                                    self.selected_tracks.clear();
+                                                                }
+                                                                self.selected_tracks.extend(&row_order[a.min(c)..=a.max(c)]);
+                                                            }
+                                                            _ if modifiers.command => {
+                                                                if !self.selected_tracks.remove(&orig_idx) {
+                                                                    self.selected_tracks.insert(orig_idx);
+                                                                }
+                                                                self.selection_anchor = Some(orig_idx);
+                                                            }
+                                                            _ => {
+                                                                self.selected_tracks.clear();
+                                                                self.selected_tracks.insert(orig_idx);
+                                                                self.selection_anchor = Some(orig_idx);
+                                                            }
+                                                        }
                                                         self.selected_track = Some(orig_idx);
                                                     }
                                                     
-                                                    // Add to playlist button
-                                                    if !self.playlists.is_empty() && ui.small_button("➕").clicked() {
-                                                        // Add to selected playlist or show menu
+                                                    // Act on the whole selection when the row is part of it
+                                                    let targets: Vec<usize> = if self.selected_tracks.contains(&orig_idx) {
+                                                        self.selected_tracks.iter().copied().collect()
+                                                    } else {
+                                                        vec![orig_idx]
+                                                    };
+                                                    response.context_menu(|ui| {
+                                                        ui.label(format!("Add {} track(s) to:", targets.len()));
+                                                        if let Some(pl_idx) = playlist_target_menu(ui, &self.playlists, &mut self.add_options) {
+                                                            pending_add = Some((pl_idx, targets.clone()));
+                                                        }
+                                                    });
+                                                    
+                                                    // Add to the playlist selected in the manager
+                                                    if !self.playlists.is_empty() && ui.small_button("➕")
+                                                        .on_hover_text("Add to the selected playlist (right-click a song for more)")
+                                                        .clicked()
+                                                    {
                                                         if let Some(pl_idx) = self.selected_playlist_idx {
-                                                            if let Some(playlist) = self.playlists.get_mut(pl_idx) {
-                                                                if !playlist.track_indices.contains(&orig_idx) {
-                                                                    playlist.track_indices.push(orig_idx);
-                                                                    eprintln!("[Playlist] Added track to '{}'", playlist.name);
-                                                                }
-                                                            }
+                                                            pending_add = Some((pl_idx, targets));
                                                         }
                                                     }
                                                 });
                                             }
                                         });
+                                    
+                                    if let Some((pl_idx, indices)) = pending_add {
+                                        self.add_tracks_to_playlist(pl_idx, indices);
+                                    }
                                 } else {
                                     // Show hint when no album is selected
                                     ui.heading("🎵 Songs");
@@ -392,6 +1371,7 @@ impl eframe::App for TrackViewerApp {
                         // Only fetch album art if we switched to a different track
                         if self.last_details_track != Some(idx) {
                             self.last_details_track = Some(idx);
+                            self.art_embed_status = None;
                             
                             if let Some(meta_ref) = &meta {
                                 if !meta_ref.artist.is_empty() && !meta_ref.album.is_empty() {
@@ -408,14 +1388,13 @@ impl eframe::App for TrackViewerApp {
                                         let sender = self.art_sender.clone();
                                         let artist = meta_ref.artist.clone();
                                         let album = meta_ref.album.clone();
+                                        // Art in the files or their folders is shown without going online
+                                        let paths: Vec<std::path::PathBuf> = self.album_tracks(idx).iter()
+                                            .map(|i| std::path::PathBuf::from(&self.tracks[*i].path))
+                                            .collect();
                                         
                                         std::thread::spawn(move || {
-                                            let rt = tokio::runtime::Builder::new_current_thread()
-                                                .enable_all()
-                                                .build()
-                                                .unwrap();
-                                            
-                                            let result = rt.block_on(crate::album_art::fetch_album_art(&artist, &album));
+                                            let result = art_cache::fetch_album_art_cached("music_index.db", &artist, &album, &paths);
                                             let _ = sender.send((key, result));
                                         });
                                         
@@ -432,20 +1411,70 @@ impl eframe::App for TrackViewerApp {
                             self.fetching_art_for.contains_key(&key)
                         }).unwrap_or(false);
                         
-                        // Get the album art URL from cache
-                        let album_art_url = meta.as_ref().and_then(|m| {
+                        // Get the album art URL and its provider from cache
+                        let album_art = meta.as_ref().and_then(|m| {
                             let key = (m.artist.clone(), m.album.clone());
                             self.album_art_cache.get(&key).and_then(|opt| opt.clone())
                         });
+                        let art_failures = meta.as_ref().and_then(|m| self.art_failures.get(&(m.artist.clone(), m.album.clone())).cloned());
                         
+                        let mut tag_edit = None;
+                        let mut embed_request = None;
+                        let mut refresh_art = false;
+                        let mut open_picker = false;
+                        let mut unpin = false;
+                        let pinned = album_art.as_ref().is_some_and(|found| found.pinned);
+                        let cover_url = album_art.as_ref().map(|found| found.url.clone());
                         egui::Window::new("Track Details").open(&mut true).show(ctx, |ui| {
                             if let Some(meta) = meta {
                                 ui.horizontal(|ui| {
                                     ui.vertical(|ui| {
                                         ui.heading("Track Details");
-                                        ui.label(format!("Title: {}", meta.title));
-                                        ui.label(format!("Artist: {}", meta.artist));
-                                        ui.label(format!("Album: {}", meta.album));
+                                        match &mut self.tag_draft {
+                                            Some((draft_idx, draft)) if *draft_idx == idx => {
+                                                egui::Grid::new("tag_edit_grid").num_columns(2).show(ui, |ui| {
+                                                    ui.label("Title:");
+                                                    ui.text_edit_singleline(&mut draft.title);
+                                                    ui.end_row();
+                                                    ui.label("Artist:");
+                                                    ui.text_edit_singleline(&mut draft.artist);
+                                                    ui.end_row();
+                                                    ui.label("Album:");
+                                                    ui.text_edit_singleline(&mut draft.album);
+                                                    ui.end_row();
+                                                });
+                                                ui.horizontal(|ui| {
+                                                    if ui.button("✔ Apply").clicked() {
+                                                        tag_edit = Some(Some(draft.clone()));
+                                                    }
+                                                    if ui.button("Cancel").clicked() {
+                                                        tag_edit = Some(None);
+                                                    }
+                                                });
+                                            }
+                                            _ => {
+                                                ui.label(format!("Title: {}", meta.title));
+                                                ui.label(format!("Artist: {}", meta.artist));
+                                                ui.label(format!("Album: {}", meta.album));
+                                                if let Some(track) = self.tracks.get(idx) {
+                                                    let mut format = vec![if track.codec.is_empty() { "unknown codec".to_string() } else { track.codec.to_uppercase() }];
+                                                    format.extend(track.sample_rate.map(|r| format!("{} Hz", r)));
+                                                    format.extend(track.bitrate_kbps.map(|k| format!("{} kbps", k)));
+                                                    format.extend(track.channels.map(|c| format!("{} ch", c)));
+                                                    ui.label(format!("Format: {}", format.join(", ")));
+                                                    let issues = compat::check(track);
+                                                    if !issues.is_empty() {
+                                                        ui.colored_label(egui::Color32::YELLOW, format!("⚠ Won't play on the PSP: {}", compat::describe(&issues)));
+                                                    }
+                                                }
+                                                if ui.button("✏️ Edit tags").clicked() {
+                                                    self.tag_draft = Some((idx, meta.clone()));
+                                                }
+                                                if let Some(status) = &self.tag_status {
+                                                    ui.colored_label(egui::Color32::YELLOW, status);
+                                                }
+                                            }
+                                        }
                                     });
                                     
                                     // Display album art if available
@@ -454,8 +1483,8 @@ impl eframe::App for TrackViewerApp {
                                             ui.spinner();
                                             ui.label("Searching for album art...");
                                         });
-                                    } else if let Some(url) = album_art_url {
-                                        if let Some(tex) = self.get_or_load_image(ctx, &url) {
+                                    } else if let Some(found) = album_art {
+                                        if let Some(tex) = self.get_or_load_image(ctx, &found.url) {
                                             let available = ui.available_size();
                                             let max_dim = 256.0_f32.min(available.x.min(available.y));
                                             let size = tex.size_vec2();
@@ -466,21 +1495,154 @@ impl eframe::App for TrackViewerApp {
                                                 (max_dim * aspect, max_dim)
                                             };
                                             ui.image((tex.id(), egui::vec2(w, h)));
+                                            let pinned = if found.pinned { " (pinned)" } else { "" };
+                                            ui.small(format!("Cover from {}{}", cover_source(&found.provider), pinned));
+                                        } else if http::is_offline() {
+                                            ui.label("This cover wasn't saved, so it can't be shown offline.");
                                         } else {
                                             ui.label("Loading image...");
                                         }
+                                    } else if let Some(failures) = &art_failures {
+                                        ui.colored_label(egui::Color32::YELLOW, art_failure_text(failures))
+                                            .on_hover_text(album_art::describe_failures(failures));
                                     } else {
                                         ui.label("No album art found.");
                                     }
                                 });
+                                if !is_fetching && !meta.artist.is_empty() && !meta.album.is_empty() {
+                                    ui.horizontal(|ui| {
+                                        if ui.button("🔄 Refresh this album's art")
+                                            .on_hover_text("Ask the art providers again instead of using the saved cover")
+                                            .clicked()
+                                        {
+                                            refresh_art = true;
+                                        }
+                                        if ui.button("🖼 Choose cover...").clicked() {
+                                            open_picker = true;
+                                        }
+                                        if pinned && ui.button("📌 Unpin").on_hover_text("Go back to the cover found automatically").clicked() {
+                                            unpin = true;
+                                        }
+                                    });
+                                }
+                                // Write the shown cover (else the file's own art) into the files as a PSP-ready JPEG
+                                ui.horizontal(|ui| {
+                                    if ui.add_enabled(!self.embedding, egui::Button::new("🖼 Embed cover in track")).clicked() {
+                                        embed_request = Some(false);
+                                    }
+                                    if ui.add_enabled(!self.embedding, egui::Button::new("🖼 Embed cover in album")).clicked() {
+                                        embed_request = Some(true);
+                                    }
+                                    ui.checkbox(&mut self.embed_backup, "Back up files first")
+                                        .on_hover_text("Copies each file into art_backups/ before changing it");
+                                });
+                                if let Some(status) = &self.art_embed_status {
+                                    ui.label(status);
+                                }
                             }
                         });
+                        
+                        if let Some(whole_album) = embed_request {
+                            self.embed_art(ctx, idx, whole_album, cover_url);
+                        }
+                        if let Some(meta) = self.metadata_cache.get(idx).cloned() {
+                            if refresh_art {
+                                self.refresh_album_art(&meta.artist, &meta.album);
+                            }
+                            if unpin {
+                                self.unpin_album_art(&meta.artist, &meta.album);
+                            }
+                        }
+                        if open_picker {
+                            self.open_art_picker(idx);
+                        }
+                        
+                        // Apply or discard the tag draft outside the window closure
+                        if let Some(edit) = tag_edit {
+                            self.tag_draft = None;
+                            if let (Some(after), Some(before)) = (edit, self.metadata_cache.get(idx).cloned()) {
+                                if after != before {
+                                    self.execute(EditCommand::EditTags { track: idx, before, after });
+                                }
+                            }
+                        }
                     }
-                    ui.horizontal(|ui| {
-                        if ui.button("Clear Image Cache").clicked() {
-                            self.clear_image_cache(ctx);
+                    // Cover picker: local art and the best few covers of each provider, with where they came from
+                    if let Some(picker) = &self.art_picker {
+                        let mut open = true;
+                        let mut picked = None;
+                        let mut choose_file = false;
+                        egui::Window::new(format!("Choose Cover: {} - {}", picker.key.0, picker.key.1))
+                            .open(&mut open)
+                            .show(ctx, |ui| {
+                                match &picker.choices {
+                                    None => {
+                                        ui.horizontal(|ui| {
+                                            ui.spinner();
+                                            ui.label("Gathering covers from the files and art providers...");
+                                        });
+                                    }
+                                    Some(choices) if choices.is_empty() => {
+                                        ui.label("No covers found.");
+                                    }
+                                    Some(choices) => {
+                                        egui::ScrollArea::vertical().max_height(480.0).show(ui, |ui| {
+                                            ui.horizontal_wrapped(|ui| {
+                                                for (choice, texture) in choices {
+                                                    ui.group(|ui| {
+                                                        ui.vertical(|ui| {
+                                                            ui.set_width(160.0);
+                                                            match texture {
+                                                                Some(tex) => {
+                                                                    let size = tex.size_vec2();
+                                                                    let scale = 150.0 / size.x.max(size.y);
+                                                                    ui.image((tex.id(), size * scale));
+                                                                    let [w, h] = tex.size();
+                                                                    ui.label(format!("{}×{}", w, h));
+                                                                }
+                                                                None => {
+                                                                    ui.colored_label(egui::Color32::GRAY, "Image didn't load");
+                                                                }
+                                                            }
+                                                            ui.small(format!("From {}", cover_source(&choice.art.provider)));
+                                                            if !choice.release.is_empty() {
+                                                                ui.small(&choice.release);
+                                                            }
+                                                            if let Some(score) = choice.score {
+                                                                ui.small(format!("Match {:.0}%", score * 100.0));
+                                                            }
+                                                            if ui.add_enabled(texture.is_some(), egui::Button::new("📌 Use this")).clicked() {
+                                                                picked = Some(choice.art.clone());
+                                                            }
+                                                        });
+                                                    });
+                                                }
+                                            });
+                                        });
+                                    }
+                                }
+                                ui.separator();
+                                if ui.button("📁 Choose image file...").clicked() {
+                                    choose_file = true;
+                                }
+                            });
+                        let key = picker.key.clone();
+                        if !open {
+                            self.art_picker = None;
                         }
-                    });
+                        if choose_file {
+                            if let Some(path) = rfd::FileDialog::new()
+                                .add_filter("Images", &["jpg", "jpeg", "png", "bmp", "gif", "webp"])
+                                .pick_file()
+                            {
+                                let source = art_embed::CoverSource::File(path);
+                                picked = Some(ArtResult { url: source.url(), provider: "file".to_string(), pinned: true });
+                            }
+                        }
+                        if let Some(art) = picked {
+                            self.pin_album_art(key, art);
+                        }
+                    }
                     // Debug window: show raw music_index contents and highlight hovered track
                     if self.debug {
                         egui::Window::new("music_index.db contents").show(ctx, |ui| {
@@ -514,13 +1676,125 @@ impl eframe::App for TrackViewerApp {
                                     ui.label("New playlist:");
                                     ui.text_edit_singleline(&mut self.new_playlist_name);
                                     if ui.button("➕ Create").clicked() && !self.new_playlist_name.trim().is_empty() {
-                                        self.playlists.push(Playlist {
+                                        let playlist = Playlist {
                                             name: self.new_playlist_name.trim().to_string(),
                                             track_indices: Vec::new(),
-                                        });
+                                        };
+                                        self.execute(EditCommand::CreatePlaylist { index: self.playlists.len(), playlist });
                                         self.new_playlist_name.clear();
                                     }
                                 });
+                                ui.horizontal(|ui| {
+                                    if ui.button("🩹 Repair playlist on PSP...")
+                                        .on_hover_text("Find moved files for entries that no longer exist")
+                                        .clicked()
+                                    {
+                                        self.start_relink();
+                                    }
+                                    ui.label("Tracks the PSP can't play:");
+                                    egui::ComboBox::from_id_source("export_incompatible")
+                                        .selected_text(self.export_incompatible.label())
+                                        .show_ui(ui, |ui| {
+                                            for option in [IncompatibleExport::Keep, IncompatibleExport::Skip, IncompatibleExport::Convert] {
+                                                if ui.selectable_value(&mut self.export_incompatible, option, option.label()).changed() {
+                                                    self.capacity_view = None;
+                                                }
+                                            }
+                                        })
+                                        .response
+                                        .on_hover_text("Convert writes playable copies into transcode_cache/, using ffmpeg or lame when installed; copy them into MUSIC on the Memory Stick");
+                                });
+                                if let Some(status) = &self.export_status {
+                                    ui.label(status);
+                                }
+                                
+                                // What fits on the device, as a gauge
+                                ui.collapsing("💾 Capacity", |ui| {
+                                    if self.capacity_view.is_none() {
+                                        let choices = self.capacity_choices();
+                                        let plan = self.capacity_plan(&choices);
+                                        self.capacity_view = Some(CapacityView { choices, plan });
+                                    }
+                                    let Some(CapacityView { choices, plan: Some((device, plan)) }) = &self.capacity_view else {
+                                        ui.colored_label(egui::Color32::GRAY, "No PSP with known free space. Connect one and refresh the device list.");
+                                        return;
+                                    };
+                                    let size = generator::format_size;
+                                    // Share of the stick in use once everything that fits is copied
+                                    let fraction = match plan.total_bytes.filter(|t| *t > 0) {
+                                        Some(total) => 1.0 - plan.remaining() as f32 / total as f32,
+                                        None if plan.free_bytes > 0 => plan.needed() as f32 / plan.free_bytes as f32,
+                                        None => 1.0,
+                                    };
+                                    ui.label(format!("🎮 {}: {} free", device, size(plan.free_bytes)));
+                                    ui.add(egui::ProgressBar::new(fraction)
+                                        .text(format!("{} planned, {} left", size(plan.needed()), size(plan.remaining()))));
+                                    for selection in &plan.selections {
+                                        let converted = if selection.converted > 0 { format!(", {} converted", selection.converted) } else { String::new() };
+                                        if selection.fits {
+                                            ui.colored_label(egui::Color32::GREEN, format!("✔ {} — {}{}", selection.label, size(selection.bytes), converted));
+                                        } else {
+                                            ui.colored_label(egui::Color32::RED, format!("✖ {} — {}, needs {} more", selection.label, size(selection.bytes), size(selection.short_by)));
+                                        }
+                                    }
+                                    ui.label("Fit in this order (tick most wanted first):");
+                                    let mut toggled = None;
+                                    egui::ScrollArea::vertical().id_source("capacity_choices").max_height(150.0).show(ui, |ui| {
+                                        for (label, _) in choices {
+                                            let mut picked = self.capacity_picks.contains(label);
+                                            if ui.checkbox(&mut picked, label).changed() {
+                                                toggled = Some((label.clone(), picked));
+                                            }
+                                        }
+                                    });
+                                    if let Some((label, picked)) = toggled {
+                                        if picked {
+                                            self.capacity_picks.push(label);
+                                        } else {
+                                            self.capacity_picks.retain(|l| *l != label);
+                                        }
+                                        self.capacity_view = None;
+                                    }
+                                });
+                                
+                                // Duration/size targeted generation
+                                ui.collapsing("🎲 Generate playlist", |ui| {
+                                    let sources = self.generator_sources();
+                                    let mut generate = None;
+                                    let form = &mut self.generator_form;
+                                    ui.horizontal(|ui| {
+                                        ui.label("From:");
+                                        egui::ComboBox::from_id_source("generator_source")
+                                            .selected_text(form.source.clone())
+                                            .show_ui(ui, |ui| {
+                                                for (label, _) in &sources {
+                                                    ui.selectable_value(&mut form.source, label.clone(), label);
+                                                }
+                                            });
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.radio_value(&mut form.by_size, false, "Duration");
+                                        ui.radio_value(&mut form.by_size, true, "Size");
+                                        if form.by_size {
+                                            ui.add(egui::DragValue::new(&mut form.megabytes).clamp_range(1..=65536).suffix(" MB"));
+                                        } else {
+                                            ui.add(egui::DragValue::new(&mut form.minutes).clamp_range(1..=1440).suffix(" min"));
+                                        }
+                                        ui.checkbox(&mut form.keep_albums, "Keep albums intact");
+                                    });
+                                    if ui.button("🎲 Generate").on_hover_text("Uses the playlist shuffle seed when set").clicked() {
+                                        generate = sources.into_iter().find(|(label, _)| *label == form.source).map(|(_, source)| source);
+                                        if generate.is_none() {
+                                            form.report = Some("The chosen source is no longer available".to_string());
+                                        }
+                                    }
+                                    if let Some(report) = &form.report {
+                                        ui.label(report);
+                                    }
+                                    if let Some(source) = generate {
+                                        self.generate_playlist(source);
+                                    }
+                                });
                                 
                                 ui.separator();
                                 ui.add_space(5.0);
@@ -530,38 +1804,116 @@ impl eframe::App for TrackViewerApp {
                                     ui.colored_label(egui::Color32::GRAY, "No playlists yet. Create one above!");
                                 } else {
                                     egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
-                                        let mut to_remove = None;
                                         let mut to_export = None;
+                                        let mut to_verify = None;
+                                        let mut to_rename = None;
+                                        let mut entry_edit = None;
+                                        let mut to_order = None;
                                         
                                         for (idx, playlist) in self.playlists.iter().enumerate() {
                                             let is_selected = self.selected_playlist_idx == Some(idx);
                                             
                                             ui.horizontal(|ui| {
-                                                if ui.selectable_label(is_selected, format!("📋 {}", playlist.name)).clicked() {
-                                                    self.selected_playlist_idx = Some(idx);
+                                                match &mut self.renaming {
+                                                    Some((rename_idx, name)) if *rename_idx == idx => {
+                                                        ui.text_edit_singleline(name);
+                                                        if ui.button("✔").clicked() && !name.trim().is_empty() {
+                                                            to_rename = Some((idx, name.trim().to_string()));
+                                                        }
+                                                        if ui.button("✖").clicked() {
+                                                            self.renaming = None;
+                                                        }
+                                                    }
+                                                    _ => {
+                                                        if ui.selectable_label(is_selected, format!("📋 {}", playlist.name)).clicked() {
+                                                            self.selected_playlist_idx = Some(idx);
+                                                        }
+                                                        if ui.small_button("✏️").on_hover_text("Rename").clicked() {
+                                                            self.renaming = Some((idx, playlist.name.clone()));
+                                                        }
+                                                    }
                                                 }
                                                 
                                                 ui.label(format!("({} tracks)", playlist.track_indices.len()));
+                                                let incompatible = playlist.track_indices.iter()
+                                                    .filter(|i| self.tracks.get(**i).is_some_and(|t| !compat::is_compatible(t)))
+                                                    .count();
+                                                if incompatible > 0 {
+                                                    ui.colored_label(egui::Color32::YELLOW, format!("⚠ {}", incompatible))
+                                                        .on_hover_text(format!("{} tracks won't play on the PSP", incompatible));
+                                                }
                                                 
-                                                if ui.button("💾 Export").clicked() {
+                                                if ui.add_enabled(!self.exporting, egui::Button::new("💾 Export")).clicked() {
                                                     to_export = Some(idx);
                                                 }
                                                 
+                                                if ui.button("🔍 Verify")
+                                                    .on_hover_text("Check that every entry exists on the PSP")
+                                                    .clicked()
+                                                {
+                                                    to_verify = Some(idx);
+                                                }
+                                                
                                                 if ui.button("🗑️").clicked() {
-                                                    to_remove = Some(idx);
+                                                    self.confirm_delete = Some(idx);
                                                 }
                                             });
                                             
                                             // Show tracks in selected playlist
                                             if is_selected {
                                                 ui.indent(idx, |ui| {
+                                                    ui.horizontal(|ui| {
+                                                        ui.menu_button("↕ Sort by", |ui| {
+                                                            for (name, keys) in SORT_PRESETS {
+                                                                if ui.button(*name).clicked() {
+                                                                    to_order = Some((idx, OrderOp::Sort(name, keys)));
+                                                                    ui.close_menu();
+                                                                }
+                                                            }
+                                                        });
+                                                        if ui.button("🔀 Shuffle").clicked() {
+                                                            to_order = Some((idx, OrderOp::Shuffle));
+                                                        }
+                                                        if ui.button("🔀 Smart shuffle")
+                                                            .on_hover_text("Avoid the same artist or album back to back")
+                                                            .clicked()
+                                                        {
+                                                            to_order = Some((idx, OrderOp::SmartShuffle));
+                                                        }
+                                                        ui.label("Seed:");
+                                                        ui.add(egui::TextEdit::singleline(&mut self.shuffle_seed)
+                                                            .hint_text("random")
+                                                            .desired_width(90.0))
+                                                            .on_hover_text("Leave empty for a new random order each time; Undo's tooltip shows the seed used");
+                                                    });
+                                                    if let Some(error) = &self.seed_error {
+                                                        ui.colored_label(egui::Color32::LIGHT_RED, error);
+                                                    }
                                                     if playlist.track_indices.is_empty() {
                                                         ui.colored_label(egui::Color32::GRAY, "  (empty playlist)");
                                                     } else {
-                                                        for track_idx in &playlist.track_indices {
+                                                        let entries = &playlist.track_indices;
+                                                        for (pos, track_idx) in entries.iter().enumerate() {
                                                             if let Some(_track) = self.tracks.get(*track_idx) {
                                                                 let meta = &self.metadata_cache[*track_idx];
-                                                                ui.label(format!("  ♪ {} - {}", meta.artist, meta.title));
+                                                                ui.horizontal(|ui| {
+                                                                    if ui.add_enabled(pos > 0, egui::Button::new("⬆").small()).clicked() {
+                                                                        let mut after = entries.clone();
+                                                                        after.swap(pos, pos - 1);
+                                                                        entry_edit = Some((idx, after, format!("move '{}' up", meta.title)));
+                                                                    }
+                                                                    if ui.add_enabled(pos + 1 < entries.len(), egui::Button::new("⬇").small()).clicked() {
+                                                                        let mut after = entries.clone();
+                                                                        after.swap(pos, pos + 1);
+                                                                        entry_edit = Some((idx, after, format!("move '{}' down", meta.title)));
+                                                                    }
+                                                                    if ui.small_button("✖").on_hover_text("Remove from playlist").clicked() {
+                                                                        let mut after = entries.clone();
+                                                                        after.remove(pos);
+                                                                        entry_edit = Some((idx, after, format!("remove '{}' from '{}'", meta.title, playlist.name)));
+                                                                    }
+                                                                    ui.label(format!("♪ {} - {}", meta.artist, meta.title));
+                                                                });
                                                             }
                                                         }
                                                     }
@@ -569,22 +1921,142 @@ impl eframe::App for TrackViewerApp {
                                             }
                                         }
                                         
-                                        // Handle removals
-                                        if let Some(idx) = to_remove {
-                                            self.playlists.remove(idx);
-                                            if self.selected_playlist_idx == Some(idx) {
-                                                self.selected_playlist_idx = None;
+                                        // Handle renames
+                                        if let Some((idx, new)) = to_rename {
+                                            self.renaming = None;
+                                            let old = self.playlists[idx].name.clone();
+                                            if old != new {
+                                                self.execute(EditCommand::RenamePlaylist { index: idx, old, new });
                                             }
                                         }
                                         
+                                        // Handle entry removal/reordering
+                                        if let Some((idx, after, label)) = entry_edit {
+                                            self.set_playlist_entries(idx, after, label);
+                                        }
+                                        if let Some((idx, op)) = to_order {
+                                            self.order_playlist(idx, op);
+                                        }
+                                        
                                         // Handle exports
                                         if let Some(idx) = to_export {
-                                            self.export_playlist(idx);
+                                            self.export_playlist(ctx, idx);
+                                        }
+                                        if let Some(idx) = to_verify {
+                                            self.verify_playlist(idx);
                                         }
                                     });
                                 }
                             });
                     }
+                    
+                    // Confirm before deleting a playlist
+                    if let Some(idx) = self.confirm_delete {
+                        let mut confirmed = None;
+                        egui::Window::new("Delete playlist?")
+                            .collapsible(false)
+                            .resizable(false)
+                            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                            .show(ctx, |ui| {
+                                if let Some(playlist) = self.playlists.get(idx) {
+                                    ui.label(format!("Delete '{}' ({} tracks)?", playlist.name, playlist.track_indices.len()));
+                                    ui.colored_label(egui::Color32::GRAY, "You can undo this with Ctrl+Z.");
+                                }
+                                ui.horizontal(|ui| {
+                                    if ui.button("🗑️ Delete").clicked() {
+                                        confirmed = Some(true);
+                                    }
+                                    if ui.button("Cancel").clicked() {
+                                        confirmed = Some(false);
+                                    }
+                                });
+                            });
+                        if let Some(confirmed) = confirmed {
+                            self.confirm_delete = None;
+                            if let Some(playlist) = self.playlists.get(idx).filter(|_| confirmed).cloned() {
+                                self.execute(EditCommand::DeletePlaylist { index: idx, playlist });
+                            }
+                        }
+                    }
+                    
+                    // Results of checking a playlist against the device
+                    if let Some((name, report)) = &self.verify_result {
+                        let mut open = true;
+                        egui::Window::new(format!("Verify '{}'", name))
+                            .open(&mut open)
+                            .collapsible(false)
+                            .show(ctx, |ui| {
+                                ui.label(format!("{} of {} entries found in {}", report.ok_count(), report.checks.len(), self.music_dir));
+                                if report.problems().next().is_none() {
+                                    ui.colored_label(egui::Color32::GREEN, "✔ Everything is on the device.");
+                                }
+                                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                                    for check in report.problems() {
+                                        let detail = match &check.status {
+                                            EntryStatus::Unreadable(why) | EntryStatus::Unsupported(why) => format!(" ({})", why),
+                                            _ => String::new(),
+                                        };
+                                        ui.colored_label(egui::Color32::LIGHT_RED, format!("{}: {}{}", check.status.label(), check.entry, detail));
+                                    }
+                                });
+                            });
+                        if !open {
+                            self.verify_result = None;
+                        }
+                    }
+                    
+                    // Confirm relink proposals before the playlist file is rewritten
+                    if let Some(review) = &mut self.relink_review {
+                        let mut open = true;
+                        egui::Window::new("Repair playlist")
+                            .open(&mut open)
+                            .collapsible(false)
+                            .show(ctx, |ui| {
+                                ui.label(review.path.display().to_string());
+                                if review.report.proposals.is_empty() && review.report.unmatched.is_empty() {
+                                    ui.colored_label(egui::Color32::GREEN, "✔ No broken entries.");
+                                }
+                                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                                    for (proposal, accepted) in review.report.proposals.iter().zip(review.accepted.iter_mut()) {
+                                        let text = format!("{:.0}%  {} → {}", proposal.confidence * 100.0, proposal.entry, proposal.replacement);
+                                        let hint = format!("Matched {}", proposal.matched_on.join(", "));
+                                        ui.horizontal(|ui| {
+                                            ui.checkbox(accepted, text).on_hover_text(hint);
+                                            if proposal.confidence < relink::DEFAULT_MIN_CONFIDENCE {
+                                                ui.colored_label(egui::Color32::YELLOW, "check");
+                                            }
+                                        });
+                                    }
+                                    for entry in &review.report.unmatched {
+                                        ui.colored_label(egui::Color32::GRAY, format!("no match: {}", entry));
+                                    }
+                                });
+                                let chosen: Vec<&relink::Proposal> = review.report.proposals.iter()
+                                    .zip(&review.accepted)
+                                    .filter(|(_, accepted)| **accepted)
+                                    .map(|(p, _)| p)
+                                    .collect();
+                                if ui.add_enabled(!chosen.is_empty(), egui::Button::new(format!("🩹 Relink {} entries", chosen.len()))).clicked() {
+                                    let result = relink::apply(&review.path, &chosen);
+                                    review.outcome = Some(match &result {
+                                        Ok(n) => format!("Relinked {} entries.", n),
+                                        Err(e) => format!("Failed to rewrite the playlist: {}", e),
+                                    });
+                                    if result.is_ok() {
+                                        // Only the entries left alone stay up for review
+                                        let mut accepted = std::mem::take(&mut review.accepted).into_iter();
+                                        review.report.proposals.retain(|_| !accepted.next().unwrap_or(false));
+                                        review.accepted = vec![false; review.report.proposals.len()];
+                                    }
+                                }
+                                if let Some(outcome) = &review.outcome {
+                                    ui.label(outcome);
+                                }
+                            });
+                        if !open {
+                            self.relink_review = None;
+                        }
+                    }
                 } // end else (is_scanning)
             }); // end CentralPanel
         }
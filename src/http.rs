@@ -0,0 +1,197 @@
+use reqwest::{Client, Method, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Sent with every request; MusicBrainz turns away clients that don't say who they are
+pub const USER_AGENT: &str = concat!("PSPPlaylistMaker/", env!("CARGO_PKG_VERSION"), " ( ", env!("CARGO_PKG_REPOSITORY"), " )");
+
+/// Tries per request while a service answers 503 or 429
+const MAX_ATTEMPTS: u32 = 4;
+/// Longest pause a Retry-After header can ask for
+const MAX_RETRY_WAIT: Duration = Duration::from_secs(60);
+
+/// Why nothing was sent while offline mode is on
+pub const OFFLINE_MODE: &str = "offline mode";
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Turn offline mode on or off for the whole process. While it is on no request is sent;
+/// lookups fail at once with `ProviderError::Offline` instead of waiting for a timeout.
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Why a service couldn't answer a lookup; "nothing found" is not an error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProviderError {
+    /// No answer at all: network down, name not resolved, connection refused or timed out
+    Offline(String),
+    /// Still 429 or 503 after the retries
+    RateLimited(StatusCode),
+    /// Any other unsuccessful status
+    Http(StatusCode),
+    /// An answer in a shape we don't understand, e.g. after the service changed its API
+    Unexpected(String),
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProviderError::Offline(why) => write!(f, "unreachable ({})", why),
+            ProviderError::RateLimited(status) => write!(f, "rate limited (HTTP {})", status),
+            ProviderError::Http(status) => write!(f, "HTTP {}", status),
+            ProviderError::Unexpected(why) => write!(f, "unexpected answer: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+/// A web service and the pace it asks clients to keep
+pub struct Service {
+    pub name: &'static str,
+    /// Least time between two requests, kept across all threads of the process
+    pub min_interval: Duration,
+    /// Longest a whole request may take
+    pub timeout: Duration,
+}
+
+/// 50 requests per 5 seconds
+pub const DEEZER: Service = Service { name: "deezer", min_interval: Duration::from_millis(100), timeout: Duration::from_secs(10) };
+/// About 20 searches a minute
+pub const ITUNES: Service = Service { name: "itunes", min_interval: Duration::from_secs(3), timeout: Duration::from_secs(10) };
+/// One request per second
+pub const MUSICBRAINZ: Service = Service { name: "musicbrainz", min_interval: Duration::from_secs(1), timeout: Duration::from_secs(10) };
+/// No published limit; only HEAD checks go here
+pub const COVERARTARCHIVE: Service = Service { name: "coverartarchive", min_interval: Duration::ZERO, timeout: Duration::from_secs(10) };
+/// Five requests per second
+pub const LASTFM: Service = Service { name: "lastfm", min_interval: Duration::from_millis(200), timeout: Duration::from_secs(10) };
+
+lazy_static::lazy_static! {
+    static ref CLIENTS: Mutex<HashMap<&'static str, Client>> = Mutex::new(HashMap::new());
+    // Service name -> earliest time of its next request
+    static ref NEXT_TURN: Mutex<HashMap<&'static str, Instant>> = Mutex::new(HashMap::new());
+    static ref DOWNLOADER: reqwest::blocking::Client = reqwest::blocking::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(Duration::from_secs(5))
+        .timeout(Duration::from_secs(20))
+        .build()
+        .unwrap_or_default();
+}
+
+/// The service's client, built on first use and shared by every lookup in the process
+pub fn client(service: &Service) -> Client {
+    CLIENTS.lock().unwrap()
+        .entry(service.name)
+        .or_insert_with(|| {
+            Client::builder()
+                .user_agent(USER_AGENT)
+                .connect_timeout(Duration::from_secs(5))
+                .timeout(service.timeout)
+                // Lookups run on short-lived runtimes, and a pooled connection dies with the one that opened it
+                .pool_max_idle_per_host(0)
+                .build()
+                .unwrap_or_default()
+        })
+        .clone()
+}
+
+/// Wait until the service's rate limit allows another request, then claim it. The next turn is
+/// looked at again after every sleep, so a `back_off` meanwhile holds back callers already waiting.
+async fn wait_turn(service: &Service) {
+    loop {
+        let wait = {
+            let mut next = NEXT_TURN.lock().unwrap();
+            let now = Instant::now();
+            match next.get(service.name).filter(|t| **t > now) {
+                Some(turn) => *turn - now,
+                None => {
+                    next.insert(service.name, now + service.min_interval);
+                    return;
+                }
+            }
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Hold every request to the service back for `pause`
+fn back_off(service: &Service, pause: Duration) {
+    let until = Instant::now() + pause;
+    let mut next = NEXT_TURN.lock().unwrap();
+    let turn = next.entry(service.name).or_insert(until);
+    *turn = (*turn).max(until);
+}
+
+// Retry-After in seconds; the HTTP-date form is rare from APIs and gets the usual backoff
+fn retry_after(resp: &Response) -> Option<Duration> {
+    let secs: u64 = resp.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(secs).min(MAX_RETRY_WAIT))
+}
+
+fn is_busy(status: StatusCode) -> bool {
+    status == StatusCode::SERVICE_UNAVAILABLE || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Send a request to the service within its rate limit. While it answers 503 or 429 the request
+/// is tried again, after the Retry-After it asks for or else 1, 2, then 4 seconds; other threads
+/// wait too. Any other answer is returned whatever its status.
+pub async fn send(service: &Service, method: Method, url: &str) -> Result<Response, ProviderError> {
+    let client = client(service);
+    let mut attempt = 1;
+    loop {
+        if is_offline() {
+            return Err(ProviderError::Offline(OFFLINE_MODE.to_string()));
+        }
+        wait_turn(service).await;
+        let resp = client.request(method.clone(), url).send().await
+            .map_err(|e| ProviderError::Offline(e.without_url().to_string()))?;
+        let status = resp.status();
+        if !is_busy(status) || attempt == MAX_ATTEMPTS {
+            return Ok(resp);
+        }
+        let pause = retry_after(&resp).unwrap_or(Duration::from_secs(1 << (attempt - 1)));
+        eprintln!("[HTTP] {} answered {}, trying again in {}s", service.name, status, pause.as_secs());
+        back_off(service, pause);
+        attempt += 1;
+    }
+}
+
+/// GET a JSON answer from the service and read it into `T`
+pub async fn get_json<T: DeserializeOwned>(service: &Service, url: &str) -> Result<T, ProviderError> {
+    let resp = send(service, Method::GET, url).await?;
+    let status = resp.status();
+    if is_busy(status) {
+        return Err(ProviderError::RateLimited(status));
+    }
+    if !status.is_success() {
+        return Err(ProviderError::Http(status));
+    }
+    resp.json().await.map_err(|e| {
+        if e.is_decode() {
+            ProviderError::Unexpected(e.without_url().to_string())
+        } else {
+            ProviderError::Offline(e.without_url().to_string())
+        }
+    })
+}
+
+/// Download an image or other file. Blocks; call it outside any async runtime.
+pub fn download(url: &str) -> Result<Vec<u8>, String> {
+    if is_offline() {
+        return Err(format!("{} not downloaded in {}", url, OFFLINE_MODE));
+    }
+    let response = DOWNLOADER.get(url).send().map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("{} answered {}", url, response.status()));
+    }
+    Ok(response.bytes().map_err(|e| e.to_string())?.to_vec())
+}
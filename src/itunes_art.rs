@@ -1,21 +1,21 @@
-use serde::Deserialize;
+use crate::album_art::ArtCandidate;
 
-#[derive(Debug, Deserialize, Clone)]
-pub struct ITunesArt {
-    pub url: String,
-}
-
-pub async fn fetch_itunes_art(artist: &str, album: &str) -> Option<String> {
+/// Search the iTunes API at `base_url`, e.g. "https://itunes.apple.com", for album covers
+pub async fn fetch_itunes_art(base_url: &str, artist: &str, album: &str) -> Result<Vec<ArtCandidate>, crate::http::ProviderError> {
     let query = format!("{} {}", artist, album);
-    let url = format!("https://itunes.apple.com/search?term={}&entity=album&limit=1", urlencoding::encode(&query));
-    let resp = reqwest::get(&url).await.ok()?;
-    let json: serde_json::Value = resp.json().await.ok()?;
-    let results = json["results"].as_array()?;
-    if let Some(album) = results.first() {
-        if let Some(art_url) = album["artworkUrl100"].as_str() {
-            // Use higher-res version if available
-            return Some(art_url.replace("100x100bb", "600x600bb"));
-        }
-    }
-    None
+    let url = format!("{}/search?term={}&entity=album&limit=10", base_url.trim_end_matches('/'), urlencoding::encode(&query));
+    let json: serde_json::Value = crate::http::get_json(&crate::http::ITUNES, &url).await?;
+    let results = json["results"].as_array()
+        .ok_or_else(|| crate::http::ProviderError::Unexpected("no results list".to_string()))?;
+    Ok(results.iter()
+        .filter_map(|album| {
+            let art_url = album["artworkUrl100"].as_str()?;
+            Some(ArtCandidate {
+                // Use higher-res version if available
+                url: art_url.replace("100x100bb", "600x600bb"),
+                artist: album["artistName"].as_str().unwrap_or_default().to_string(),
+                album: album["collectionName"].as_str().unwrap_or_default().to_string(),
+            })
+        })
+        .collect())
 }
@@ -1,8 +1,26 @@
+pub mod config;
 pub mod db;
 pub mod music;
 pub mod playlist;
+pub mod ordering;
+pub mod generator;
+pub mod template;
+pub mod auto_playlist;
+pub mod device;
+pub mod sync;
+pub mod fat32;
+pub mod verify;
+pub mod compat;
+pub mod transcode;
+pub mod capacity;
+pub mod relink;
 pub mod gui;
 pub mod metadata;
+pub mod tag_writer;
 pub mod itunes_art;
 pub mod embedded_art;
+pub mod art_embed;
+pub mod local_art;
 pub mod album_art;
+pub mod art_cache;
+pub mod http;
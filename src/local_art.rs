@@ -0,0 +1,66 @@
+use crate::album_art::ArtResult;
+use crate::art_embed::CoverSource;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Image files looked for next to the tracks, in order; names match ignoring case
+pub const DEFAULT_SIDECAR_NAMES: &[&str] = &["cover.jpg", "cover.png", "folder.jpg", "folder.png", "front.jpg", "front.png", "albumart.jpg"];
+
+/// Those of `names` present in `dir`, in the order of `names`
+pub fn sidecar_images(dir: &Path, names: &[String]) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    names.iter()
+        .filter_map(|name| {
+            files.iter()
+                .find(|path| path.file_name().is_some_and(|f| f.to_string_lossy().eq_ignore_ascii_case(name)))
+                .cloned()
+        })
+        .collect()
+}
+
+/// The first of `names` present in `dir`
+pub fn sidecar_image(dir: &Path, names: &[String]) -> Option<PathBuf> {
+    sidecar_images(dir, names).into_iter().next()
+}
+
+fn embedded(track: &Path) -> ArtResult {
+    ArtResult { url: CoverSource::Embedded(track.to_path_buf()).url(), provider: "embedded".to_string(), pinned: false }
+}
+
+fn folder(image: PathBuf) -> ArtResult {
+    ArtResult { url: CoverSource::File(image).url(), provider: "folder".to_string(), pinned: false }
+}
+
+fn track_dirs(tracks: &[PathBuf]) -> Vec<&Path> {
+    let mut dirs: Vec<&Path> = tracks.iter().filter_map(|t| t.parent()).collect();
+    dirs.dedup();
+    dirs
+}
+
+/// Art the album's own files already have: a picture embedded in one of `tracks`, else an image
+/// named like one of `sidecar_names` in their folders. Reads only local files.
+pub fn find_local_art(tracks: &[PathBuf], sidecar_names: &[String]) -> Option<ArtResult> {
+    if let Some(track) = tracks.iter().find(|t| crate::embedded_art::extract_embedded_art(&t.to_string_lossy()).is_some()) {
+        return Some(embedded(track));
+    }
+    track_dirs(tracks).into_iter().find_map(|dir| sidecar_image(dir, sidecar_names).map(folder))
+}
+
+/// All local art on offer: the first embedded picture, then every matching image in the tracks' folders
+pub fn local_covers(tracks: &[PathBuf], sidecar_names: &[String]) -> Vec<ArtResult> {
+    let mut covers: Vec<ArtResult> = tracks.iter()
+        .find(|t| crate::embedded_art::extract_embedded_art(&t.to_string_lossy()).is_some())
+        .map(|t| embedded(t))
+        .into_iter()
+        .collect();
+    for dir in track_dirs(tracks) {
+        covers.extend(sidecar_images(dir, sidecar_names).into_iter().map(folder));
+    }
+    covers
+}
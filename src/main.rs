@@ -1,20 +1,248 @@
+use psp_playlist_maker::album_art;
+use psp_playlist_maker::art_cache;
+use psp_playlist_maker::art_embed;
+use psp_playlist_maker::auto_playlist;
+use psp_playlist_maker::capacity;
+use psp_playlist_maker::compat;
+use psp_playlist_maker::config::Config;
 use psp_playlist_maker::db;
+use psp_playlist_maker::device;
+use psp_playlist_maker::embedded_art;
+use psp_playlist_maker::generator;
+use psp_playlist_maker::http;
 use psp_playlist_maker::music;
 use psp_playlist_maker::gui;
+use psp_playlist_maker::metadata;
+use psp_playlist_maker::ordering;
+use psp_playlist_maker::playlist;
+use psp_playlist_maker::relink;
+use psp_playlist_maker::sync;
+use psp_playlist_maker::tag_writer;
+use psp_playlist_maker::transcode;
+use psp_playlist_maker::verify;
+
+const USAGE: &str = "Usage: psp_playlist_maker <command> [options]
+Commands:
+  scan [music_dir]   Index music files (default: MUSIC on the first detected PSP)
+  devices            List mounted PSP Memory Sticks
+  compat             List indexed tracks the PSP can't play, with reasons
+  playlist <out.m3u8> [--artist NAME] [--album NAME] [--sort KEYS] [--shuffle | --smart-shuffle] [--seed N]
+                     [--skip-incompatible | --transcode]
+                     Write a playlist from the index; KEYS is a comma list of
+                     artist, album, disc, track, title, duration
+  generate <out.m3u8> (--minutes N | --size SIZE) [--artist NAME | --genre NAME | --from PLAYLIST]
+                     [--keep-albums] [--seed N] [--skip-incompatible | --transcode]
+                     Pick tracks to fill a duration or size (e.g. 45 minutes, 2G)
+  auto-playlists <album|artist|genre|dir> [--out DIR] [--template T] [--min-tracks N] [--dry-run]
+                     Write one playlist per group; template fields: {name}, {artist},
+                     {album}, {genre}, {dir}, {count} (default \"{name}.m3u8\")
+  sync <source_dir> [--to MUSIC_DIR] [--artist NAME] [--album NAME] [--playlist FILE.m3u8]...
+                     [--layout T] [--dry-run] [--verify] [--transcode]
+                     Copy tracks from a PC library onto the PSP; playlists are rewritten
+                     to the copied paths (default layout \"{artist}/{album}/{track:02} {title}.{ext}\")
+                     --transcode converts tracks the PSP can't play; pick the converter
+                     with --encoder ffmpeg|lame|wav and the MP3 bitrate with --bitrate N
+  capacity <source_dir> [--to MUSIC_DIR] [--free SIZE] [--playlist FILE.m3u8]... [--album NAME]...
+                     [--artist NAME]... [--layout T] [--skip-incompatible | --transcode]
+                     Show which playlists and albums fit on the PSP, in the order given, and
+                     the room left; --transcode projects the size of converted tracks
+  verify [PLAYLIST.m3u8]... [--root DIR]
+                     Check that every playlist entry exists on the PSP (default: all
+                     playlists in MUSIC on the first detected PSP); DIR is the stick's root
+  relink [PLAYLIST.m3u8]... [--root DIR] [--apply] [--min-confidence N]
+                     Find moved files for broken playlist entries by file name, size and
+                     tags; --apply rewrites entries matched with at least N confidence (0.8)
+  tags <FILE> [--title T] [--artist A] [--album A] [--album-artist A] [--track N] [--disc N]
+                     [--year N] [--genre G] [--musicbrainz]
+                     Write tags into a file and the index; --musicbrainz fills title, artist
+                     and album from MusicBrainz; an empty value clears a field
+  tags --history [FILE] | tags --undo N
+                     List logged tag edits, or restore the tags edit N replaced
+  embed-art [FILE]... [--artist NAME] [--album NAME] [--image FILE | --url URL | --fetch] [--backup DIR]
+                     Embed cover art the PSP can show (baseline JPEG, 300px at most) into the
+                     named files or the indexed tracks of an artist/album; without a source,
+                     the album's pinned cover, else art already embedded in it, is re-encoded
+  art fetch [--artist NAME] [--jobs N] [--report FILE]
+                     Look up cover art for every indexed album, N at a time (4), within each
+                     service's rate limit; answers are cached, so a rerun resumes and retries
+                     only the albums that failed; FILE gets the found/missing/failed summary
+  gui               View indexed tracks in GUI
+  help              Show this message
+Options:
+  --offline          Don't go online, with any command: art comes only from the tracks, their
+                     folders and the cache (also \"offline\": true in psp_playlist_maker.json)";
+
+/// Value following `flag` on the command line, e.g. `--sort artist,title`
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1)).map(|s| s.as_str())
+}
+
+/// Every value given for a repeatable flag, e.g. `--playlist a.m3u8 --playlist b.m3u8`
+fn flag_values<'a>(args: &'a [String], flag: &str) -> Vec<&'a str> {
+    args.windows(2).filter(|w| w[0] == flag).map(|w| w[1].as_str()).collect()
+}
+
+/// `--seed N`, or a random seed when absent
+fn seed_arg(args: &[String]) -> Result<u64, String> {
+    match flag_value(args, "--seed") {
+        Some(seed) => seed.parse().map_err(|e| format!("Invalid --seed: {}", e)),
+        None => Ok(ordering::random_seed()),
+    }
+}
+
+/// `--transcode` with its `--encoder` and `--bitrate`, converting into ./transcode_cache
+fn transcoder_arg(args: &[String]) -> Result<Option<std::sync::Arc<transcode::Transcoder>>, String> {
+    if !args.iter().any(|a| a == "--transcode") {
+        return Ok(None);
+    }
+    let bitrate = match flag_value(args, "--bitrate") {
+        Some(kbps) => kbps.parse().map_err(|e| format!("Invalid --bitrate: {}", e))?,
+        None => transcode::DEFAULT_BITRATE_KBPS,
+    };
+    let encoder = match flag_value(args, "--encoder") {
+        Some(name) => {
+            let encoder = transcode::encoder_by_name(name, bitrate)
+                .ok_or_else(|| format!("Unknown --encoder '{}' (use ffmpeg, lame or wav)", name))?;
+            if !encoder.available() {
+                return Err(format!("{} is not installed.", name));
+            }
+            encoder
+        }
+        None => transcode::default_encoder(bitrate),
+    };
+    println!("Converting tracks the PSP can't play with {}.", encoder.name());
+    Ok(Some(std::sync::Arc::new(transcode::Transcoder::new(encoder, "transcode_cache"))))
+}
+
+/// Tracks to write to a playlist: those the PSP can't play are reported, then kept,
+/// dropped (`--skip-incompatible`) or replaced by a converted copy next to them (`--transcode`)
+fn export_tracks(args: &[String], tracks: &[music::Track], entries: &[usize]) -> Result<Vec<music::Track>, String> {
+    let skip = args.iter().any(|a| a == "--skip-incompatible");
+    let transcoder = transcoder_arg(args)?;
+    let mut out = Vec::with_capacity(entries.len());
+    let mut incompatible = 0;
+    for idx in entries {
+        let mut track = tracks[*idx].clone();
+        let issues = compat::check(&track);
+        if !issues.is_empty() {
+            incompatible += 1;
+            eprintln!("  won't play: {} ({})", track.path, compat::describe(&issues));
+            if skip {
+                continue;
+            }
+            if let Some(transcoder) = &transcoder {
+                match transcoder.transcode_beside(std::path::Path::new(&track.path)) {
+                    Ok(converted) => track.path = converted.display().to_string(),
+                    Err(e) => eprintln!("  failed to convert {}: {}", track.path, e),
+                }
+            }
+        }
+        out.push(track);
+    }
+    if incompatible > 0 {
+        if skip {
+            println!("Left out {} tracks the PSP can't play.", incompatible);
+        } else if transcoder.is_some() {
+            println!("Converted {} tracks the PSP can't play.", incompatible);
+        } else {
+            println!("Warning: {} tracks won't play on the PSP; pass --skip-incompatible to leave them out or --transcode to convert them.", incompatible);
+        }
+    }
+    Ok(out)
+}
+
+/// Tags on one line, e.g. "Artist - Title [Album] #3 (1999, Rock)"
+fn describe_tags(values: &tag_writer::TagValues) -> String {
+    let mut out = format!("{} - {}", values.artist, values.title);
+    if !values.album.is_empty() {
+        out.push_str(&format!(" [{}]", values.album));
+    }
+    if let Some(n) = values.track_number {
+        out.push_str(&format!(" #{}", n));
+    }
+    let extra: Vec<String> = values.year.map(|y| y.to_string()).into_iter()
+        .chain((!values.genre.is_empty()).then(|| values.genre.clone()))
+        .collect();
+    if !extra.is_empty() {
+        out.push_str(&format!(" ({})", extra.join(", ")));
+    }
+    out
+}
+
+/// An album's (artist, album) as first spelled in the index, with its tracks
+type AlbumTracks = ((String, String), Vec<std::path::PathBuf>);
+
+fn load_index(db_path: &str) -> Vec<music::Track> {
+    match db::init_db(db_path) {
+        Ok(conn) => db::load_tracks(&conn).unwrap_or_else(|e| {
+            eprintln!("Failed to load tracks: {}", e);
+            Vec::new()
+        }),
+        Err(e) => {
+            eprintln!("Failed to initialize database: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Device root (`--root`, else the first detected PSP) and the playlists named on the
+/// command line, or every playlist in its MUSIC folder when none are given
+fn device_playlists(args: &[String]) -> Option<(std::path::PathBuf, Vec<std::path::PathBuf>)> {
+    let (root, music_dir) = match flag_value(args, "--root") {
+        Some(dir) => {
+            let root = std::path::PathBuf::from(dir);
+            let music_dir = device::find_child_dir(&root, "MUSIC").unwrap_or_else(|| root.join("MUSIC"));
+            (root, music_dir)
+        }
+        None => match device::detect_devices().into_iter().next() {
+            Some(d) => (d.root, d.music_dir),
+            None => {
+                eprintln!("No PSP detected; pass the Memory Stick's root with --root.");
+                return None;
+            }
+        },
+    };
+    // Positional arguments, skipping the values of flags that take one
+    let mut playlists: Vec<std::path::PathBuf> = args.iter()
+        .enumerate()
+        .skip(2)
+        .filter(|(i, a)| !a.starts_with("--") && !matches!(args[i - 1].as_str(), "--root" | "--min-confidence"))
+        .map(|(_, a)| std::path::PathBuf::from(a))
+        .collect();
+    if playlists.is_empty() {
+        playlists = verify::find_playlists(&music_dir);
+        if playlists.is_empty() {
+            println!("No playlists found in {}.", music_dir.display());
+            return None;
+        }
+    }
+    Some((root, playlists))
+}
 
 fn main() {
     use std::env;
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    let offline_flag = args.iter().any(|a| a == "--offline");
+    args.retain(|a| a != "--offline");
+    if offline_flag || Config::load().offline {
+        http::set_offline(true);
+    }
     if args.len() < 2 {
         println!("PSP Playlist Maker CLI");
-        println!("Usage: psp_playlist_maker <command> [options]\nCommands:\n  scan <music_dir>   Index music files\n  gui               View indexed tracks in GUI\n  help              Show this message");
+        println!("{}", USAGE);
         return;
     }
     match args[1].as_str() {
         "scan" => {
-            let music_dir = if args.len() > 2 { &args[2] } else { "/MUSIC" };
+            // Mounts are only probed when no directory is given
+            let music_dir = match args.get(2) {
+                Some(dir) => dir.clone(),
+                None => device::detect_devices().into_iter().next()
+                    .map(|d| d.music_dir.display().to_string())
+                    .unwrap_or_else(|| "/MUSIC".to_string()),
+            };
             println!("Scanning music directory: {}", music_dir);
-            let library = music::MusicLibrary::scan_dir(music_dir);
+            let library = music::MusicLibrary::scan_dir(&music_dir);
             println!("Indexed {} tracks.", library.tracks.len());
             let db_path = "music_index.db";
             match db::init_db(db_path) {
@@ -27,6 +255,798 @@ fn main() {
                 Err(e) => eprintln!("Failed to initialize database: {}", e),
             }
         }
+        "devices" => {
+            let devices = device::detect_devices();
+            if devices.is_empty() {
+                println!("No PSP found. Connect it in USB mode and make sure the Memory Stick is mounted.");
+            }
+            let space = |bytes: Option<u64>| bytes.map(generator::format_size).unwrap_or_else(|| "?".to_string());
+            for d in devices {
+                println!("{}  {}  (free {} of {})", d.label, d.music_dir.display(), space(d.free_bytes), space(d.total_bytes));
+            }
+        }
+        "compat" => {
+            let tracks = load_index("music_index.db");
+            let mut incompatible = 0;
+            for track in &tracks {
+                let issues = compat::check(track);
+                if !issues.is_empty() {
+                    println!("{}: {}", track.path, compat::describe(&issues));
+                    incompatible += 1;
+                }
+            }
+            println!("{} of {} indexed tracks won't play on the PSP.", incompatible, tracks.len());
+            if tracks.iter().any(|t| t.codec.is_empty() && compat::is_compatible(t)) {
+                println!("Some tracks were indexed without audio details; rescan to check them fully.");
+            }
+        }
+        "playlist" => {
+            let Some(output) = args.get(2).filter(|a| !a.starts_with("--")) else {
+                eprintln!("Missing output file.\n{}", USAGE);
+                return;
+            };
+            let tracks = load_index("music_index.db");
+            let artist = flag_value(&args, "--artist").map(|a| a.to_lowercase());
+            let album = flag_value(&args, "--album").map(|a| a.to_lowercase());
+            let mut entries: Vec<usize> = (0..tracks.len())
+                .filter(|i| artist.as_ref().is_none_or(|a| &tracks[*i].artist.to_lowercase() == a))
+                .filter(|i| album.as_ref().is_none_or(|a| &tracks[*i].album.to_lowercase() == a))
+                .collect();
+            if let Some(keys) = flag_value(&args, "--sort") {
+                match ordering::parse_sort_keys(keys) {
+                    Ok(keys) => ordering::sort_entries(&mut entries, &tracks, &keys),
+                    Err(e) => {
+                        eprintln!("Invalid --sort: {}", e);
+                        return;
+                    }
+                }
+            }
+            let smart = args.iter().any(|a| a == "--smart-shuffle");
+            if smart || args.iter().any(|a| a == "--shuffle") {
+                let seed = match seed_arg(&args) {
+                    Ok(seed) => seed,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        return;
+                    }
+                };
+                if smart {
+                    ordering::smart_shuffle_entries(&mut entries, &tracks, seed);
+                } else {
+                    ordering::shuffle_entries(&mut entries, seed);
+                }
+                println!("Shuffled with seed {} (pass --seed {} to repeat).", seed, seed);
+            }
+            let export = match export_tracks(&args, &tracks, &entries) {
+                Ok(export) => export,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+            let playlist_tracks: Vec<&music::Track> = export.iter().collect();
+            match playlist::write_m3u8(output, &playlist_tracks) {
+                Ok(_) => println!("Wrote {} tracks to {}.", playlist_tracks.len(), output),
+                Err(e) => eprintln!("Failed to write playlist: {}", e),
+            }
+        }
+        "generate" => {
+            let Some(output) = args.get(2).filter(|a| !a.starts_with("--")) else {
+                eprintln!("Missing output file.\n{}", USAGE);
+                return;
+            };
+            let target = if let Some(minutes) = flag_value(&args, "--minutes") {
+                match minutes.parse::<f64>() {
+                    Ok(m) => generator::Target::DurationSecs((m * 60.0) as u64),
+                    Err(e) => {
+                        eprintln!("Invalid --minutes: {}", e);
+                        return;
+                    }
+                }
+            } else if let Some(size) = flag_value(&args, "--size") {
+                match generator::parse_size(size) {
+                    Some(bytes) => generator::Target::Bytes(bytes),
+                    None => {
+                        eprintln!("Invalid --size '{}': use e.g. 700M or 2G", size);
+                        return;
+                    }
+                }
+            } else {
+                eprintln!("Give a target with --minutes or --size.\n{}", USAGE);
+                return;
+            };
+            let tracks = load_index("music_index.db");
+            let source = if let Some(artist) = flag_value(&args, "--artist") {
+                generator::TrackSource::Artist(artist.to_string())
+            } else if let Some(genre) = flag_value(&args, "--genre") {
+                generator::TrackSource::Genre(genre.to_string())
+            } else if let Some(from) = flag_value(&args, "--from") {
+                match playlist::read_m3u8(from) {
+                    Ok(entries) => generator::TrackSource::Entries(playlist::match_entries(&entries, &tracks)),
+                    Err(e) => {
+                        eprintln!("Failed to read {}: {}", from, e);
+                        return;
+                    }
+                }
+            } else {
+                generator::TrackSource::Library
+            };
+            let seed = match seed_arg(&args) {
+                Ok(seed) => seed,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+            let options = generator::GenerateOptions {
+                target,
+                keep_albums: args.iter().any(|a| a == "--keep-albums"),
+                seed,
+            };
+            // Fill the target with playable tracks only, rather than dropping some afterwards
+            let source = if args.iter().any(|a| a == "--skip-incompatible") {
+                generator::TrackSource::Entries(source.entries(&tracks).into_iter().filter(|i| compat::is_compatible(&tracks[*i])).collect())
+            } else {
+                source
+            };
+            let report = generator::generate(&tracks, &source, &options);
+            println!("Generated {} (seed {}).", report.summary(), seed);
+            if report.skipped_unknown > 0 {
+                println!("Skipped {} tracks with unknown duration/size; rescan to index them.", report.skipped_unknown);
+            }
+            let export = match export_tracks(&args, &tracks, &report.entries) {
+                Ok(export) => export,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+            let playlist_tracks: Vec<&music::Track> = export.iter().collect();
+            match playlist::write_m3u8(output, &playlist_tracks) {
+                Ok(_) => println!("Wrote {} tracks to {}.", playlist_tracks.len(), output),
+                Err(e) => eprintln!("Failed to write playlist: {}", e),
+            }
+        }
+        "auto-playlists" => {
+            let group_by = match args.get(2).map(|g| g.parse::<auto_playlist::GroupBy>()) {
+                Some(Ok(group_by)) => group_by,
+                Some(Err(e)) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+                None => {
+                    eprintln!("Missing grouping.\n{}", USAGE);
+                    return;
+                }
+            };
+            let min_tracks = match flag_value(&args, "--min-tracks").map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) => n,
+                Some(Err(e)) => {
+                    eprintln!("Invalid --min-tracks: {}", e);
+                    return;
+                }
+                None => 1,
+            };
+            let options = auto_playlist::AutoPlaylistOptions {
+                group_by,
+                template: flag_value(&args, "--template").unwrap_or(auto_playlist::DEFAULT_TEMPLATE).to_string(),
+                min_tracks,
+            };
+            let out_dir = std::path::Path::new(flag_value(&args, "--out").unwrap_or("."));
+            let tracks = load_index("music_index.db");
+            let plan = auto_playlist::plan(&tracks, &options);
+            for (group, count) in &plan.skipped {
+                println!("  skip  {} ({} tracks, under --min-tracks)", group, count);
+            }
+            for planned in &plan.playlists {
+                println!("  write {} ({} tracks)", out_dir.join(&planned.file_name).display(), planned.entries.len());
+            }
+            if args.iter().any(|a| a == "--dry-run") {
+                println!("Dry run: {} playlists would be written, {} groups skipped.", plan.playlists.len(), plan.skipped.len());
+                return;
+            }
+            match auto_playlist::write_all(&plan, &tracks, out_dir) {
+                Ok(n) => println!("Wrote {} playlists to {}.", n, out_dir.display()),
+                Err(e) => eprintln!("Failed to write playlists: {}", e),
+            }
+        }
+        "sync" => {
+            let Some(source_dir) = args.get(2).filter(|a| !a.starts_with("--")) else {
+                eprintln!("Missing source directory.\n{}", USAGE);
+                return;
+            };
+            let music_dir = match flag_value(&args, "--to") {
+                Some(dir) => std::path::PathBuf::from(dir),
+                None => match device::detect_devices().into_iter().next() {
+                    Some(d) => d.music_dir,
+                    None => {
+                        eprintln!("No PSP detected; pass its MUSIC folder with --to.");
+                        return;
+                    }
+                },
+            };
+            println!("Scanning source library: {}", source_dir);
+            let library = music::MusicLibrary::scan_dir(source_dir);
+            let tracks = library.tracks;
+
+            let mut playlists = Vec::new();
+            for path in flag_values(&args, "--playlist") {
+                match sync::resolve_source_playlist(std::path::Path::new(path), &tracks) {
+                    Ok((entries, missing)) => {
+                        for entry in &missing {
+                            eprintln!("  {}: not in source library: {}", path, entry);
+                        }
+                        playlists.push((path, entries));
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to read {}: {}", path, e);
+                        return;
+                    }
+                }
+            }
+            let artist = flag_value(&args, "--artist").map(|a| a.to_lowercase());
+            let album = flag_value(&args, "--album").map(|a| a.to_lowercase());
+            let mut selection: Vec<usize> = playlists.iter().flat_map(|(_, e)| e.iter().copied()).collect();
+            if artist.is_some() || album.is_some() || playlists.is_empty() {
+                selection.extend((0..tracks.len())
+                    .filter(|i| artist.as_ref().is_none_or(|a| &tracks[*i].artist.to_lowercase() == a))
+                    .filter(|i| album.as_ref().is_none_or(|a| &tracks[*i].album.to_lowercase() == a)));
+            }
+            let mut seen = std::collections::HashSet::new();
+            selection.retain(|i| seen.insert(*i));
+
+            let transcoder = match transcoder_arg(&args) {
+                Ok(transcoder) => transcoder,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+            let options = sync::SyncOptions {
+                layout: flag_value(&args, "--layout").unwrap_or(sync::DEFAULT_LAYOUT).to_string(),
+                dry_run: args.iter().any(|a| a == "--dry-run"),
+                verify_hash: args.iter().any(|a| a == "--verify"),
+                transcoder,
+            };
+            let report = sync::sync_tracks(&tracks, &selection, &music_dir, &options, |p| {
+                let action = match &p.item.action {
+                    sync::SyncAction::Copy if p.item.transcode => "convert",
+                    sync::SyncAction::Copy => "copy",
+                    sync::SyncAction::Skip => "skip",
+                    sync::SyncAction::Failed(_) => "fail",
+                };
+                println!("[{}/{}] {} {}", p.done + 1, p.total, action, p.item.dest.display());
+            });
+            for (path, entries) in &playlists {
+                let device_tracks = report.device_tracks(&tracks, entries);
+                let refs: Vec<&music::Track> = device_tracks.iter().collect();
+                let name = std::path::Path::new(path).file_name().unwrap_or_default();
+                let dest = music_dir.join(name);
+                if options.dry_run {
+                    println!("Would write {} ({} tracks)", dest.display(), refs.len());
+                } else if let Err(e) = playlist::write_m3u8(&dest.to_string_lossy(), &refs) {
+                    eprintln!("Failed to write {}: {}", dest.display(), e);
+                } else {
+                    println!("Wrote {} ({} tracks)", dest.display(), refs.len());
+                }
+            }
+            for change in &report.renamed {
+                println!("Renamed for FAT32: {} -> {}", change.original, change.sanitized);
+            }
+            let verb = if options.dry_run { "Dry run: would copy" } else { "Copied" };
+            println!(
+                "{} {} tracks ({}), {} already present, {} failed.",
+                verb,
+                report.copied(),
+                generator::format_size(report.bytes_copied()),
+                report.skipped(),
+                report.failed()
+            );
+        }
+        "capacity" => {
+            let Some(source_dir) = args.get(2).filter(|a| !a.starts_with("--")) else {
+                eprintln!("Missing source directory.\n{}", USAGE);
+                return;
+            };
+            let (music_dir, mut free, total) = match flag_value(&args, "--to") {
+                Some(dir) => {
+                    let music_dir = std::path::PathBuf::from(dir);
+                    let free = device::free_space(&music_dir);
+                    (music_dir, free, None)
+                }
+                None => match device::detect_devices().into_iter().next() {
+                    Some(d) => (d.music_dir, d.free_bytes, d.total_bytes),
+                    None => {
+                        eprintln!("No PSP detected; pass its MUSIC folder with --to.");
+                        return;
+                    }
+                },
+            };
+            if let Some(size) = flag_value(&args, "--free") {
+                match generator::parse_size(size) {
+                    Some(bytes) => free = Some(bytes),
+                    None => {
+                        eprintln!("Invalid --free '{}': use e.g. 700M or 2G", size);
+                        return;
+                    }
+                }
+            }
+            let Some(free) = free else {
+                eprintln!("Can't tell the free space in {}; give it with --free.", music_dir.display());
+                return;
+            };
+            println!("Scanning source library: {}", source_dir);
+            let tracks = music::MusicLibrary::scan_dir(source_dir).tracks;
+
+            // Selections in command line order
+            let mut selections = Vec::new();
+            for pair in args.windows(2) {
+                let (flag, value) = (pair[0].as_str(), pair[1].as_str());
+                let (label, entries) = match flag {
+                    "--playlist" => match sync::resolve_source_playlist(std::path::Path::new(value), &tracks) {
+                        Ok((entries, missing)) => {
+                            for entry in &missing {
+                                eprintln!("  {}: not in source library: {}", value, entry);
+                            }
+                            (value.to_string(), entries)
+                        }
+                        Err(e) => {
+                            eprintln!("Failed to read {}: {}", value, e);
+                            return;
+                        }
+                    },
+                    "--album" | "--artist" => {
+                        let wanted = value.to_lowercase();
+                        let entries = (0..tracks.len())
+                            .filter(|i| {
+                                let field = if flag == "--album" { &tracks[*i].album } else { &tracks[*i].artist };
+                                field.to_lowercase() == wanted
+                            })
+                            .collect();
+                        (format!("{} {}", &flag[2..], value), entries)
+                    }
+                    _ => continue,
+                };
+                selections.push(capacity::Selection { label, tracks: entries });
+            }
+            if selections.is_empty() {
+                eprintln!("Choose what to plan with --playlist, --album or --artist.\n{}", USAGE);
+                return;
+            }
+            if args.iter().any(|a| a == "--skip-incompatible") {
+                for selection in &mut selections {
+                    selection.tracks.retain(|i| compat::is_compatible(&tracks[*i]));
+                }
+            }
+
+            let transcoder = match transcoder_arg(&args) {
+                Ok(transcoder) => transcoder,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+            let options = sync::SyncOptions {
+                layout: flag_value(&args, "--layout").unwrap_or(sync::DEFAULT_LAYOUT).to_string(),
+                dry_run: true,
+                verify_hash: false,
+                transcoder,
+            };
+            let mut all: Vec<usize> = selections.iter().flat_map(|s| s.tracks.iter().copied()).collect();
+            let mut seen = std::collections::HashSet::new();
+            all.retain(|i| seen.insert(*i));
+            let costs = capacity::costs_from_sync(&sync::plan_sync(&tracks, &all, &music_dir, &options));
+            let plan = capacity::plan(&selections, free, total, |i| costs.get(&i).copied().unwrap_or_default());
+
+            match plan.total_bytes {
+                Some(total) => println!("Free on the stick: {} of {}", generator::format_size(plan.free_bytes), generator::format_size(total)),
+                None => println!("Free on the stick: {}", generator::format_size(plan.free_bytes)),
+            }
+            for selection in &plan.selections {
+                let status = if selection.fits { "fits" } else { "overflow" };
+                let mut detail = String::new();
+                if selection.converted > 0 {
+                    detail.push_str(&format!("  ({} converted)", selection.converted));
+                }
+                if !selection.fits {
+                    detail.push_str(&format!("  needs {} more", generator::format_size(selection.short_by)));
+                }
+                println!(
+                    "  {:<9} {} ({} tracks)  {}{}",
+                    status,
+                    selection.label,
+                    selection.tracks,
+                    generator::format_size(selection.bytes),
+                    detail
+                );
+            }
+            let overflowing = plan.overflowing().count();
+            println!(
+                "Selections that fit take {}; {} left.{}",
+                generator::format_size(plan.needed()),
+                generator::format_size(plan.remaining()),
+                if overflowing > 0 { format!(" {} won't fit.", overflowing) } else { String::new() }
+            );
+        }
+        "verify" => {
+            let Some((root, playlists)) = device_playlists(&args) else {
+                return;
+            };
+            let mut total_problems = 0;
+            for path in &playlists {
+                let report = match verify::verify_playlist(&root, path) {
+                    Ok(report) => report,
+                    Err(e) => {
+                        eprintln!("Failed to read {}: {}", path.display(), e);
+                        continue;
+                    }
+                };
+                println!("{}: {} of {} entries OK", path.display(), report.ok_count(), report.checks.len());
+                for check in report.problems() {
+                    let detail = match &check.status {
+                        verify::EntryStatus::Unreadable(why) | verify::EntryStatus::Unsupported(why) => format!(" ({})", why),
+                        _ => String::new(),
+                    };
+                    println!("  {:<11} {}{}", check.status.label(), check.entry, detail);
+                    total_problems += 1;
+                }
+            }
+            if total_problems == 0 {
+                println!("All entries found on the device.");
+            } else {
+                println!("{} entries won't play.", total_problems);
+            }
+        }
+        "relink" => {
+            let min_confidence = match flag_value(&args, "--min-confidence").map(|n| n.parse::<f32>()) {
+                Some(Ok(n)) => n,
+                Some(Err(e)) => {
+                    eprintln!("Invalid --min-confidence: {}", e);
+                    return;
+                }
+                None => relink::DEFAULT_MIN_CONFIDENCE,
+            };
+            let Some((root, playlists)) = device_playlists(&args) else {
+                return;
+            };
+            let apply = args.iter().any(|a| a == "--apply");
+            let tracks = load_index("music_index.db");
+            let mut needs_review = 0;
+            for path in &playlists {
+                let report = match relink::relink_playlist(&root, path, &tracks) {
+                    Ok(report) => report,
+                    Err(e) => {
+                        eprintln!("Failed to read {}: {}", path.display(), e);
+                        continue;
+                    }
+                };
+                if report.proposals.is_empty() && report.unmatched.is_empty() {
+                    continue;
+                }
+                println!("{}:", path.display());
+                for p in &report.proposals {
+                    let review = if p.confidence < min_confidence { "  [review]" } else { "" };
+                    println!("  {:>3.0}%  {} -> {} (matched {}){}", p.confidence * 100.0, p.entry, p.replacement, p.matched_on.join(", "), review);
+                }
+                for entry in &report.unmatched {
+                    println!("    --  {}: no match in the index", entry);
+                }
+                needs_review += report.uncertain(min_confidence).count();
+                if apply {
+                    let confident: Vec<&relink::Proposal> = report.confident(min_confidence).collect();
+                    if confident.is_empty() {
+                        continue;
+                    }
+                    match relink::apply(path, &confident) {
+                        Ok(n) => println!("  Relinked {} entries.", n),
+                        Err(e) => eprintln!("  Failed to rewrite {}: {}", path.display(), e),
+                    }
+                }
+            }
+            if !apply {
+                println!("Nothing changed; pass --apply to rewrite entries matched with at least {:.0}% confidence.", min_confidence * 100.0);
+            }
+            if needs_review > 0 {
+                println!("{} matches are below {:.0}% and were left alone; check them and rerun with a lower --min-confidence to accept.", needs_review, min_confidence * 100.0);
+            }
+        }
+        "tags" => {
+            let mut conn = match db::init_db("music_index.db") {
+                Ok(conn) => conn,
+                Err(e) => {
+                    eprintln!("Failed to initialize database: {}", e);
+                    return;
+                }
+            };
+            let file = args.get(2).filter(|a| !a.starts_with("--"));
+            if args.iter().any(|a| a == "--history") {
+                match db::load_tag_edits(&conn, file.map(|f| f.as_str()), Some(50)) {
+                    Ok(edits) if edits.is_empty() => println!("No tag edits yet."),
+                    Ok(edits) => {
+                        for edit in edits {
+                            let undone = if edit.undone { "  (undone)" } else { "" };
+                            println!("#{}  {}{}", edit.id, edit.path, undone);
+                            println!("    {} -> {}", describe_tags(&edit.before), describe_tags(&edit.after));
+                        }
+                    }
+                    Err(e) => eprintln!("Failed to read tag edits: {}", e),
+                }
+                return;
+            }
+            if let Some(id) = flag_value(&args, "--undo") {
+                let Ok(id) = id.trim_start_matches('#').parse() else {
+                    eprintln!("Invalid --undo '{}': give an edit number from --history", id);
+                    return;
+                };
+                match tag_writer::undo_edit(&mut conn, id) {
+                    Ok(edit) => println!("Restored {}: {}", edit.path, describe_tags(&edit.before)),
+                    Err(e) => eprintln!("Failed to undo edit {}: {}", id, e),
+                }
+                return;
+            }
+            let Some(file) = file else {
+                eprintln!("Missing file.\n{}", USAGE);
+                return;
+            };
+            let path = std::path::Path::new(file);
+            let before = match tag_writer::read_tags(path) {
+                Ok(values) => values,
+                Err(e) => {
+                    eprintln!("Failed to read tags of {}: {}", file, e);
+                    return;
+                }
+            };
+            let mut after = before.clone();
+            if args.iter().any(|a| a == "--musicbrainz") {
+                let endpoints = Config::load().endpoints;
+                let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+                match rt.block_on(metadata::fetch_metadata(&endpoints.musicbrainz, &before.artist, &before.album, &before.title)) {
+                    Ok(Some(found)) => {
+                        after.title = found.title;
+                        after.artist = found.artist;
+                        after.album = found.album;
+                    }
+                    Ok(None) => {
+                        eprintln!("No MusicBrainz match for {}.", describe_tags(&before));
+                        return;
+                    }
+                    Err(e) => {
+                        eprintln!("MusicBrainz lookup failed: {}", e);
+                        return;
+                    }
+                }
+            }
+            // Explicit flags win over MusicBrainz; an empty value clears the field
+            let number = |flag: &str| -> Result<Option<Option<u32>>, String> {
+                match flag_value(&args, flag) {
+                    None => Ok(None),
+                    Some("") => Ok(Some(None)),
+                    Some(n) => n.parse().map(|n| Some(Some(n))).map_err(|e| format!("Invalid {}: {}", flag, e)),
+                }
+            };
+            for (flag, field) in [
+                ("--title", &mut after.title),
+                ("--artist", &mut after.artist),
+                ("--album", &mut after.album),
+                ("--album-artist", &mut after.album_artist),
+                ("--genre", &mut after.genre),
+            ] {
+                if let Some(value) = flag_value(&args, flag) {
+                    *field = value.to_string();
+                }
+            }
+            for (flag, field) in [("--track", &mut after.track_number), ("--disc", &mut after.disc_number), ("--year", &mut after.year)] {
+                match number(flag) {
+                    Ok(Some(value)) => *field = value,
+                    Ok(None) => {}
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        return;
+                    }
+                }
+            }
+            if after == before {
+                println!("Nothing to change: {}", describe_tags(&before));
+                return;
+            }
+            match tag_writer::apply_edit(&mut conn, path, &after) {
+                Ok(id) => {
+                    println!("Wrote {}: {}", file, describe_tags(&after));
+                    println!("Undo with: psp_playlist_maker tags --undo {}", id);
+                }
+                Err(e) => eprintln!("Failed to write tags of {}: {}", file, e),
+            }
+        }
+        "embed-art" => {
+            // Files named on the command line, else indexed tracks of the given artist/album
+            let files: Vec<std::path::PathBuf> = args.iter()
+                .enumerate()
+                .skip(2)
+                .filter(|(i, a)| !a.starts_with("--") && !matches!(args[i - 1].as_str(), "--artist" | "--album" | "--image" | "--url" | "--backup"))
+                .map(|(_, a)| std::path::PathBuf::from(a))
+                .collect();
+            let artist = flag_value(&args, "--artist").map(|a| a.to_lowercase());
+            let album = flag_value(&args, "--album").map(|a| a.to_lowercase());
+            // Each album gets its own cover
+            let mut groups: Vec<((String, String), Vec<std::path::PathBuf>)> = Vec::new();
+            let mut add = |key: (String, String), path: std::path::PathBuf| {
+                match groups.iter_mut().find(|(k, _)| *k == key) {
+                    Some((_, paths)) => paths.push(path),
+                    None => groups.push((key, vec![path])),
+                }
+            };
+            if !files.is_empty() {
+                for path in files {
+                    let meta = embedded_art::extract_metadata(&path.to_string_lossy()).unwrap_or_default();
+                    add((meta.artist, meta.album), path);
+                }
+            } else if artist.is_some() || album.is_some() {
+                for track in load_index("music_index.db") {
+                    if artist.as_ref().is_none_or(|a| &track.artist.to_lowercase() == a)
+                        && album.as_ref().is_none_or(|a| &track.album.to_lowercase() == a)
+                    {
+                        add((track.artist.clone(), track.album.clone()), std::path::PathBuf::from(&track.path));
+                    }
+                }
+            } else {
+                eprintln!("Name the tracks, or pick indexed ones with --artist/--album.\n{}", USAGE);
+                return;
+            }
+            if groups.is_empty() {
+                println!("No matching tracks in the index.");
+                return;
+            }
+            let backup_dir = flag_value(&args, "--backup").map(std::path::PathBuf::from);
+            let fetch = args.iter().any(|a| a == "--fetch");
+            let (mut embedded, mut failed) = (0, 0);
+            for ((artist, album), paths) in &groups {
+                let cover = if let Some(image) = flag_value(&args, "--image") {
+                    art_embed::CoverSource::File(image.into())
+                } else if let Some(url) = flag_value(&args, "--url") {
+                    art_embed::CoverSource::Url(url.to_string())
+                } else if fetch {
+                    match art_cache::fetch_album_art_cached("music_index.db", artist, album, &[]) {
+                        Ok(Some(found)) => {
+                            println!("Cover for {} - {} from {}", artist, album, found.provider);
+                            art_embed::CoverSource::from_url(&found.url)
+                        }
+                        Ok(None) => {
+                            eprintln!("No cover found for {} - {}", artist, album);
+                            failed += paths.len();
+                            continue;
+                        }
+                        Err(failures) => {
+                            eprintln!("Cover lookup failed for {} - {}: {}", artist, album, album_art::describe_failures(&failures));
+                            failed += paths.len();
+                            continue;
+                        }
+                    }
+                } else if let Some(pinned) = art_cache::pinned_art("music_index.db", artist, album) {
+                    println!("Cover for {} - {} pinned from {}", artist, album, pinned.provider);
+                    art_embed::CoverSource::from_url(&pinned.url)
+                } else {
+                    // Re-encode art one of the album's files already has
+                    match paths.iter().find(|p| embedded_art::extract_embedded_art(&p.to_string_lossy()).is_some()) {
+                        Some(path) => art_embed::CoverSource::Embedded(path.clone()),
+                        None => {
+                            eprintln!("No embedded art for {} - {}; pass --image, --url or --fetch.", artist, album);
+                            failed += paths.len();
+                            continue;
+                        }
+                    }
+                };
+                match art_embed::embed_batch(paths, &cover, backup_dir.as_deref()) {
+                    Ok(report) => {
+                        for path in &report.embedded {
+                            println!("  embedded {}", path.display());
+                        }
+                        for (path, why) in &report.failed {
+                            eprintln!("  failed {}: {}", path.display(), why);
+                        }
+                        embedded += report.embedded.len();
+                        failed += report.failed.len();
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to prepare the cover for {} - {}: {}", artist, album, e);
+                        failed += paths.len();
+                    }
+                }
+            }
+            println!("Embedded cover art into {} tracks, {} failed.", embedded, failed);
+            if let Some(dir) = &backup_dir {
+                println!("Originals backed up to {}.", dir.display());
+            }
+        }
+        "art" if args.get(2).map(String::as_str) == Some("fetch") => {
+            let jobs: usize = match flag_value(&args, "--jobs").map(str::parse) {
+                Some(Ok(n)) if n > 0 => n,
+                None => 4,
+                _ => {
+                    eprintln!("--jobs needs a positive number.\n{}", USAGE);
+                    return;
+                }
+            };
+            let artist_filter = flag_value(&args, "--artist").map(art_cache::normalize);
+            // Every indexed album once, however its names are capitalized or spaced
+            let mut by_key: std::collections::BTreeMap<(String, String), AlbumTracks> = Default::default();
+            let mut untagged = 0;
+            for track in load_index("music_index.db") {
+                if track.artist.trim().is_empty() || track.album.trim().is_empty() {
+                    untagged += 1;
+                    continue;
+                }
+                let key = (art_cache::normalize(&track.artist), art_cache::normalize(&track.album));
+                if artist_filter.as_ref().is_some_and(|a| *a != key.0) {
+                    continue;
+                }
+                by_key.entry(key)
+                    .or_insert_with(|| ((track.artist.clone(), track.album.clone()), Vec::new()))
+                    .1
+                    .push(std::path::PathBuf::from(&track.path));
+            }
+            let albums: Vec<AlbumTracks> = by_key.into_values().collect();
+            if albums.is_empty() {
+                println!("No albums in the index to fetch art for.");
+                return;
+            }
+
+            // Workers take albums in turn; providers keep their rate limits across all of them.
+            // Each answer is cached as it comes, so an interrupted run picks up where it stopped.
+            let next = std::sync::atomic::AtomicUsize::new(0);
+            let done = std::sync::atomic::AtomicUsize::new(0);
+            let outcomes = std::sync::Mutex::new(Vec::new());
+            std::thread::scope(|scope| {
+                for _ in 0..jobs.min(albums.len()) {
+                    scope.spawn(|| loop {
+                        let i = next.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                        let Some(((artist, album), paths)) = albums.get(i) else {
+                            break;
+                        };
+                        let outcome = art_cache::fetch_album_art_cached("music_index.db", artist, album, paths);
+                        let status = match &outcome {
+                            Ok(Some(found)) => format!("found ({})", found.provider),
+                            Ok(None) => "missing".to_string(),
+                            Err(failures) => format!("failed ({})", album_art::describe_failures(failures)),
+                        };
+                        let n = done.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+                        println!("[{}/{}] {} - {}: {}", n, albums.len(), artist, album, status);
+                        outcomes.lock().unwrap().push((i, outcome));
+                    });
+                }
+            });
+
+            let mut outcomes = outcomes.into_inner().unwrap();
+            outcomes.sort_by_key(|(i, _)| *i);
+            let name = |i: usize| format!("{} - {}", albums[i].0 .0, albums[i].0 .1);
+            let found = outcomes.iter().filter(|(_, o)| matches!(o, Ok(Some(_)))).count();
+            let missing: Vec<String> = outcomes.iter().filter(|(_, o)| matches!(o, Ok(None))).map(|(i, _)| name(*i)).collect();
+            let failed: Vec<String> = outcomes.iter()
+                .filter_map(|(i, o)| o.as_ref().err().map(|f| format!("{}: {}", name(*i), album_art::describe_failures(f))))
+                .collect();
+            let mut summary = format!("Album art for {} albums: {} found, {} missing, {} failed.\n", albums.len(), found, missing.len(), failed.len());
+            if !missing.is_empty() {
+                summary.push_str("\nMissing (no service had a matching cover):\n");
+                for album in &missing {
+                    summary.push_str(&format!("  {}\n", album));
+                }
+            }
+            if !failed.is_empty() {
+                summary.push_str("\nFailed (run again to retry these):\n");
+                for album in &failed {
+                    summary.push_str(&format!("  {}\n", album));
+                }
+            }
+            if untagged > 0 {
+                summary.push_str(&format!("\n{} tracks without artist or album tags were skipped.\n", untagged));
+            }
+            print!("\n{}", summary);
+            if let Some(report) = flag_value(&args, "--report") {
+                match std::fs::write(report, &summary) {
+                    Ok(()) => println!("Summary written to {}", report),
+                    Err(e) => eprintln!("Failed to write {}: {}", report, e),
+                }
+            }
+        }
         "gui" => {
             let options = eframe::NativeOptions::default();
             let debug = args.iter().any(|a| a == "--debug");
@@ -37,7 +1057,7 @@ fn main() {
             );
         }
         "help" => {
-            println!("Usage: psp_playlist_maker <command> [options]\nCommands:\n  scan <music_dir>   Index music files\n  gui               View indexed tracks in GUI\n  help              Show this message");
+            println!("{}", USAGE);
         }
         _ => {
             println!("Unknown command. Use 'help' for usage.");
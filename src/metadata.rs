@@ -1,3 +1,4 @@
+use crate::http::{self, ProviderError};
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize, Clone)]
@@ -8,15 +9,41 @@ pub struct MBTrack {
     pub cover_url: Option<String>,
 }
 
-pub async fn fetch_metadata(artist: &str, album: &str, title: &str) -> Option<MBTrack> {
+#[derive(Debug, Deserialize)]
+struct RecordingSearch {
+    recordings: Vec<Recording>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Recording {
+    title: String,
+    #[serde(default, rename = "artist-credit")]
+    artist_credit: Vec<Named>,
+    #[serde(default)]
+    releases: Vec<Titled>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Named {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Titled {
+    title: String,
+}
+
+/// Search MusicBrainz at `base_url`, e.g. "https://musicbrainz.org"; `Ok(None)` when no recording matches
+pub async fn fetch_metadata(base_url: &str, artist: &str, album: &str, title: &str) -> Result<Option<MBTrack>, ProviderError> {
     let query = format!("recording:{} AND artist:{} AND release:{}", title, artist, album);
-    let url = format!("https://musicbrainz.org/ws/2/recording/?query={}&fmt=json", urlencoding::encode(&query));
-    let resp = reqwest::get(&url).await.ok()?;
-    let json: serde_json::Value = resp.json().await.ok()?;
-    let recording = json["recordings"].get(0)?;
-    let title = recording["title"].as_str()?.to_string();
-    let artist = recording["artist-credit"][0]["name"].as_str()?.to_string();
-    let album = recording["releases"][0]["title"].as_str()?.to_string();
+    let url = format!("{}/ws/2/recording/?query={}&fmt=json", base_url.trim_end_matches('/'), urlencoding::encode(&query));
+    let json: RecordingSearch = http::get_json(&http::MUSICBRAINZ, &url).await?;
+    let Some(recording) = json.recordings.into_iter().next() else {
+        return Ok(None);
+    };
+    let (Some(artist), Some(release)) = (recording.artist_credit.into_iter().next(), recording.releases.into_iter().next()) else {
+        return Ok(None);
+    };
     let cover_url = None; // For now, MusicBrainz does not provide cover art directly
-    Some(MBTrack { title, artist, album, cover_url })
+    Ok(Some(MBTrack { title: recording.title, artist: artist.name, album: release.title, cover_url }))
 }
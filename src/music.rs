@@ -6,6 +6,17 @@ pub struct Track {
     pub artist: String,
     pub album: String,
     pub title: String,
+    pub genre: String,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub duration_secs: Option<u32>,
+    pub size_bytes: u64,
+    /// Audio codec, e.g. "mp3", "aac", "flac"; empty when unknown
+    pub codec: String,
+    pub sample_rate: Option<u32>,
+    pub bitrate_kbps: Option<u32>,
+    pub channels: Option<u8>,
+    pub drm: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -35,7 +46,8 @@ impl MusicLibrary {
             .filter(|e| {
                 let path = e.path();
                 if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
-                    matches!(ext.to_lowercase().as_str(), "mp3" | "flac" | "wav" | "m4a")
+                    // Formats the PSP can't play are indexed too, so they can be flagged
+                    matches!(ext.to_lowercase().as_str(), "mp3" | "flac" | "wav" | "m4a" | "m4p" | "aac" | "ogg" | "opus" | "wma")
                 } else {
                     false
                 }
@@ -46,15 +58,10 @@ impl MusicLibrary {
                 let path = entry.path();
                 let path_str = path.display().to_string();
                 // Try to extract embedded metadata first
-                let (title, artist, album) = match crate::embedded_art::extract_metadata(&path_str) {
-                    Some((t, a, al)) => {
-                        let title = if t.is_empty() { None } else { Some(t) };
-                        let artist = if a.is_empty() { None } else { Some(a) };
-                        let album = if al.is_empty() { None } else { Some(al) };
-                        (title, artist, album)
-                    },
-                    _ => (None, None, None)
-                };
+                let embedded = crate::embedded_art::extract_metadata(&path_str).unwrap_or_default();
+                let title = if embedded.title.is_empty() { None } else { Some(embedded.title) };
+                let artist = if embedded.artist.is_empty() { None } else { Some(embedded.artist) };
+                let album = if embedded.album.is_empty() { None } else { Some(embedded.album) };
                 // Fallback to folder names if metadata missing
                 let rel_path = match path.strip_prefix(music_dir) {
                     Ok(p) => p,
@@ -72,6 +79,16 @@ impl MusicLibrary {
                     artist: artist.unwrap_or(fallback_artist),
                     album: album.unwrap_or(fallback_album),
                     title: title.unwrap_or(fallback_title),
+                    genre: embedded.genre,
+                    track_number: embedded.track_number,
+                    disc_number: embedded.disc_number,
+                    duration_secs: embedded.duration_secs,
+                    size_bytes: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                    codec: embedded.codec,
+                    sample_rate: embedded.sample_rate,
+                    bitrate_kbps: embedded.bitrate_kbps,
+                    channels: embedded.channels,
+                    drm: embedded.drm,
                 })
             }).collect();
         MusicLibrary { tracks }
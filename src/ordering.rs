@@ -0,0 +1,151 @@
+use crate::music::Track;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Field a playlist can be sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Artist,
+    Album,
+    Disc,
+    Track,
+    Title,
+    Duration,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "artist" => Ok(SortKey::Artist),
+            "album" => Ok(SortKey::Album),
+            "disc" => Ok(SortKey::Disc),
+            "track" => Ok(SortKey::Track),
+            "title" => Ok(SortKey::Title),
+            "duration" => Ok(SortKey::Duration),
+            other => Err(format!("unknown sort key '{}'", other)),
+        }
+    }
+}
+
+/// Parse a comma-separated list of sort keys, e.g. "artist,album,track"
+pub fn parse_sort_keys(s: &str) -> Result<Vec<SortKey>, String> {
+    s.split(',').filter(|k| !k.trim().is_empty()).map(SortKey::from_str).collect()
+}
+
+fn compare_by(a: &Track, b: &Track, key: SortKey) -> Ordering {
+    match key {
+        SortKey::Artist => a.artist.to_lowercase().cmp(&b.artist.to_lowercase()),
+        SortKey::Album => a.album.to_lowercase().cmp(&b.album.to_lowercase()),
+        SortKey::Disc => a.disc_number.unwrap_or(0).cmp(&b.disc_number.unwrap_or(0)),
+        SortKey::Track => a.track_number.unwrap_or(0).cmp(&b.track_number.unwrap_or(0)),
+        SortKey::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+        SortKey::Duration => a.duration_secs.unwrap_or(0).cmp(&b.duration_secs.unwrap_or(0)),
+    }
+}
+
+/// Stable sort of playlist entries (indices into `tracks`) by the given keys in priority order
+pub fn sort_entries(entries: &mut [usize], tracks: &[Track], keys: &[SortKey]) {
+    entries.sort_by(|a, b| {
+        let (ta, tb) = (&tracks[*a], &tracks[*b]);
+        keys.iter()
+            .map(|key| compare_by(ta, tb, *key))
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+/// Small deterministic PRNG (SplitMix64) so a seed always gives the same order,
+/// independent of any external crate's algorithm changes
+pub struct SeededRng(u64);
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        SeededRng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `0..n` (n must be non-zero)
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// Seed derived from the clock, for when the user doesn't pick one
+pub fn random_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// Fisher-Yates shuffle; the same seed always produces the same order
+pub fn shuffle_entries(entries: &mut [usize], seed: u64) {
+    let mut rng = SeededRng::new(seed);
+    for i in (1..entries.len()).rev() {
+        let j = rng.below(i + 1);
+        entries.swap(i, j);
+    }
+}
+
+/// Shuffle that avoids playing the same artist or album back to back where possible.
+///
+/// Each step picks, among tracks that differ in artist and album from the previous one,
+/// a track of the artist with the most tracks left, so large artists are spread out
+/// instead of piling up at the end.
+pub fn smart_shuffle_entries(entries: &mut Vec<usize>, tracks: &[Track], seed: u64) {
+    let mut shuffled = entries.clone();
+    shuffle_entries(&mut shuffled, seed);
+
+    // Lowercase each artist and album once and number them, so the loop below only compares numbers
+    let mut artist_ids: HashMap<String, usize> = HashMap::new();
+    let mut album_ids: HashMap<(usize, String), usize> = HashMap::new();
+    let mut remaining: Vec<(usize, usize, usize)> = Vec::with_capacity(shuffled.len());
+    for idx in shuffled {
+        let next = artist_ids.len();
+        let artist = *artist_ids.entry(tracks[idx].artist.to_lowercase()).or_insert(next);
+        let next = album_ids.len();
+        let album = *album_ids.entry((artist, tracks[idx].album.to_lowercase())).or_insert(next);
+        remaining.push((idx, artist, album));
+    }
+    let mut left_per_artist = vec![0usize; artist_ids.len()];
+    for (_, artist, _) in &remaining {
+        left_per_artist[*artist] += 1;
+    }
+
+    let mut ordered = Vec::with_capacity(remaining.len());
+    let mut prev: Option<(usize, usize)> = None;
+    while !remaining.is_empty() {
+        let differs_artist = |artist: usize| prev.is_none_or(|(p, _)| p != artist);
+        let differs_album = |album: usize| prev.is_none_or(|(_, p)| p != album);
+
+        // Relax the constraints when they can't be met
+        let candidates: Vec<usize> = [
+            remaining.iter().enumerate().filter(|(_, (_, artist, album))| differs_artist(*artist) && differs_album(*album)).map(|(pos, _)| pos).collect::<Vec<_>>(),
+            remaining.iter().enumerate().filter(|(_, (_, _, album))| differs_album(*album)).map(|(pos, _)| pos).collect(),
+        ]
+        .into_iter()
+        .find(|c| !c.is_empty())
+        .unwrap_or_else(|| (0..remaining.len()).collect());
+
+        // max_by_key keeps the last maximum; iterate in reverse to prefer the earliest shuffled pick
+        let pos = candidates.iter().rev()
+            .copied()
+            .max_by_key(|pos| left_per_artist[remaining[*pos].1])
+            .unwrap_or(0);
+        let (idx, artist, album) = remaining.remove(pos);
+        left_per_artist[artist] -= 1;
+        prev = Some((artist, album));
+        ordered.push(idx);
+    }
+    *entries = ordered;
+}
@@ -1,11 +1,14 @@
+use crate::fat32;
 use crate::music::Track;
 use std::fs::File;
-use std::io::{self, Write};
+use std::io::{self, BufRead, BufReader, Write};
 use std::path::Path;
 
 /// Convert a full file path to PSP-relative format
 /// Example: /mnt/psp/MUSIC/Album/song.mp3 -> /MUSIC/Album/song.mp3
-fn to_psp_path(full_path: &str) -> String {
+/// Names are made FAT32-safe the same way `sync` names the copied files; paths it already
+/// renamed come through unchanged.
+pub fn to_psp_path(full_path: &str) -> String {
     let path = Path::new(full_path);
     
     // Find the MUSIC directory in the path
@@ -19,14 +22,14 @@ fn to_psp_path(full_path: &str) -> String {
             .collect();
         
         // PSP expects normal paths with spaces and special characters as-is
-        format!("/{}", psp_components.join("/"))
+        fat32::sanitize_path(&format!("/{}", psp_components.join("/")))
     } else {
         // Fallback: just use the filename with /MUSIC/ prefix
         let filename = path.file_name()
             .unwrap_or_default()
             .to_string_lossy()
             .to_string();
-        format!("/MUSIC/{}", filename)
+        fat32::sanitize_path(&format!("/MUSIC/{}", filename))
     }
 }
 
@@ -41,3 +44,28 @@ pub fn write_m3u8(playlist_path: &str, tracks: &[&Track]) -> io::Result<()> {
     
     Ok(())
 }
+
+/// Read the entries of an M3U8 playlist, skipping comments and blank lines
+pub fn read_m3u8(playlist_path: &str) -> io::Result<Vec<String>> {
+    let file = File::open(playlist_path)?;
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            entries.push(line.to_string());
+        }
+    }
+    Ok(entries)
+}
+
+/// Map playlist entries back to indexed tracks by PSP path (case-insensitive, like FAT)
+pub fn match_entries(entries: &[String], tracks: &[Track]) -> Vec<usize> {
+    let by_path: std::collections::HashMap<String, usize> = tracks.iter()
+        .enumerate()
+        .map(|(i, t)| (to_psp_path(&t.path).to_lowercase(), i))
+        .collect();
+    entries.iter()
+        .filter_map(|e| by_path.get(&e.replace('\\', "/").to_lowercase()).copied())
+        .collect()
+}
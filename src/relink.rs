@@ -0,0 +1,241 @@
+use crate::music::Track;
+use crate::playlist;
+use crate::verify;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Proposals at or above this confidence are applied without asking
+pub const DEFAULT_MIN_CONFIDENCE: f32 = 0.8;
+
+// Evidence weights, in the order matches are searched: file name, then size, then tags
+const NAME_WEIGHT: f32 = 0.5;
+const SIZE_WEIGHT: f32 = 0.3;
+const TITLE_WEIGHT: f32 = 0.1;
+const ARTIST_WEIGHT: f32 = 0.05;
+const ALBUM_WEIGHT: f32 = 0.05;
+// Weaker matches aren't worth proposing at all
+const MIN_PROPOSAL: f32 = 0.3;
+
+/// A replacement for a playlist entry that no longer resolves on the device
+#[derive(Debug, Clone)]
+pub struct Proposal {
+    pub entry: String,
+    /// Index of the matched track
+    pub track: usize,
+    /// New playlist entry, e.g. "/MUSIC/Artist/Album/song.mp3"
+    pub replacement: String,
+    /// 0.0 to 1.0
+    pub confidence: f32,
+    /// What agreed: "file name", "size", "title", "artist", "album"
+    pub matched_on: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RelinkReport {
+    pub proposals: Vec<Proposal>,
+    /// Broken entries without any plausible match
+    pub unmatched: Vec<String>,
+}
+
+impl RelinkReport {
+    /// Proposals safe to apply without confirmation
+    pub fn confident(&self, min_confidence: f32) -> impl Iterator<Item = &Proposal> {
+        self.proposals.iter().filter(move |p| p.confidence >= min_confidence)
+    }
+
+    /// Proposals that need a human to confirm them
+    pub fn uncertain(&self, min_confidence: f32) -> impl Iterator<Item = &Proposal> {
+        self.proposals.iter().filter(move |p| p.confidence < min_confidence)
+    }
+}
+
+// What is known about the file a broken entry used to point at
+struct Reference {
+    file_name: String,
+    size: Option<u64>,
+    title: String,
+    artist: String,
+    album: String,
+}
+
+impl Reference {
+    /// Prefer a stale index row for the old path (real tags and size), else read what the path says
+    fn for_entry(entry: &str, stale: Option<&Track>) -> Self {
+        let normalized = entry.replace('\\', "/");
+        let path = Path::new(&normalized);
+        let file_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if let Some(track) = stale {
+            return Reference {
+                file_name,
+                size: Some(track.size_bytes).filter(|s| *s > 0),
+                title: title_key(track),
+                artist: normalize(&track.artist),
+                album: normalize(&track.album),
+            };
+        }
+        // "/MUSIC/<artist>/<album>/<nn title>.mp3", as sync and most rippers lay files out
+        let mut dirs = path.parent().into_iter().flat_map(|p| p.iter()).map(|c| c.to_string_lossy().to_string()).rev();
+        let album = dirs.next().filter(|d| !d.eq_ignore_ascii_case("MUSIC")).unwrap_or_default();
+        let artist = dirs.next().filter(|d| !d.eq_ignore_ascii_case("MUSIC") && d != "/").unwrap_or_default();
+        let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        Reference {
+            file_name,
+            size: None,
+            title: normalize(strip_track_number(&stem)),
+            artist: normalize(&artist),
+            album: normalize(&album),
+        }
+    }
+
+    fn score(&self, track: &Track) -> (f32, Vec<&'static str>) {
+        let mut score = 0.0;
+        let mut matched_on = Vec::new();
+        let mut check = |agrees: bool, weight: f32, label: &'static str| {
+            if agrees {
+                score += weight;
+                matched_on.push(label);
+            }
+        };
+        let name = Path::new(&track.path).file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+        check(name == self.file_name.to_lowercase(), NAME_WEIGHT, "file name");
+        check(self.size == Some(track.size_bytes), SIZE_WEIGHT, "size");
+        let agrees = |wanted: &str, value: &str| !wanted.is_empty() && wanted == normalize(value);
+        check(!self.title.is_empty() && self.title == title_key(track), TITLE_WEIGHT, "title");
+        check(agrees(&self.artist, &track.artist), ARTIST_WEIGHT, "artist");
+        check(agrees(&self.album, &track.album), ALBUM_WEIGHT, "album");
+        (score, matched_on)
+    }
+
+    // Best possible score; size only counts when the old size is known
+    fn max_score(&self) -> f32 {
+        let size = if self.size.is_some() { SIZE_WEIGHT } else { 0.0 };
+        NAME_WEIGHT + size + TITLE_WEIGHT + ARTIST_WEIGHT + ALBUM_WEIGHT
+    }
+}
+
+// Untagged tracks are titled with their file name, e.g. "02 Other.mp3"; compare just "other"
+fn title_key(track: &Track) -> String {
+    let path = Path::new(&track.path);
+    let title = match (path.file_name(), path.file_stem()) {
+        (Some(name), Some(stem)) if name.to_string_lossy() == track.title => stem.to_string_lossy().to_string(),
+        _ => track.title.clone(),
+    };
+    normalize(strip_track_number(&title))
+}
+
+// Lowercase letters and digits only, so "Don't Stop" and "dont stop" agree
+fn normalize(s: &str) -> String {
+    s.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+// "03 Title", "03 - Title", "3. Title" -> "Title"; a bare number like "1979" stays
+fn strip_track_number(stem: &str) -> &str {
+    let rest = stem.trim_start_matches(|c: char| c.is_ascii_digit());
+    if rest.len() == stem.len() || !rest.starts_with([' ', '-', '.', '_']) {
+        return stem;
+    }
+    let rest = rest.trim_start_matches([' ', '-', '.', '_']);
+    if rest.is_empty() { stem } else { rest }
+}
+
+/// Find replacements for the entries of a playlist in `playlist_dir` that don't resolve under `device_root`.
+///
+/// Candidates are indexed tracks that exist on the device. An index row still holding a
+/// broken entry's old path (rescans keep them) supplies the size and tags to match on.
+pub fn plan_relink(device_root: &Path, playlist_dir: &Path, entries: &[String], tracks: &[Track]) -> RelinkReport {
+    let mut report = RelinkReport::default();
+    let broken: Vec<&String> = entries.iter()
+        .filter(|e| verify::resolve_entry(device_root, playlist_dir, e).is_none())
+        .collect();
+    if broken.is_empty() {
+        return report;
+    }
+
+    // Indexed tracks by their PSP path; those on the device are candidates, the rest stale
+    let mut seen = HashSet::new();
+    let mut candidates = Vec::new();
+    let mut stale: HashMap<String, usize> = HashMap::new();
+    for (idx, track) in tracks.iter().enumerate() {
+        let psp_path = playlist::to_psp_path(&track.path);
+        if !seen.insert(psp_path.to_lowercase()) {
+            continue;
+        }
+        if verify::resolve_entry(device_root, playlist_dir, &psp_path).is_some() {
+            candidates.push((idx, psp_path));
+        } else {
+            stale.insert(psp_path.to_lowercase(), idx);
+        }
+    }
+
+    let mut done = HashSet::new();
+    for entry in broken {
+        if !done.insert(entry.to_lowercase()) {
+            continue;
+        }
+        let key = entry.replace('\\', "/");
+        let key = key.strip_prefix("ms0:").unwrap_or(&key).to_lowercase();
+        let reference = Reference::for_entry(entry, stale.get(&key).map(|i| &tracks[*i]));
+        let mut scored: Vec<(f32, usize, &String, Vec<&'static str>)> = candidates.iter()
+            .map(|(idx, psp_path)| {
+                let (score, matched_on) = reference.score(&tracks[*idx]);
+                (score, *idx, psp_path, matched_on)
+            })
+            .filter(|(score, ..)| *score > 0.0)
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let Some((best, idx, psp_path, matched_on)) = scored.first().cloned() else {
+            report.unmatched.push(entry.clone());
+            continue;
+        };
+        let mut confidence = best / reference.max_score();
+        // A tie means the evidence can't tell the files apart
+        if scored.get(1).is_some_and(|second| second.0 >= best) {
+            confidence /= 2.0;
+        }
+        if confidence < MIN_PROPOSAL {
+            report.unmatched.push(entry.clone());
+            continue;
+        }
+        report.proposals.push(Proposal {
+            entry: entry.clone(),
+            track: idx,
+            replacement: psp_path.clone(),
+            confidence,
+            matched_on,
+        });
+    }
+    report
+}
+
+/// Read a playlist on the device and find replacements for its broken entries
+pub fn relink_playlist(device_root: &Path, playlist_path: &Path, tracks: &[Track]) -> io::Result<RelinkReport> {
+    let entries = playlist::read_m3u8(&playlist_path.to_string_lossy())?;
+    let playlist_dir = playlist_path.parent().unwrap_or(device_root);
+    Ok(plan_relink(device_root, playlist_dir, &entries, tracks))
+}
+
+/// Rewrite the given entries in a playlist file, keeping comments and everything else as they were.
+/// Returns how many lines changed.
+pub fn apply(playlist_path: &Path, proposals: &[&Proposal]) -> io::Result<usize> {
+    let replacements: HashMap<&str, &str> = proposals.iter()
+        .map(|p| (p.entry.as_str(), p.replacement.as_str()))
+        .collect();
+    let content = fs::read_to_string(playlist_path)?;
+    let mut changed = 0;
+    let mut out = String::with_capacity(content.len());
+    for line in content.lines() {
+        let entry = line.trim_start_matches('\u{feff}').trim();
+        match replacements.get(entry) {
+            Some(replacement) => {
+                out.push_str(&line.replace(entry, replacement));
+                changed += 1;
+            }
+            None => out.push_str(line),
+        }
+        out.push('\n');
+    }
+    fs::write(playlist_path, out)?;
+    Ok(changed)
+}
@@ -0,0 +1,310 @@
+use crate::compat;
+use crate::fat32::{Fat32Sanitizer, PathChange};
+use crate::music::Track;
+use crate::template;
+use crate::transcode::Transcoder;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Where copied tracks go under MUSIC; fields: artist, album, title, track, disc, genre, filename, ext
+pub const DEFAULT_LAYOUT: &str = "{artist}/{album}/{track:02} {title}.{ext}";
+
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    pub layout: String,
+    pub dry_run: bool,
+    /// Compare content hashes, not just sizes, before skipping an existing file
+    pub verify_hash: bool,
+    /// Convert tracks the PSP can't play instead of copying them as they are
+    pub transcoder: Option<Arc<Transcoder>>,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        SyncOptions { layout: DEFAULT_LAYOUT.to_string(), dry_run: false, verify_hash: false, transcoder: None }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncAction {
+    Copy,
+    /// Same file already on the device
+    Skip,
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct SyncItem {
+    /// Index into the source track list
+    pub track: usize,
+    pub source: PathBuf,
+    pub dest: PathBuf,
+    /// Bytes written to the device; estimated for conversions that haven't run yet
+    pub size: u64,
+    pub action: SyncAction,
+    /// Converted before copying, see [`SyncOptions::transcoder`]
+    pub transcode: bool,
+}
+
+/// Progress callback payload, sent before each file is handled
+pub struct SyncProgress<'a> {
+    pub done: usize,
+    pub total: usize,
+    pub item: &'a SyncItem,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SyncReport {
+    pub items: Vec<SyncItem>,
+    /// Device paths (from the device root) renamed to be FAT32-safe
+    pub renamed: Vec<PathChange>,
+}
+
+impl SyncReport {
+    fn count(&self, pred: impl Fn(&SyncAction) -> bool) -> usize {
+        self.items.iter().filter(|i| pred(&i.action)).count()
+    }
+
+    pub fn copied(&self) -> usize {
+        self.count(|a| *a == SyncAction::Copy)
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.count(|a| *a == SyncAction::Skip)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|a| matches!(a, SyncAction::Failed(_)))
+    }
+
+    pub fn bytes_copied(&self) -> u64 {
+        self.items.iter().filter(|i| i.action == SyncAction::Copy).map(|i| i.size).sum()
+    }
+
+    /// Source tracks re-pointed at their copies on the device, for rewriting playlists
+    pub fn device_tracks(&self, tracks: &[Track], entries: &[usize]) -> Vec<Track> {
+        let dest_by_track: HashMap<usize, &PathBuf> = self.items.iter()
+            .filter(|i| !matches!(i.action, SyncAction::Failed(_)))
+            .map(|i| (i.track, &i.dest))
+            .collect();
+        entries.iter()
+            .filter_map(|idx| {
+                let dest = dest_by_track.get(idx)?;
+                let mut track = tracks[*idx].clone();
+                track.path = dest.display().to_string();
+                Some(track)
+            })
+            .collect()
+    }
+}
+
+/// Device path for a track under `music_dir`, following the layout template
+pub fn layout_path(track: &Track, music_dir: &Path, layout: &str) -> PathBuf {
+    let source = Path::new(&track.path);
+    let ext = source.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let filename = source.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    // Untagged tracks get the file name as title; don't carry its extension over
+    let title = match source.file_name() {
+        Some(name) if name.to_string_lossy() == track.title => filename.clone(),
+        _ => track.title.clone(),
+    };
+    let or_unknown = |value: &str, unknown: &str| if value.trim().is_empty() { unknown.to_string() } else { value.to_string() };
+    let rendered = template::render(layout, |field| {
+        let value = match field {
+            "artist" => or_unknown(&track.artist, "Unknown Artist"),
+            "album" => or_unknown(&track.album, "Unknown Album"),
+            "title" => or_unknown(&title, &filename),
+            "track" => track.track_number.map(|n| n.to_string()).unwrap_or_default(),
+            "disc" => track.disc_number.map(|n| n.to_string()).unwrap_or_default(),
+            "genre" => track.genre.clone(),
+            "filename" => filename.clone(),
+            "ext" => ext.clone(),
+            _ => return None,
+        };
+        Some(template::component(&value))
+    });
+    // The template's own slashes separate directories; drop empty parts like a missing {album}
+    rendered.split('/')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .fold(music_dir.to_path_buf(), |path, part| path.join(part))
+}
+
+/// Work out where each selected track goes and whether it needs copying
+pub fn plan_sync(tracks: &[Track], selection: &[usize], music_dir: &Path, options: &SyncOptions) -> SyncReport {
+    let mut used = HashSet::new();
+    let mut sanitizer = Fat32Sanitizer::new();
+    let device_root = music_dir.parent().unwrap_or(music_dir);
+    let mut items = Vec::new();
+    for &idx in selection {
+        let track = &tracks[idx];
+        let source = PathBuf::from(&track.path);
+        let transcoder = options.transcoder.as_ref().filter(|_| !compat::is_compatible(track));
+        let mut dest = layout_path(track, music_dir, &options.layout);
+        if let Some(transcoder) = transcoder {
+            dest.set_extension(transcoder.encoder().extension());
+        }
+        // Two tracks rendering to the same name (e.g. same title, no track number)
+        let base = dest.clone();
+        let mut n = 2;
+        while !used.insert(dest.to_string_lossy().to_lowercase()) {
+            let stem = base.with_extension("");
+            dest = PathBuf::from(format!("{} ({})", stem.display(), n));
+            if let Some(ext) = base.extension() {
+                dest.set_extension(ext);
+            }
+            n += 1;
+        }
+        if let Ok(relative) = dest.strip_prefix(device_root) {
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            dest = device_root.join(sanitizer.sanitize(&relative));
+        }
+        let (size, action) = match transcoder {
+            // Compare against an earlier conversion; without one there is nothing to skip on
+            Some(transcoder) => match transcoder.cached(&source) {
+                Some(cached) => {
+                    let size = fs::metadata(&cached).map(|m| m.len()).unwrap_or(0);
+                    (size, present_action(&cached, &dest, size, options.verify_hash))
+                }
+                None => {
+                    let estimate = track.duration_secs.map(|d| transcoder.encoder().estimate_size(d));
+                    (estimate.unwrap_or(track.size_bytes), SyncAction::Copy)
+                }
+            },
+            None => {
+                let size = fs::metadata(&source).map(|m| m.len()).unwrap_or(track.size_bytes);
+                (size, present_action(&source, &dest, size, options.verify_hash))
+            }
+        };
+        items.push(SyncItem { track: idx, source, dest, size, action, transcode: transcoder.is_some() });
+    }
+    SyncReport { items, renamed: sanitizer.changes().to_vec() }
+}
+
+/// Copy the selected tracks into `music_dir`, reporting progress per file.
+/// With `dry_run` nothing is written and the report shows what would happen.
+pub fn sync_tracks(
+    tracks: &[Track],
+    selection: &[usize],
+    music_dir: &Path,
+    options: &SyncOptions,
+    mut progress: impl FnMut(SyncProgress),
+) -> SyncReport {
+    let mut report = plan_sync(tracks, selection, music_dir, options);
+    let total = report.items.len();
+    for (done, item) in report.items.iter_mut().enumerate() {
+        progress(SyncProgress { done, total, item });
+        if options.dry_run || item.action != SyncAction::Copy {
+            continue;
+        }
+        let copied = match options.transcoder.as_ref().filter(|_| item.transcode) {
+            Some(transcoder) => transcoder.transcode(&item.source).and_then(|converted| {
+                item.size = fs::metadata(&converted)?.len();
+                copy_file(&converted, &item.dest)
+            }),
+            None => copy_file(&item.source, &item.dest),
+        };
+        if let Err(e) = copied {
+            eprintln!("[Sync] Failed to copy {}: {}", item.source.display(), e);
+            item.action = SyncAction::Failed(e.to_string());
+        }
+    }
+    report
+}
+
+fn copy_file(source: &Path, dest: &Path) -> io::Result<()> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Copy to a temporary name first so an unplugged stick never keeps a half-written track
+    let mut partial = dest.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    fs::copy(source, &partial)?;
+    fs::rename(&partial, dest)
+}
+
+fn present_action(source: &Path, dest: &Path, size: u64, verify_hash: bool) -> SyncAction {
+    match already_present(source, dest, size, verify_hash) {
+        Ok(true) => SyncAction::Skip,
+        Ok(false) => SyncAction::Copy,
+        Err(e) => SyncAction::Failed(e.to_string()),
+    }
+}
+
+fn already_present(source: &Path, dest: &Path, size: u64, verify_hash: bool) -> io::Result<bool> {
+    match fs::metadata(dest) {
+        Ok(meta) if meta.len() == size => {
+            if verify_hash {
+                Ok(file_hash(source)? == file_hash(dest)?)
+            } else {
+                Ok(true)
+            }
+        }
+        Ok(_) => Ok(false),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Stable 64-bit FNV-1a hash of a file's contents
+pub fn file_hash(path: &Path) -> io::Result<u64> {
+    let mut hasher = Fnv1a::default();
+    let mut file = File::open(path)?;
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.write(&buf[..n]);
+    }
+    Ok(hasher.finish())
+}
+
+// std's DefaultHasher may change between releases; FNV-1a stays comparable over time
+pub(crate) struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Resolve a PC-side playlist's entries (absolute or relative to the playlist) to source tracks.
+/// Returns the matched indices and the entries that couldn't be found.
+pub fn resolve_source_playlist(playlist_path: &Path, tracks: &[Track]) -> io::Result<(Vec<usize>, Vec<String>)> {
+    let entries = crate::playlist::read_m3u8(&playlist_path.to_string_lossy())?;
+    let base = playlist_path.parent().unwrap_or(Path::new("."));
+    let by_path: HashMap<PathBuf, usize> = tracks.iter()
+        .enumerate()
+        .filter_map(|(i, t)| Some((fs::canonicalize(&t.path).ok()?, i)))
+        .collect();
+    let mut matched = Vec::new();
+    let mut missing = Vec::new();
+    for entry in entries {
+        let path = base.join(entry.replace('\\', "/"));
+        match fs::canonicalize(&path).ok().and_then(|p| by_path.get(&p)) {
+            Some(idx) => matched.push(*idx),
+            None => missing.push(entry),
+        }
+    }
+    Ok((matched, missing))
+}
@@ -0,0 +1,170 @@
+use crate::db;
+use lofty::config::WriteOptions;
+use lofty::prelude::*;
+use lofty::probe::Probe;
+use lofty::id3::v2::Id3v2Tag;
+use lofty::tag::{Tag, TagType};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+
+/// The tag fields we write; empty strings and `None` mean the field is absent
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagValues {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub album_artist: String,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub year: Option<u32>,
+    pub genre: String,
+}
+
+/// One write to a file's tags, as logged in the index database
+#[derive(Debug, Clone)]
+pub struct TagEdit {
+    pub id: i64,
+    pub path: String,
+    /// Seconds since the Unix epoch
+    pub edited_at: u64,
+    /// Tags as they were, restored by an undo
+    pub before: TagValues,
+    pub after: TagValues,
+    pub undone: bool,
+}
+
+fn to_io(e: lofty::error::LoftyError) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+fn read_tag(path: &Path) -> io::Result<Tag> {
+    let tagged = Probe::open(path).map_err(to_io)?.guess_file_type()?.read().map_err(to_io)?;
+    Ok(tagged.primary_tag()
+        .or_else(|| tagged.first_tag())
+        .cloned()
+        .unwrap_or_else(|| Tag::new(tagged.primary_tag_type())))
+}
+
+// Year from a year field or the start of a date like "1999-04-01"
+fn year(tag: &Tag) -> Option<u32> {
+    tag.year().or_else(|| {
+        let date = tag.get_string(&ItemKey::RecordingDate)?;
+        date.get(..4)?.parse().ok()
+    })
+}
+
+/// Current values of the fields we write
+pub fn read_tags(path: &Path) -> io::Result<TagValues> {
+    let tag = read_tag(path)?;
+    let text = |s: Option<std::borrow::Cow<str>>| s.map(|s| s.to_string()).unwrap_or_default();
+    Ok(TagValues {
+        title: text(tag.title()),
+        artist: text(tag.artist()),
+        album: text(tag.album()),
+        album_artist: tag.get_string(&ItemKey::AlbumArtist).unwrap_or_default().to_string(),
+        track_number: tag.track(),
+        disc_number: tag.disk(),
+        year: year(&tag),
+        genre: text(tag.genre()),
+    })
+}
+
+/// Write `values` into the file's main tag, leaving everything else in it (pictures, comments) alone.
+/// MP3 tags are saved as ID3v2.3, which the PSP reads.
+pub fn write_tags(path: &Path, values: &TagValues) -> io::Result<()> {
+    let mut tag = read_tag(path)?;
+    let set_text = |tag: &mut Tag, key: ItemKey, value: &str| {
+        if value.is_empty() {
+            tag.remove_key(&key);
+        } else {
+            tag.insert_text(key, value.to_string());
+        }
+    };
+    set_text(&mut tag, ItemKey::TrackTitle, &values.title);
+    set_text(&mut tag, ItemKey::TrackArtist, &values.artist);
+    set_text(&mut tag, ItemKey::AlbumTitle, &values.album);
+    set_text(&mut tag, ItemKey::AlbumArtist, &values.album_artist);
+    set_text(&mut tag, ItemKey::Genre, &values.genre);
+    match values.track_number {
+        Some(n) => tag.set_track(n),
+        None => tag.remove_track(),
+    }
+    match values.disc_number {
+        Some(n) => tag.set_disk(n),
+        None => tag.remove_disk(),
+    }
+    // Kept as a recording date, which ends up as TYER in ID3v2.3
+    tag.remove_key(&ItemKey::Year);
+    match values.year {
+        Some(year) => {
+            tag.insert_text(ItemKey::RecordingDate, year.to_string());
+        }
+        None => tag.remove_key(&ItemKey::RecordingDate),
+    }
+    save_for_psp(tag, path)
+}
+
+/// Save a tag in a form the PSP reads: ID3v2 tags go out as ID3v2.3
+pub(crate) fn save_for_psp(mut tag: Tag, path: &Path) -> io::Result<()> {
+    // lofty writes an unreadable ID3v2.3 APIC frame for a picture without a description
+    let pictures = tag.pictures().to_vec();
+    for (i, mut picture) in pictures.into_iter().enumerate() {
+        if picture.description().is_none() {
+            picture.set_description(Some(String::new()));
+            tag.set_picture(i, picture);
+        }
+    }
+    let options = WriteOptions::new().use_id3v23(true);
+    if tag.tag_type() == TagType::Id3v2 {
+        // Converting first turns dates into timestamp frames; saved as plain text they'd be dropped from ID3v2.3
+        let id3: Id3v2Tag = tag.into();
+        return id3.save_to_path(path, options).map_err(to_io);
+    }
+    tag.save_to_path(path, options).map_err(to_io)
+}
+
+/// Write new tags to a file, log the edit for undo and update the index in one step.
+/// If the index can't be updated the file gets its old tags back. Returns the edit's id.
+pub fn apply_edit(conn: &mut Connection, path: &Path, after: &TagValues) -> io::Result<i64> {
+    let before = read_tags(path)?;
+    write_tags(path, after)?;
+    match db::record_tag_edit(conn, &path.to_string_lossy(), &before, after) {
+        Ok(id) => {
+            eprintln!("[Tags] Wrote tags of {:?} (edit {})", path, id);
+            Ok(id)
+        }
+        Err(e) => {
+            write_tags(path, &before)?;
+            Err(io::Error::other(format!("index not updated, tags restored: {}", e)))
+        }
+    }
+}
+
+/// Put back the tags a logged edit replaced, and update the index to match
+pub fn undo_edit(conn: &mut Connection, id: i64) -> io::Result<TagEdit> {
+    let edit = db::load_tag_edit(conn, id)
+        .map_err(|e| io::Error::other(e.to_string()))?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no tag edit {}", id)))?;
+    if edit.undone {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("edit {} was already undone", id)));
+    }
+    // Restoring an older snapshot would silently drop the later edits of the file
+    let later = db::load_tag_edits(conn, Some(&edit.path), None)
+        .map_err(|e| io::Error::other(e.to_string()))?
+        .into_iter()
+        .find(|e| !e.undone && e.id > id);
+    if let Some(later) = later {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("undo the later edit {} of this file first", later.id)));
+    }
+    let path = Path::new(&edit.path);
+    let current = read_tags(path)?;
+    write_tags(path, &edit.before)?;
+    if let Err(e) = db::mark_tag_edit_undone(conn, &edit) {
+        write_tags(path, &current)?;
+        return Err(io::Error::other(format!("index not updated, tags left as they were: {}", e)));
+    }
+    eprintln!("[Tags] Undid edit {} of {:?}", id, path);
+    Ok(edit)
+}
@@ -0,0 +1,37 @@
+/// Expand `{field}` placeholders in a file/path template.
+///
+/// A numeric field may carry a zero-pad width, e.g. `{track:02}`. Fields the
+/// lookup doesn't know are left as written so typos stay visible.
+pub fn render(template: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+        let placeholder = &rest[start + 1..start + len];
+        let (field, width) = match placeholder.split_once(':') {
+            Some((field, spec)) => (field, spec.trim_start_matches('0').parse::<usize>().ok()),
+            None => (placeholder, None),
+        };
+        match lookup(field) {
+            Some(value) => match width {
+                Some(width) if !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()) => {
+                    out.push_str(&format!("{:0>width$}", value, width = width));
+                }
+                _ => out.push_str(&value),
+            },
+            None => out.push_str(&rest[start..=start + len]),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Make a substituted value safe to use as a single path component
+pub fn component(value: &str) -> String {
+    value.replace(['/', '\\'], "-").trim().to_string()
+}
@@ -0,0 +1,410 @@
+use crate::sync;
+use lofty::file::TaggedFileExt;
+use lofty::probe::Probe;
+use lofty::tag::TagType;
+use std::fmt;
+use std::fs;
+use std::hash::Hasher;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::UNIX_EPOCH;
+
+/// Bitrate of transcoded MP3s unless configured otherwise
+pub const DEFAULT_BITRATE_KBPS: u32 = 256;
+
+/// A way of turning a track the PSP can't play into one it can
+pub trait Encoder: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Extension of the files written, without the dot
+    fn extension(&self) -> &'static str;
+    /// Whether the tools this encoder needs are installed
+    fn available(&self) -> bool;
+    /// Convert `source` into a new file at `dest`; tags are copied separately
+    fn encode(&self, source: &Path, dest: &Path) -> io::Result<()>;
+    /// Expected output size for a track of this length
+    fn estimate_size(&self, duration_secs: u32) -> u64;
+}
+
+/// MP3 through a local `ffmpeg`; reads anything ffmpeg can decode
+pub struct FfmpegEncoder {
+    pub bitrate_kbps: u32,
+}
+
+impl Encoder for FfmpegEncoder {
+    fn name(&self) -> &'static str {
+        "ffmpeg"
+    }
+
+    fn extension(&self) -> &'static str {
+        "mp3"
+    }
+
+    fn available(&self) -> bool {
+        tool_available("ffmpeg", "-version")
+    }
+
+    fn encode(&self, source: &Path, dest: &Path) -> io::Result<()> {
+        let status = Command::new("ffmpeg")
+            .args(["-nostdin", "-loglevel", "error", "-y", "-i"])
+            .arg(source)
+            .args(["-map", "0:a:0", "-map_metadata", "-1", "-c:a", "libmp3lame", "-ar", "44100", "-ac", "2", "-f", "mp3"])
+            .args(["-b:a", &format!("{}k", self.bitrate_kbps)])
+            .arg(dest)
+            .status()?;
+        check_status("ffmpeg", status)
+    }
+
+    fn estimate_size(&self, duration_secs: u32) -> u64 {
+        duration_secs as u64 * self.bitrate_kbps as u64 * 1000 / 8
+    }
+}
+
+/// MP3 through a local `lame`, decoding FLAC with `flac` first (lame itself only reads WAV/AIFF/MP3)
+pub struct LameEncoder {
+    pub bitrate_kbps: u32,
+}
+
+impl Encoder for LameEncoder {
+    fn name(&self) -> &'static str {
+        "lame"
+    }
+
+    fn extension(&self) -> &'static str {
+        "mp3"
+    }
+
+    fn available(&self) -> bool {
+        tool_available("lame", "--version")
+    }
+
+    fn encode(&self, source: &Path, dest: &Path) -> io::Result<()> {
+        let mut lame = Command::new("lame");
+        lame.args(["--silent", "--resample", "44.1", "-m", "j", "-b", &self.bitrate_kbps.to_string()]);
+        match extension(source).as_str() {
+            "wav" | "aif" | "aiff" | "mp3" => check_status("lame", lame.arg(source).arg(dest).status()?),
+            "flac" => {
+                let wav = decode_flac(source)?;
+                let mut child = lame.arg("-").arg(dest).stdin(Stdio::piped()).spawn()?;
+                child.stdin.take().expect("piped stdin").write_all(&wav)?;
+                check_status("lame", child.wait()?)
+            }
+            other => Err(io::Error::new(io::ErrorKind::Unsupported, format!("lame can't read .{} files; install ffmpeg", other))),
+        }
+    }
+
+    fn estimate_size(&self, duration_secs: u32) -> u64 {
+        duration_secs as u64 * self.bitrate_kbps as u64 * 1000 / 8
+    }
+}
+
+/// 44.1 kHz 16-bit stereo WAV, converted here without an encoder.
+/// Reads WAV, and FLAC when the `flac` tool is installed.
+pub struct WavEncoder;
+
+impl Encoder for WavEncoder {
+    fn name(&self) -> &'static str {
+        "wav"
+    }
+
+    fn extension(&self) -> &'static str {
+        "wav"
+    }
+
+    fn available(&self) -> bool {
+        true
+    }
+
+    fn encode(&self, source: &Path, dest: &Path) -> io::Result<()> {
+        let data = fs::read(source)?;
+        // Go by content: a WAV may carry another extension
+        let data = match extension(source).as_str() {
+            _ if data.starts_with(b"RIFF") => data,
+            "flac" => decode_flac(source)?,
+            other => return Err(io::Error::new(io::ErrorKind::Unsupported, format!("can't convert .{} files without ffmpeg", other))),
+        };
+        let pcm = wav::Pcm::parse(&data)?;
+        fs::write(dest, pcm.to_psp_wav())
+    }
+
+    fn estimate_size(&self, duration_secs: u32) -> u64 {
+        // 44100 Hz * 2 channels * 2 bytes
+        duration_secs as u64 * 176_400
+    }
+}
+
+/// Encoders by name, in the order tried when none is chosen
+pub fn encoder_by_name(name: &str, bitrate_kbps: u32) -> Option<Box<dyn Encoder>> {
+    match name {
+        "ffmpeg" => Some(Box::new(FfmpegEncoder { bitrate_kbps })),
+        "lame" => Some(Box::new(LameEncoder { bitrate_kbps })),
+        "wav" => Some(Box::new(WavEncoder)),
+        _ => None,
+    }
+}
+
+/// The first installed encoder: ffmpeg, then lame, then the built-in WAV conversion
+pub fn default_encoder(bitrate_kbps: u32) -> Box<dyn Encoder> {
+    ["ffmpeg", "lame"].iter()
+        .filter_map(|name| encoder_by_name(name, bitrate_kbps))
+        .find(|e| e.available())
+        .unwrap_or_else(|| Box::new(WavEncoder))
+}
+
+fn extension(path: &Path) -> String {
+    path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+fn tool_available(tool: &str, version_flag: &str) -> bool {
+    Command::new(tool)
+        .arg(version_flag)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+fn check_status(tool: &str, status: std::process::ExitStatus) -> io::Result<()> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{} failed ({})", tool, status)))
+    }
+}
+
+// FLAC decoded to WAV bytes by the reference `flac` tool
+fn decode_flac(source: &Path) -> io::Result<Vec<u8>> {
+    let output = Command::new("flac")
+        .args(["--decode", "--stdout", "--silent"])
+        .arg(source)
+        .output()
+        .map_err(|e| io::Error::new(e.kind(), format!("decoding FLAC needs the flac tool or ffmpeg: {}", e)))?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!("flac failed ({})", output.status)));
+    }
+    Ok(output.stdout)
+}
+
+/// Converts tracks with an [`Encoder`], keeping finished files in a cache keyed by the
+/// source's content hash so unchanged tracks are never converted twice
+pub struct Transcoder {
+    encoder: Box<dyn Encoder>,
+    cache_dir: PathBuf,
+}
+
+impl fmt::Debug for Transcoder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Transcoder")
+            .field("encoder", &self.encoder.name())
+            .field("cache_dir", &self.cache_dir)
+            .finish()
+    }
+}
+
+impl Transcoder {
+    pub fn new(encoder: Box<dyn Encoder>, cache_dir: impl Into<PathBuf>) -> Self {
+        Transcoder { encoder, cache_dir: cache_dir.into() }
+    }
+
+    pub fn encoder(&self) -> &dyn Encoder {
+        self.encoder.as_ref()
+    }
+
+    /// Where the converted file for `source` is cached
+    pub fn cache_path(&self, source: &Path) -> io::Result<PathBuf> {
+        let hash = sync::file_hash(source)?;
+        Ok(self.cache_dir.join(format!("{:016x}-{}.{}", hash, self.encoder.name(), self.encoder.extension())))
+    }
+
+    // Records which cache entry a source converted to, named by the source's path, size and
+    // modification time so finding it again doesn't read the whole file
+    fn stamp_path(&self, source: &Path) -> io::Result<PathBuf> {
+        let meta = fs::metadata(source)?;
+        let modified = meta.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut hasher = sync::Fnv1a::default();
+        hasher.write(source.to_string_lossy().as_bytes());
+        hasher.write_u64(meta.len());
+        hasher.write_u128(modified.as_nanos());
+        Ok(self.cache_dir.join(format!("{:016x}-{}.source", hasher.finish(), self.encoder.name())))
+    }
+
+    /// The cached conversion of `source`, if it was converted before and hasn't changed since.
+    /// Only looks at the file's size and modification time, never its contents.
+    pub fn cached(&self, source: &Path) -> Option<PathBuf> {
+        let name = fs::read_to_string(self.stamp_path(source).ok()?).ok()?;
+        Some(self.cache_dir.join(name.trim())).filter(|p| p.is_file())
+    }
+
+    /// Convert `source` (or reuse the cached result), with its tags and cover art copied over
+    pub fn transcode(&self, source: &Path) -> io::Result<PathBuf> {
+        if let Some(cached) = self.cached(source) {
+            return Ok(cached);
+        }
+        let cached = self.cache_path(source)?;
+        if !cached.is_file() {
+            fs::create_dir_all(&self.cache_dir)?;
+            // Work on a temporary name so an interrupted run never leaves a truncated cache entry
+            let partial = cached.with_extension(format!("part.{}", self.encoder.extension()));
+            let result = self.encoder.encode(source, &partial).and_then(|_| copy_tags(source, &partial));
+            if let Err(e) = result {
+                let _ = fs::remove_file(&partial);
+                return Err(e);
+            }
+            fs::rename(&partial, &cached)?;
+            eprintln!("[Transcode] {} -> {}", source.display(), cached.display());
+        }
+        // Without the stamp the next lookup hashes the file again, which is slower but still right
+        if let Some(name) = cached.file_name() {
+            if let Err(e) = self.stamp_path(source).and_then(|stamp| fs::write(stamp, name.to_string_lossy().as_bytes())) {
+                eprintln!("[Transcode] Could not remember the conversion of {}: {}", source.display(), e);
+            }
+        }
+        Ok(cached)
+    }
+
+    /// Put a converted copy next to `source` (same name, new extension) and return its path.
+    /// A copy already there is reused.
+    pub fn transcode_beside(&self, source: &Path) -> io::Result<PathBuf> {
+        let ext = self.encoder.extension();
+        let mut dest = source.with_extension(ext);
+        if dest == source {
+            let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+            dest = source.with_file_name(format!("{} (PSP).{}", stem, ext));
+        }
+        if !dest.is_file() {
+            let converted = self.transcode(source)?;
+            fs::copy(converted, &dest)?;
+        }
+        Ok(dest)
+    }
+}
+
+/// Copy the source's tags, cover art included, into the converted file as ID3v2.3, which the PSP reads
+fn copy_tags(source: &Path, dest: &Path) -> io::Result<()> {
+    let to_io = |e: lofty::error::LoftyError| io::Error::other(e.to_string());
+    let tagged = Probe::open(source).map_err(to_io)?.read().map_err(to_io)?;
+    let Some(tag) = tagged.primary_tag().or_else(|| tagged.first_tag()) else {
+        return Ok(());
+    };
+    let mut tag = tag.clone();
+    tag.re_map(TagType::Id3v2);
+    crate::tag_writer::save_for_psp(tag, dest)
+}
+
+// Just enough WAV handling to bring any PCM or float WAV down to what the PSP plays
+mod wav {
+    use std::io;
+
+    const PSP_RATE: u32 = 44_100;
+
+    /// Decoded audio as -1.0..1.0 samples per channel
+    pub struct Pcm {
+        rate: u32,
+        channels: Vec<Vec<f32>>,
+    }
+
+    fn invalid(msg: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, format!("WAV: {}", msg))
+    }
+
+    fn u16_at(b: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([b[at], b[at + 1]])
+    }
+
+    fn u32_at(b: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([b[at], b[at + 1], b[at + 2], b[at + 3]])
+    }
+
+    impl Pcm {
+        pub fn parse(bytes: &[u8]) -> io::Result<Pcm> {
+            if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+                return Err(invalid("not a RIFF/WAVE file"));
+            }
+            let mut format = None;
+            let mut data: Option<&[u8]> = None;
+            let mut at = 12;
+            while at + 8 <= bytes.len() {
+                let id = &bytes[at..at + 4];
+                let len = u32_at(bytes, at + 4) as usize;
+                // Decoders writing to a pipe leave the data length at 0 or 0xFFFFFFFF
+                let end = (at + 8).saturating_add(len).min(bytes.len());
+                let body = &bytes[at + 8..end];
+                match id {
+                    b"fmt " if body.len() >= 16 => {
+                        let mut tag = u16_at(body, 0);
+                        // WAVE_FORMAT_EXTENSIBLE keeps the real format in its sub-format GUID
+                        if tag == 0xFFFE && body.len() >= 26 {
+                            tag = u16_at(body, 24);
+                        }
+                        format = Some((tag, u16_at(body, 2), u32_at(body, 4), u16_at(body, 14)));
+                    }
+                    b"data" => {
+                        data = Some(if len == 0 || len == u32::MAX as usize { &bytes[at + 8..] } else { body });
+                        break;
+                    }
+                    _ => {}
+                }
+                at = end + (len & 1);
+            }
+            let (tag, channel_count, rate, bits) = format.ok_or_else(|| invalid("missing fmt chunk"))?;
+            let data = data.ok_or_else(|| invalid("missing data chunk"))?;
+            if channel_count == 0 || rate == 0 {
+                return Err(invalid("no channels"));
+            }
+            let width = (bits as usize).div_ceil(8);
+            let decode: fn(&[u8]) -> f32 = match (tag, width) {
+                (1, 1) => |s| (s[0] as f32 - 128.0) / 128.0,
+                (1, 2) => |s| i16::from_le_bytes([s[0], s[1]]) as f32 / 32768.0,
+                (1, 3) => |s| (i32::from_le_bytes([0, s[0], s[1], s[2]]) >> 8) as f32 / 8_388_608.0,
+                (1, 4) => |s| i32::from_le_bytes([s[0], s[1], s[2], s[3]]) as f32 / 2_147_483_648.0,
+                (3, 4) => |s| f32::from_le_bytes([s[0], s[1], s[2], s[3]]),
+                (3, 8) => |s| f64::from_le_bytes([s[0], s[1], s[2], s[3], s[4], s[5], s[6], s[7]]) as f32,
+                _ => return Err(invalid(&format!("unsupported sample format {} with {} bits", tag, bits))),
+            };
+            let frame = width * channel_count as usize;
+            let mut channels = vec![Vec::with_capacity(data.len() / frame); channel_count as usize];
+            for chunk in data.chunks_exact(frame) {
+                for (channel, sample) in channels.iter_mut().zip(chunk.chunks_exact(width)) {
+                    channel.push(decode(sample));
+                }
+            }
+            Ok(Pcm { rate, channels })
+        }
+
+        /// 44.1 kHz, 16-bit, stereo: mono is doubled, surround keeps the front pair
+        pub fn to_psp_wav(&self) -> Vec<u8> {
+            let left = &self.channels[0];
+            let right = self.channels.get(1).unwrap_or(left);
+            let frames = (left.len() as u64 * PSP_RATE as u64 / self.rate as u64) as usize;
+            let step = self.rate as f64 / PSP_RATE as f64;
+            // Linear interpolation between neighbouring source frames
+            let sample = |channel: &[f32], i: usize| -> i16 {
+                let pos = i as f64 * step;
+                let idx = pos as usize;
+                let frac = (pos - idx as f64) as f32;
+                let a = channel.get(idx).copied().unwrap_or(0.0);
+                let b = channel.get(idx + 1).copied().unwrap_or(a);
+                ((a + (b - a) * frac) * 32768.0).clamp(-32768.0, 32767.0) as i16
+            };
+            let data_len = frames * 4;
+            let mut out = Vec::with_capacity(44 + data_len);
+            out.extend_from_slice(b"RIFF");
+            out.extend_from_slice(&(36 + data_len as u32).to_le_bytes());
+            out.extend_from_slice(b"WAVEfmt ");
+            out.extend_from_slice(&16u32.to_le_bytes());
+            out.extend_from_slice(&1u16.to_le_bytes());
+            out.extend_from_slice(&2u16.to_le_bytes());
+            out.extend_from_slice(&PSP_RATE.to_le_bytes());
+            out.extend_from_slice(&(PSP_RATE * 4).to_le_bytes());
+            out.extend_from_slice(&4u16.to_le_bytes());
+            out.extend_from_slice(&16u16.to_le_bytes());
+            out.extend_from_slice(b"data");
+            out.extend_from_slice(&(data_len as u32).to_le_bytes());
+            for i in 0..frames {
+                out.extend_from_slice(&sample(left, i).to_le_bytes());
+                out.extend_from_slice(&sample(right, i).to_le_bytes());
+            }
+            out
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use crate::compat::{self, Issue};
+use crate::playlist;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryStatus {
+    /// Found on the device, at this path
+    Ok(PathBuf),
+    Missing,
+    Unreadable(String),
+    /// Present, but the PSP can't play it
+    Unsupported(String),
+}
+
+impl EntryStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            EntryStatus::Ok(_) => "ok",
+            EntryStatus::Missing => "missing",
+            EntryStatus::Unreadable(_) => "unreadable",
+            EntryStatus::Unsupported(_) => "unsupported",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EntryCheck {
+    pub entry: String,
+    pub status: EntryStatus,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    pub checks: Vec<EntryCheck>,
+}
+
+impl VerifyReport {
+    pub fn ok_count(&self) -> usize {
+        self.checks.iter().filter(|c| matches!(c.status, EntryStatus::Ok(_))).count()
+    }
+
+    /// Entries that won't play, in playlist order
+    pub fn problems(&self) -> impl Iterator<Item = &EntryCheck> {
+        self.checks.iter().filter(|c| !matches!(c.status, EntryStatus::Ok(_)))
+    }
+}
+
+/// Find a playlist entry on the device, ignoring case like FAT does.
+/// Absolute entries ("/MUSIC/...", "ms0:/MUSIC/...") start at `device_root`,
+/// relative ones at the playlist's folder.
+pub fn resolve_entry(device_root: &Path, playlist_dir: &Path, entry: &str) -> Option<PathBuf> {
+    let entry = entry.replace('\\', "/");
+    let entry = entry.strip_prefix("ms0:").unwrap_or(&entry);
+    let start = if entry.starts_with('/') { device_root } else { playlist_dir };
+    let mut path = start.to_path_buf();
+    for component in Path::new(entry).components() {
+        match component {
+            Component::Normal(name) => {
+                let exact = path.join(name);
+                path = if exact.exists() {
+                    exact
+                } else {
+                    let wanted = name.to_string_lossy().to_lowercase();
+                    fs::read_dir(&path).ok()?
+                        .filter_map(|e| e.ok())
+                        .find(|e| e.file_name().to_string_lossy().to_lowercase() == wanted)?
+                        .path()
+                };
+            }
+            Component::ParentDir => {
+                path.pop();
+            }
+            _ => {}
+        }
+    }
+    path.is_file().then_some(path)
+}
+
+/// Check one playlist entry against the device
+pub fn check_entry(device_root: &Path, playlist_dir: &Path, entry: &str) -> EntryCheck {
+    let status = match resolve_entry(device_root, playlist_dir, entry) {
+        None => EntryStatus::Missing,
+        Some(path) => {
+            let ext = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+            if !compat::is_supported_extension(&ext) {
+                EntryStatus::Unsupported(Issue::Format(ext).to_string())
+            } else {
+                match read_first_byte(&path) {
+                    Ok(true) => EntryStatus::Ok(path),
+                    Ok(false) => EntryStatus::Unreadable("empty file".to_string()),
+                    Err(e) => EntryStatus::Unreadable(e.to_string()),
+                }
+            }
+        }
+    };
+    EntryCheck { entry: entry.to_string(), status }
+}
+
+// Whether the file has any content; fails when it can't be opened or read
+fn read_first_byte(path: &Path) -> io::Result<bool> {
+    let mut buf = [0u8; 1];
+    Ok(File::open(path)?.read(&mut buf)? == 1)
+}
+
+/// Check every entry of a playlist that lives in `playlist_dir` on the device
+pub fn verify_entries(device_root: &Path, playlist_dir: &Path, entries: &[String]) -> VerifyReport {
+    let checks = entries.iter().map(|e| check_entry(device_root, playlist_dir, e)).collect();
+    VerifyReport { checks }
+}
+
+/// Read a playlist file and check its entries against the device
+pub fn verify_playlist(device_root: &Path, playlist_path: &Path) -> io::Result<VerifyReport> {
+    let entries = playlist::read_m3u8(&playlist_path.to_string_lossy())?;
+    let playlist_dir = playlist_path.parent().unwrap_or(device_root);
+    Ok(verify_entries(device_root, playlist_dir, &entries))
+}
+
+/// Every .m3u/.m3u8 file under a MUSIC folder, sorted by path
+pub fn find_playlists(music_dir: &Path) -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = walkdir::WalkDir::new(music_dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .filter(|p| p.extension().is_some_and(|ext| {
+            let ext = ext.to_string_lossy().to_lowercase();
+            ext == "m3u8" || ext == "m3u"
+        }))
+        .collect();
+    found.sort();
+    found
+}
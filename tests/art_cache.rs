@@ -0,0 +1,123 @@
+mod common;
+
+use common::{silent_mp3, temp_dir};
+use psp_playlist_maker::album_art::ArtResult;
+use psp_playlist_maker::art_cache::{self, ArtCache};
+use psp_playlist_maker::art_embed;
+use psp_playlist_maker::db;
+use psp_playlist_maker::local_art;
+use std::fs;
+use std::time::Duration;
+
+fn art(url: &str, provider: &str) -> ArtResult {
+    ArtResult { url: url.to_string(), provider: provider.to_string(), pinned: false }
+}
+
+fn cache(db_path: &str, missing_ttl: Duration) -> ArtCache {
+    ArtCache::with_connection(db::init_db(db_path).unwrap(), missing_ttl)
+}
+
+fn sidecar_names() -> Vec<String> {
+    local_art::DEFAULT_SIDECAR_NAMES.iter().map(|n| n.to_string()).collect()
+}
+
+#[test]
+fn keys_names_loosely() {
+    assert_eq!(art_cache::normalize("  The  Beatles "), "the beatles");
+    assert_eq!(art_cache::normalize("Abbey\tRoad"), "abbey road");
+
+    let db_path = temp_dir("art_cache", "normalize").join("index.db");
+    let cache = cache(&db_path.to_string_lossy(), Duration::from_secs(3600));
+    let cover = art("https://example.com/abbey.jpg", "deezer");
+    cache.store("The Beatles", "Abbey Road", Some(&cover)).unwrap();
+    assert_eq!(cache.lookup(" the  BEATLES", "abbey road "), Some(Some(cover)));
+    assert_eq!(cache.lookup("The Beatles", "Let It Be"), None);
+}
+
+#[test]
+fn looks_missing_art_up_again_after_a_while() {
+    let db_path = temp_dir("art_cache", "ttl").join("index.db");
+    let db_path = db_path.to_string_lossy();
+    cache(&db_path, Duration::from_secs(3600)).store("Nobody", "Unknown", None).unwrap();
+
+    // Recently missing: don't ask again
+    assert_eq!(cache(&db_path, Duration::from_secs(3600)).lookup("Nobody", "Unknown"), Some(None));
+    // Expired: ask again
+    assert_eq!(cache(&db_path, Duration::ZERO).lookup("Nobody", "Unknown"), None);
+
+    // Found covers never expire
+    let cover = art("https://example.com/found.jpg", "itunes");
+    cache(&db_path, Duration::ZERO).store("Somebody", "Known", Some(&cover)).unwrap();
+    assert_eq!(cache(&db_path, Duration::ZERO).lookup("Somebody", "Known"), Some(Some(cover)));
+}
+
+#[test]
+fn forgets_the_lookup_and_its_image() {
+    let db_path = temp_dir("art_cache", "forget").join("index.db");
+    let db_path = db_path.to_string_lossy();
+    let cache = cache(&db_path, Duration::from_secs(3600));
+    let cover = art("https://example.com/forget.jpg", "deezer");
+    cache.store("Air", "Moon Safari", Some(&cover)).unwrap();
+    let other = art("https://example.com/keep.jpg", "deezer");
+    cache.store("Air", "Talkie Walkie", Some(&other)).unwrap();
+    let conn = db::init_db(&db_path).unwrap();
+    db::save_art_image(&conn, &cover.url, b"jpeg").unwrap();
+    db::save_art_image(&conn, &other.url, b"other jpeg").unwrap();
+    // Kept images are served without a download
+    assert_eq!(cache.image(&cover.url), Some(b"jpeg".to_vec()));
+
+    cache.forget("AIR", "moon safari").unwrap();
+    assert_eq!(cache.lookup("Air", "Moon Safari"), None);
+    assert_eq!(db::load_art_image(&conn, &cover.url).unwrap(), None);
+    // Other albums keep theirs
+    assert_eq!(cache.lookup("Air", "Talkie Walkie"), Some(Some(other.clone())));
+    assert_eq!(db::load_art_image(&conn, &other.url).unwrap(), Some(b"other jpeg".to_vec()));
+}
+
+#[test]
+fn prefers_embedded_art_to_images_beside_the_tracks() {
+    let dir = temp_dir("art_cache", "local");
+    let (first, second) = (dir.join("01.mp3"), dir.join("02.mp3"));
+    silent_mp3(&first);
+    silent_mp3(&second);
+    let tracks = vec![first, second.clone()];
+
+    assert_eq!(local_art::find_local_art(&tracks, &sidecar_names()), None);
+
+    fs::write(dir.join("Folder.JPG"), b"folder jpeg").unwrap();
+    fs::write(dir.join("cover.jpg"), b"cover jpeg").unwrap();
+    let found = local_art::find_local_art(&tracks, &sidecar_names()).unwrap();
+    // Sidecar names are tried in order, ignoring case
+    assert_eq!(found.provider, "folder");
+    assert!(found.url.ends_with("cover.jpg"));
+
+    // A picture in any of the files wins over the folder
+    art_embed::embed_cover(&second, b"embedded jpeg").unwrap();
+    let found = local_art::find_local_art(&tracks, &sidecar_names()).unwrap();
+    assert_eq!(found.provider, "embedded");
+    assert_eq!(art_embed::CoverSource::from_url(&found.url).load().unwrap(), b"embedded jpeg");
+
+    let offered: Vec<String> = local_art::local_covers(&tracks, &sidecar_names()).into_iter().map(|a| a.provider).collect();
+    assert_eq!(offered, ["embedded", "folder", "folder"]);
+}
+
+#[test]
+fn a_pinned_cover_wins_until_unpinned() {
+    let dir = temp_dir("art_cache", "pin");
+    fs::write(dir.join("cover.jpg"), b"jpeg").unwrap();
+    let tracks = [dir.join("01.mp3")];
+    let db_path = dir.join("index.db").to_string_lossy().to_string();
+    let cache = cache(&db_path, Duration::from_secs(3600));
+    let chosen = art("https://example.com/chosen.jpg", "itunes");
+
+    cache.pin("Air", "Moon Safari", &chosen).unwrap();
+    let found = art_cache::fetch_album_art_cached(&db_path, "air", "moon  safari", &tracks).unwrap().unwrap();
+    assert_eq!(found, ArtResult { pinned: true, ..chosen.clone() });
+    assert_eq!(art_cache::pinned_art(&db_path, "Air", "Moon Safari"), Some(found));
+
+    // Without the pin the folder's cover is back
+    cache.unpin("Air", "Moon Safari").unwrap();
+    let found = art_cache::fetch_album_art_cached(&db_path, "Air", "Moon Safari", &tracks).unwrap().unwrap();
+    assert_eq!(found.provider, "folder");
+    assert!(!found.pinned);
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use common::track;
+use psp_playlist_maker::auto_playlist::{self, AutoPlaylistOptions, GroupBy};
+use psp_playlist_maker::music::Track;
+
+fn options(group_by: GroupBy, template: &str, min_tracks: usize) -> AutoPlaylistOptions {
+    AutoPlaylistOptions { group_by, template: template.to_string(), min_tracks }
+}
+
+fn numbered(path: &str, artist: &str, album: &str, number: u32) -> Track {
+    Track { track_number: Some(number), ..track(path, artist, album, path) }
+}
+
+#[test]
+fn groups_albums_ignoring_case_in_track_order() {
+    let tracks = vec![
+        numbered("/MUSIC/dp/2.mp3", "Daft Punk", "Discovery", 2),
+        numbered("/MUSIC/dp/1.mp3", "daft punk", "discovery", 1),
+        numbered("/MUSIC/air/1.mp3", "Air", "Moon Safari", 1),
+        numbered("/MUSIC/loose.mp3", "Air", "", 1),
+    ];
+    let plan = auto_playlist::plan(&tracks, &options(GroupBy::Album, "{name} ({count}).m3u8", 2));
+    assert_eq!(plan.playlists.len(), 1);
+    assert_eq!(plan.playlists[0].file_name, "Daft Punk - Discovery (2).m3u8");
+    assert_eq!(plan.playlists[0].entries, [1, 0]);
+    // Too small; the untagged track has no album to join
+    assert_eq!(plan.skipped, [("Air - Moon Safari".to_string(), 1)]);
+}
+
+#[test]
+fn names_that_collide_get_numbered() {
+    let tracks = vec![
+        track("/MUSIC/a.mp3", "AC/DC", "Back in Black", "a"),
+        track("/MUSIC/b.mp3", "AC-DC", "Tribute", "b"),
+    ];
+    let plan = auto_playlist::plan(&tracks, &options(GroupBy::Artist, auto_playlist::DEFAULT_TEMPLATE, 1));
+    let names: Vec<&str> = plan.playlists.iter().map(|p| p.file_name.as_str()).collect();
+    assert_eq!(names, ["AC-DC.m3u8", "AC-DC (2).m3u8"]);
+}
+
+#[test]
+fn folders_group_by_their_top_level_directory() {
+    let tracks = vec![
+        track("/mnt/psp/MUSIC/Jazz/Miles/b.mp3", "", "", "b"),
+        track("/mnt/psp/MUSIC/Jazz/a.mp3", "", "", "a"),
+        track("/mnt/psp/MUSIC/top.mp3", "", "", "top"),
+    ];
+    let plan = auto_playlist::plan(&tracks, &options(GroupBy::Directory, "{dir}.m3u8", 1));
+    assert_eq!(plan.playlists.len(), 1);
+    assert_eq!(plan.playlists[0].file_name, "Jazz.m3u8");
+    // By path, as the folders lay them out
+    assert_eq!(plan.playlists[0].entries, [0, 1]);
+}
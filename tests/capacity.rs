@@ -0,0 +1,76 @@
+mod common;
+
+use common::timed;
+use psp_playlist_maker::capacity::{self, Selection, TrackCost};
+use psp_playlist_maker::music::Track;
+use psp_playlist_maker::transcode::{Encoder, WavEncoder};
+use std::path::Path;
+
+fn selection(label: &str, tracks: &[usize]) -> Selection {
+    Selection { label: label.to_string(), tracks: tracks.to_vec() }
+}
+
+/// Each track costs 100 bytes times its index plus one
+fn by_index(i: usize) -> TrackCost {
+    TrackCost { bytes: 100 * (i as u64 + 1), converted: i == 0 }
+}
+
+#[test]
+fn fits_selections_in_order_and_skips_whole_ones_that_dont() {
+    let selections = [selection("first", &[0, 1]), selection("too big", &[4, 5]), selection("small", &[2])];
+    let plan = capacity::plan(&selections, 700, Some(1000), by_index);
+
+    let summary: Vec<(&str, u64, bool, u64)> = plan.selections.iter().map(|s| (s.label.as_str(), s.bytes, s.fits, s.short_by)).collect();
+    assert_eq!(summary, [("first", 300, true, 0), ("too big", 1100, false, 700), ("small", 300, true, 0)]);
+    assert_eq!(plan.selections[0].converted, 1);
+    assert_eq!(plan.needed(), 600);
+    assert_eq!(plan.remaining(), 100);
+    assert_eq!(plan.overflowing().map(|s| s.label.as_str()).collect::<Vec<_>>(), ["too big"]);
+    assert_eq!(plan.total_bytes, Some(1000));
+}
+
+#[test]
+fn counts_shared_tracks_once() {
+    // The album repeats a playlist track and lists one twice
+    let selections = [selection("playlist", &[0, 1]), selection("album", &[1, 2, 2])];
+    let plan = capacity::plan(&selections, 10_000, None, by_index);
+    assert_eq!(plan.selections[1].bytes, 300);
+    assert_eq!(plan.selections[1].tracks, 3);
+    assert_eq!(plan.needed(), 600);
+
+    // A track only counts as placed once its selection fits
+    let plan = capacity::plan(&selections, 250, None, by_index);
+    assert!(!plan.selections[0].fits);
+    assert_eq!(plan.selections[0].short_by, 50);
+    assert_eq!(plan.selections[1].bytes, 500);
+    assert_eq!(plan.selections[1].short_by, 250);
+    assert_eq!(plan.remaining(), 250);
+}
+
+fn flac(path: &str) -> Track {
+    Track { codec: "flac".to_string(), ..timed(path, "Artist", "Album", 10, 5_000_000) }
+}
+
+#[test]
+fn estimates_what_copying_will_take() {
+    let root = Path::new("/mnt/psp");
+    let wav: &dyn Encoder = &WavEncoder;
+    let mp3 = timed("/home/me/Music/a.mp3", "Artist", "Album", 10, 3_000_000);
+    let on_stick = timed("/mnt/psp/MUSIC/a.mp3", "Artist", "Album", 10, 3_000_000);
+
+    assert_eq!(capacity::estimate_cost(&mp3, Some(root), Some(wav)), TrackCost { bytes: 3_000_000, converted: false });
+    assert_eq!(capacity::estimate_cost(&on_stick, Some(root), Some(wav)), TrackCost::default());
+    assert_eq!(capacity::estimate_cost(&on_stick, None, None), TrackCost { bytes: 3_000_000, converted: false });
+
+    // Tracks the PSP can't play are copied as they are, or converted
+    let lossless = flac("/home/me/Music/b.flac");
+    assert_eq!(capacity::estimate_cost(&lossless, Some(root), None), TrackCost { bytes: 5_000_000, converted: false });
+    assert_eq!(capacity::estimate_cost(&lossless, Some(root), Some(wav)), TrackCost { bytes: 1_764_000, converted: true });
+    // Already on the stick doesn't make them free: the converted copy still needs room
+    let lossless_on_stick = flac("/mnt/psp/MUSIC/b.flac");
+    assert_eq!(capacity::estimate_cost(&lossless_on_stick, Some(root), None), TrackCost::default());
+    assert_eq!(capacity::estimate_cost(&lossless_on_stick, Some(root), Some(wav)), TrackCost { bytes: 1_764_000, converted: true });
+    // Without a duration the file size stands in
+    let untimed = Track { duration_secs: None, ..lossless };
+    assert_eq!(capacity::estimate_cost(&untimed, Some(root), Some(wav)), TrackCost { bytes: 5_000_000, converted: true });
+}
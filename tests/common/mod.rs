@@ -0,0 +1,44 @@
+#![allow(dead_code)]
+
+use psp_playlist_maker::music::Track;
+
+/// An indexed MP3 with the given tags and nothing else known
+pub fn track(path: &str, artist: &str, album: &str, title: &str) -> Track {
+    Track {
+        path: path.to_string(),
+        artist: artist.to_string(),
+        album: album.to_string(),
+        title: title.to_string(),
+        genre: String::new(),
+        track_number: None,
+        disc_number: None,
+        duration_secs: None,
+        size_bytes: 0,
+        codec: "mp3".to_string(),
+        sample_rate: Some(44100),
+        bitrate_kbps: Some(192),
+        channels: Some(2),
+        drm: false,
+    }
+}
+
+/// A track of `secs` seconds and `bytes` bytes
+pub fn timed(path: &str, artist: &str, album: &str, secs: u32, bytes: u64) -> Track {
+    Track { duration_secs: Some(secs), size_bytes: bytes, ..track(path, artist, album, path) }
+}
+
+/// A fresh, empty directory under the system temp dir, one per test binary, `prefix` and `name`
+pub fn temp_dir(prefix: &str, name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("psp_{}_test_{}_{}", prefix, name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Write a short, silent MPEG-1 Layer III file (44.1 kHz, 128 kbps) without tags
+pub fn silent_mp3(path: &std::path::Path) {
+    // 144 * 128000 / 44100 bytes per frame, header included
+    let mut frame = vec![0u8; 417];
+    frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+    std::fs::write(path, frame.repeat(20)).unwrap();
+}
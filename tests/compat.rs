@@ -0,0 +1,67 @@
+mod common;
+
+use common::track;
+use psp_playlist_maker::compat::{self, Issue};
+use psp_playlist_maker::music::Track;
+
+/// A track at `path` holding `codec` audio, otherwise as the PSP likes it
+fn audio(path: &str, codec: &str) -> Track {
+    Track { codec: codec.to_string(), ..track(path, "Artist", "Album", "Title") }
+}
+
+#[test]
+fn plays_what_the_psp_supports() {
+    assert!(compat::is_compatible(&audio("/m/a.mp3", "mp3")));
+    assert!(compat::is_compatible(&Track { sample_rate: Some(48000), ..audio("/m/a.M4A", "aac") }));
+    assert!(compat::is_compatible(&Track { sample_rate: Some(44100), bitrate_kbps: Some(1411), ..audio("/m/a.wav", "pcm") }));
+    // Scanned by an older version: nothing known but the extension
+    assert!(compat::is_compatible(&Track { codec: String::new(), sample_rate: None, bitrate_kbps: None, channels: None, ..audio("/m/a.mp3", "") }));
+    // ATRAC and WMA aren't read here; the file type is trusted
+    assert!(compat::is_compatible(&Track { sample_rate: Some(96000), ..audio("/m/a.oma", "") }));
+}
+
+#[test]
+fn rejects_unsupported_file_types() {
+    assert_eq!(compat::check(&audio("/m/a.flac", "flac")), [Issue::Format("flac".to_string())]);
+    assert_eq!(compat::check(&audio("/m/a.ogg", "vorbis")), [Issue::Format("ogg".to_string())]);
+    assert_eq!(compat::check(&audio("/m/README", "")), [Issue::Format(String::new())]);
+    assert_eq!(compat::describe(&compat::check(&audio("/m/README", ""))), "file has no extension");
+    assert!(compat::is_supported_extension("aa3"));
+    assert!(!compat::is_supported_extension("flac"));
+}
+
+#[test]
+fn rejects_codecs_the_container_can_hold_but_the_psp_cant_decode() {
+    let alac = Track { sample_rate: Some(96000), ..audio("/m/a.m4a", "alac") };
+    // The codec decides; rate and bitrate of audio that won't play at all aren't listed
+    assert_eq!(compat::check(&alac), [Issue::Codec { codec: "alac".to_string(), ext: "m4a".to_string() }]);
+    assert_eq!(compat::describe(&compat::check(&alac)), "ALAC audio in a .m4a file");
+    assert_eq!(compat::check(&audio("/m/a.wav", "adpcm")), [Issue::Codec { codec: "adpcm".to_string(), ext: "wav".to_string() }]);
+}
+
+#[test]
+fn checks_sample_rate_bitrate_and_channels() {
+    let mp3 = audio("/m/a.mp3", "mp3");
+    assert_eq!(compat::check(&Track { sample_rate: Some(16000), ..mp3.clone() }), []);
+    assert_eq!(compat::check(&Track { sample_rate: Some(11025), ..mp3.clone() }), [Issue::SampleRate(11025)]);
+    assert_eq!(compat::check(&Track { bitrate_kbps: Some(320), ..mp3.clone() }), []);
+    assert_eq!(compat::check(&Track { bitrate_kbps: Some(321), ..mp3.clone() }), [Issue::Bitrate(321)]);
+    // AAC starts higher than MP3
+    assert_eq!(compat::check(&Track { sample_rate: Some(22050), ..audio("/m/a.m4a", "aac") }), [Issue::SampleRate(22050)]);
+    // WAV only at CD rate
+    assert_eq!(compat::check(&Track { sample_rate: Some(48000), ..audio("/m/a.wav", "pcm") }), [Issue::SampleRate(48000)]);
+
+    let surround = Track { sample_rate: Some(96000), bitrate_kbps: Some(448), channels: Some(6), ..mp3 };
+    let issues = compat::check(&surround);
+    assert_eq!(issues, [Issue::SampleRate(96000), Issue::Bitrate(448), Issue::Channels(6)]);
+    assert_eq!(compat::describe(&issues), "96000 Hz sample rate; 448 kbps bitrate; 6 channels (stereo at most)");
+}
+
+#[test]
+fn reports_drm_whatever_the_format() {
+    let protected = Track { drm: true, ..audio("/m/a.m4a", "aac") };
+    assert_eq!(compat::check(&protected), [Issue::Drm]);
+    let wma = Track { drm: true, ..audio("/m/a.wma", "") };
+    assert_eq!(compat::check(&wma), [Issue::Drm]);
+    assert!(!compat::is_compatible(&wma));
+}
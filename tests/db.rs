@@ -0,0 +1,36 @@
+mod common;
+
+use common::temp_dir;
+use psp_playlist_maker::album_art::ArtResult;
+use psp_playlist_maker::db;
+use std::time::Duration;
+
+#[test]
+fn connections_wait_for_each_other_instead_of_failing() {
+    let dir = temp_dir("db", "busy");
+    let db_path = dir.join("index.db").to_string_lossy().to_string();
+    let mut holder = db::init_db(&db_path).unwrap();
+
+    // One connection holds the write lock while others write; longer than rusqlite's own 5 s default wait
+    let tx = holder.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate).unwrap();
+    let writers: Vec<_> = (0..4)
+        .map(|i| {
+            let db_path = db_path.clone();
+            std::thread::spawn(move || {
+                let conn = db::init_db(&db_path).unwrap();
+                let art = ArtResult { url: format!("https://example.com/{}.jpg", i), provider: "deezer".to_string(), pinned: false };
+                db::save_art_lookup(&conn, "artist", &format!("album {}", i), Some(&art))
+            })
+        })
+        .collect();
+    std::thread::sleep(Duration::from_secs(6));
+    tx.commit().unwrap();
+
+    for writer in writers {
+        writer.join().unwrap().unwrap();
+    }
+    let conn = db::init_db(&db_path).unwrap();
+    for i in 0..4 {
+        assert!(db::load_art_lookup(&conn, "artist", &format!("album {}", i)).unwrap().is_some());
+    }
+}
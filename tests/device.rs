@@ -0,0 +1,73 @@
+mod common;
+
+use common::temp_dir;
+use psp_playlist_maker::device::DeviceScanner;
+use std::fs;
+
+#[test]
+fn finds_psp_mounts_in_fake_mount_table() {
+    let root = temp_dir("device", "scan");
+    // A PSP with a label containing a space, a USB stick without PSP/, and a PSP with lowercase dirs
+    fs::create_dir_all(root.join("media/user/MY PSP/PSP/GAME")).unwrap();
+    fs::create_dir_all(root.join("media/user/MY PSP/MUSIC")).unwrap();
+    fs::create_dir_all(root.join("media/user/STICK/MUSIC")).unwrap();
+    fs::create_dir_all(root.join("mnt/psp2/psp")).unwrap();
+    fs::create_dir_all(root.join("mnt/psp2/music")).unwrap();
+    fs::create_dir_all(root.join("dev/disk/by-label")).unwrap();
+    fs::write(root.join("dev/sdb1"), "").unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("../../sdb1", root.join("dev/disk/by-label/MEMSTICK\\x20PRO")).unwrap();
+
+    let table = root.join("mounts");
+    fs::write(&table, "\
+proc /proc proc rw 0 0
+/dev/sda1 / ext4 rw 0 0
+/dev/sdb1 /media/user/MY\\040PSP vfat rw 0 0
+/dev/sdc1 /media/user/STICK vfat rw 0 0
+/dev/sdd1 /mnt/psp2 vfat rw 0 0
+").unwrap();
+
+    let devices = DeviceScanner::with_mount_table(&table, &root).scan();
+    assert_eq!(devices.len(), 2);
+
+    let first = &devices[0];
+    assert_eq!(first.music_dir, root.join("media/user/MY PSP/MUSIC"));
+    #[cfg(unix)]
+    assert_eq!(first.label, "MEMSTICK PRO");
+    assert!(first.total_bytes.is_some());
+
+    // No by-label entry: falls back to the mount point name
+    assert_eq!(devices[1].label, "psp2");
+    assert_eq!(devices[1].music_dir, root.join("mnt/psp2/music"));
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+#[cfg(unix)]
+fn decodes_multibyte_labels() {
+    let root = temp_dir("device", "labels");
+    fs::create_dir_all(root.join("media/stick/PSP")).unwrap();
+    fs::create_dir_all(root.join("media/stick/MUSIC")).unwrap();
+    fs::create_dir_all(root.join("dev/disk/by-label")).unwrap();
+    fs::write(root.join("dev/sdb1"), "").unwrap();
+    // udev writes "Müsik PSP" as the bytes of its UTF-8, one \xNN each
+    std::os::unix::fs::symlink("../../sdb1", root.join("dev/disk/by-label/M\\xc3\\xbcsik\\x20PSP")).unwrap();
+    let table = root.join("mounts");
+    fs::write(&table, "/dev/sdb1 /media/stick vfat rw 0 0\n").unwrap();
+
+    let devices = DeviceScanner::with_mount_table(&table, &root).scan();
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].label, "Müsik PSP");
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn unreadable_table_stays_inside_the_fake_root() {
+    let root = temp_dir("device", "no_table");
+    // Would be found by probing the real system's mount locations
+    let devices = DeviceScanner::with_mount_table(root.join("missing"), &root).scan();
+    assert!(devices.is_empty());
+    fs::remove_dir_all(&root).unwrap();
+}
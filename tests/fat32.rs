@@ -0,0 +1,96 @@
+use psp_playlist_maker::fat32::{self, Fat32Sanitizer, PathChange, MAX_COMPONENT, MAX_PATH};
+
+fn units(s: &str) -> usize {
+    s.encode_utf16().count()
+}
+
+#[test]
+fn replaces_reserved_characters_and_names() {
+    assert_eq!(fat32::sanitize_component("AC/DC: Live <1991> \"Donington\" | What?*"), "AC-DC- Live (1991) 'Donington' - What__");
+    assert_eq!(fat32::sanitize_component("  Tab\there..  "), "Tab_here");
+    assert_eq!(fat32::sanitize_component("con.mp3"), "con_.mp3");
+    assert_eq!(fat32::sanitize_component("LPT1"), "LPT1_");
+    assert_eq!(fat32::sanitize_component("Console.mp3"), "Console.mp3");
+    assert_eq!(fat32::sanitize_component("..."), "_");
+    assert_eq!(fat32::sanitize_component("Sigur Rós - Ágætis byrjun"), "Sigur Rós - Ágætis byrjun");
+}
+
+#[test]
+fn cuts_names_to_the_fat32_limit() {
+    let long = format!("{}.mp3", "é".repeat(300));
+    let cut = fat32::sanitize_component(&long);
+    assert_eq!(units(&cut), MAX_COMPONENT);
+    assert!(cut.ends_with(".mp3"));
+
+    // Characters outside the BMP take two UTF-16 units and are never split
+    let wide = format!("{}.mp3", "🎵".repeat(200));
+    let cut = fat32::sanitize_component(&wide);
+    assert!(units(&cut) <= MAX_COMPONENT);
+    assert_eq!(cut, format!("{}.mp3", "🎵".repeat(125)));
+}
+
+#[test]
+fn shortens_long_paths_evenly() {
+    let path = format!("/MUSIC/{}/{}/{}.mp3", "a".repeat(150), "b".repeat(150), "c".repeat(150));
+    let short = fat32::sanitize_path(&path);
+    assert!(units(&short) <= MAX_PATH);
+    let parts: Vec<&str> = short.split('/').collect();
+    assert_eq!(parts[1], "MUSIC");
+    assert!(parts[4].ends_with(".mp3"));
+    let lens: Vec<usize> = parts[2..].iter().map(|p| units(p)).collect();
+    let (min, max) = (lens.iter().min().unwrap(), lens.iter().max().unwrap());
+    assert!(max - min <= 1, "uneven: {:?}", lens);
+}
+
+#[test]
+fn cutting_through_an_extension_drops_trailing_spaces() {
+    // The extension is longer than what the path leaves for the name, so the cut goes
+    // through it and lands just after a space
+    let name = format!("a.{} zzzzz", "y".repeat(245));
+    let short = fat32::sanitize_path(&format!("/MUSIC/{}", name));
+    assert_eq!(short, format!("/MUSIC/a.{}", "y".repeat(245)));
+}
+
+#[test]
+fn sanitizing_twice_changes_nothing() {
+    let paths = [
+        "/MUSIC/AC:DC/Back in Black?.mp3".to_string(),
+        format!("/MUSIC/{}/{}. /{}.flac", "x".repeat(200), "y".repeat(100), "z".repeat(100)),
+        format!("/MUSIC/a.{} zzzzz", "y".repeat(245)),
+        "/MUSIC/  Album. /Song.mp3".to_string(),
+    ];
+    for path in &paths {
+        let once = fat32::sanitize_path(path);
+        assert_eq!(fat32::sanitize_path(&once), once, "from {}", path);
+    }
+
+    let mut sanitizer = Fat32Sanitizer::new();
+    let once = sanitizer.sanitize("MUSIC/AC:DC/Song.mp3");
+    let mut again = Fat32Sanitizer::new();
+    assert_eq!(again.sanitize(&once), once);
+    assert!(again.changes().is_empty());
+}
+
+#[test]
+fn keeps_colliding_names_apart_and_reports_them() {
+    let mut sanitizer = Fat32Sanitizer::new();
+    assert_eq!(sanitizer.sanitize("MUSIC/AC-DC/Song.mp3"), "MUSIC/AC-DC/Song.mp3");
+    assert_eq!(sanitizer.sanitize("MUSIC/AC:DC/Song.mp3"), "MUSIC/AC-DC/Song~2.mp3");
+    // FAT ignores case, so this collides as well
+    assert_eq!(sanitizer.sanitize("MUSIC/ac|dc/song.mp3"), "MUSIC/ac-dc/song~3.mp3");
+    // The same original always gets the same answer
+    assert_eq!(sanitizer.sanitize("MUSIC/AC:DC/Song.mp3"), "MUSIC/AC-DC/Song~2.mp3");
+
+    assert_eq!(sanitizer.changes(), [
+        PathChange { original: "MUSIC/AC:DC/Song.mp3".to_string(), sanitized: "MUSIC/AC-DC/Song~2.mp3".to_string() },
+        PathChange { original: "MUSIC/ac|dc/song.mp3".to_string(), sanitized: "MUSIC/ac-dc/song~3.mp3".to_string() },
+    ]);
+
+    // A suffixed name still fits the path limit
+    let long = format!("MUSIC/{}.mp3", "n".repeat(300));
+    let first = sanitizer.sanitize(&long);
+    let second = sanitizer.sanitize(&format!("MUSIC/{}?.mp3", "n".repeat(300)));
+    assert_ne!(first.to_lowercase(), second.to_lowercase());
+    assert!(second.ends_with("~2.mp3"));
+    assert!(units(&second) <= MAX_PATH);
+}
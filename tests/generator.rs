@@ -0,0 +1,87 @@
+mod common;
+
+use common::timed;
+use psp_playlist_maker::generator::{self, GenerateOptions, Target, TrackSource};
+use psp_playlist_maker::music::Track;
+
+fn options(target: Target, keep_albums: bool, seed: u64) -> GenerateOptions {
+    GenerateOptions { target, keep_albums, seed }
+}
+
+#[test]
+fn fills_a_duration_exactly_when_it_can() {
+    let tracks: Vec<Track> = (0..10).map(|n| timed(&format!("/MUSIC/{}.mp3", n), "A", "One", 300, 5_000_000)).collect();
+    let report = generator::generate(&tracks, &TrackSource::Library, &options(Target::DurationSecs(45 * 60), false, 1));
+    assert_eq!(report.entries.len(), 9);
+    assert_eq!(report.achieved, 2700);
+    assert_eq!(report.summary(), "9 tracks, 45:00 of 45:00 (100.0%)");
+}
+
+#[test]
+fn duration_may_overshoot_but_size_never_does() {
+    let tracks: Vec<Track> = (0..3).map(|n| timed(&format!("/MUSIC/{}.mp3", n), "A", "One", 600, 600)).collect();
+    // 1200 s is 200 over, closer than stopping 400 short
+    let report = generator::generate(&tracks, &TrackSource::Library, &options(Target::DurationSecs(1000), false, 1));
+    assert_eq!(report.achieved, 1200);
+    // Free space can't be overshot
+    let report = generator::generate(&tracks, &TrackSource::Library, &options(Target::Bytes(1000), false, 1));
+    assert_eq!((report.entries.len(), report.achieved), (1, 600));
+}
+
+#[test]
+fn skips_tracks_of_unknown_length_and_honours_the_source() {
+    let mut tracks = vec![
+        timed("/MUSIC/a.mp3", "A", "One", 100, 10),
+        timed("/MUSIC/b.mp3", "B", "Two", 100, 10),
+        timed("/MUSIC/c.mp3", "a", "One", 100, 10),
+    ];
+    tracks[2].duration_secs = None;
+    let report = generator::generate(&tracks, &TrackSource::Artist("a".to_string()), &options(Target::DurationSecs(1000), false, 1));
+    assert_eq!(report.entries, [0]);
+    assert_eq!(report.skipped_unknown, 1);
+}
+
+#[test]
+fn keep_albums_adds_whole_albums_in_track_order() {
+    let mut tracks = vec![
+        timed("/MUSIC/A/3.mp3", "A", "One", 100, 10),
+        timed("/MUSIC/A/1.mp3", "A", "One", 100, 10),
+        timed("/MUSIC/A/2.mp3", "A", "One", 100, 10),
+        timed("/MUSIC/B/1.mp3", "B", "Two", 100, 10),
+        timed("/MUSIC/B/2.mp3", "B", "Two", 100, 10),
+    ];
+    for (track, number) in tracks.iter_mut().zip([3, 1, 2, 1, 2]) {
+        track.track_number = Some(number);
+    }
+    for seed in 0..20 {
+        let report = generator::generate(&tracks, &TrackSource::Library, &options(Target::DurationSecs(300), true, seed));
+        assert!(report.entries == [1, 2, 0] || report.entries == [3, 4], "seed {}: {:?}", seed, report.entries);
+    }
+}
+
+#[test]
+fn same_seed_same_playlist() {
+    let tracks: Vec<Track> = (0..30).map(|n| timed(&format!("/MUSIC/{}.mp3", n), "A", "One", 100 + n, 10)).collect();
+    let run = |seed| generator::generate(&tracks, &TrackSource::Library, &options(Target::DurationSecs(1000), false, seed)).entries;
+    assert_eq!(run(5), run(5));
+    assert_ne!(run(5), run(6));
+}
+
+#[test]
+fn sizes_and_durations_read_and_print() {
+    assert_eq!(generator::parse_size("2G"), Some(2 << 30));
+    assert_eq!(generator::parse_size("700MB"), Some(700 << 20));
+    assert_eq!(generator::parse_size(" 1.5k "), Some(1536));
+    assert_eq!(generator::parse_size("12 GiB"), Some(12 << 30));
+    assert_eq!(generator::parse_size("1048576"), Some(1048576));
+    assert_eq!(generator::parse_size("3X"), None);
+    assert_eq!(generator::parse_size(""), None);
+
+    assert_eq!(generator::format_size(512), "512 B");
+    assert_eq!(generator::format_size(1536), "1.5 KB");
+    assert_eq!(generator::format_size(2 << 30), "2.0 GB");
+
+    assert_eq!(generator::format_duration(59), "0:59");
+    assert_eq!(generator::format_duration(2700), "45:00");
+    assert_eq!(generator::format_duration(3725), "1:02:05");
+}
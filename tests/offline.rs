@@ -0,0 +1,45 @@
+mod common;
+
+use common::temp_dir;
+use psp_playlist_maker::album_art::{ArtProvider, Deezer};
+use psp_playlist_maker::art_cache;
+use psp_playlist_maker::http::{self, ProviderError};
+use psp_playlist_maker::metadata::fetch_metadata;
+use std::fs;
+use std::net::TcpListener;
+use std::time::{Duration, Instant};
+
+// Offline mode is process-wide, so its tests live in their own binary, apart from tests/providers.rs
+
+#[test]
+fn offline_mode_sends_nothing_and_keeps_local_art() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    http::set_offline(true);
+
+    let started = Instant::now();
+    assert!(http::download(&format!("{}/cover.jpg", base_url)).is_err());
+    let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let deezer = Deezer { base_url: base_url.clone() };
+    assert_eq!(
+        rt.block_on(deezer.fetch("Daft Punk", "Discovery")),
+        Err(ProviderError::Offline(http::OFFLINE_MODE.to_string()))
+    );
+    assert!(rt.block_on(fetch_metadata(&base_url, "daft punk", "discovery", "one more time")).is_err());
+    // Answered at once, not after a timeout, and nothing reached the server
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(listener.accept().is_err());
+
+    // A cover next to the tracks is still found; without one, every provider reports offline mode
+    let album = temp_dir("offline", "album");
+    fs::write(album.join("cover.jpg"), b"jpeg").unwrap();
+    let db = album.join("index.db").to_string_lossy().to_string();
+    let found = art_cache::fetch_album_art_cached(&db, "Daft Punk", "Discovery", &[album.join("01.mp3")]).unwrap().unwrap();
+    assert_eq!(found.provider, "folder");
+
+    let bare = temp_dir("offline", "bare");
+    let failures = art_cache::fetch_album_art_cached(&db, "Daft Punk", "Homework", &[bare.join("01.mp3")]).unwrap_err();
+    assert!(!failures.is_empty());
+    assert!(failures.iter().all(|(_, e)| *e == ProviderError::Offline(http::OFFLINE_MODE.to_string())));
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use common::track;
+use psp_playlist_maker::music::Track;
+use psp_playlist_maker::ordering::{self, SortKey};
+
+fn library(albums: &[(&str, &str, usize)]) -> Vec<Track> {
+    let mut tracks = Vec::new();
+    for (artist, album, count) in albums {
+        for n in 0..*count {
+            tracks.push(track(&format!("/MUSIC/{}/{}/{}.mp3", artist, album, n), artist, album, &n.to_string()));
+        }
+    }
+    tracks
+}
+
+#[test]
+fn shuffle_is_fixed_by_its_seed() {
+    let entries: Vec<usize> = (0..20).collect();
+    let shuffled = |seed| {
+        let mut e = entries.clone();
+        ordering::shuffle_entries(&mut e, seed);
+        e
+    };
+    assert_eq!(shuffled(42), shuffled(42));
+    assert_ne!(shuffled(42), shuffled(43));
+    assert_ne!(shuffled(42), entries);
+    let mut sorted = shuffled(42);
+    sorted.sort();
+    assert_eq!(sorted, entries);
+}
+
+#[test]
+fn smart_shuffle_spaces_artists() {
+    let tracks = library(&[("A", "One", 2), ("A", "Two", 2), ("B", "Three", 3), ("C", "Four", 3)]);
+    for seed in 0..50 {
+        let mut entries: Vec<usize> = (0..tracks.len()).collect();
+        ordering::smart_shuffle_entries(&mut entries, &tracks, seed);
+        for pair in entries.windows(2) {
+            assert_ne!(tracks[pair[0]].artist, tracks[pair[1]].artist, "seed {}: {:?}", seed, entries);
+        }
+        let mut sorted = entries.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..tracks.len()).collect::<Vec<_>>());
+    }
+
+    let mut again: Vec<usize> = (0..tracks.len()).collect();
+    let mut first = again.clone();
+    ordering::smart_shuffle_entries(&mut first, &tracks, 7);
+    ordering::smart_shuffle_entries(&mut again, &tracks, 7);
+    assert_eq!(first, again);
+}
+
+#[test]
+fn smart_shuffle_spaces_albums_when_artists_cant_be() {
+    // One artist only: the albums alternate instead
+    let tracks = library(&[("A", "One", 3), ("A", "Two", 3)]);
+    for seed in 0..50 {
+        let mut entries: Vec<usize> = (0..tracks.len()).collect();
+        ordering::smart_shuffle_entries(&mut entries, &tracks, seed);
+        for pair in entries.windows(2) {
+            assert_ne!(tracks[pair[0]].album, tracks[pair[1]].album, "seed {}: {:?}", seed, entries);
+        }
+    }
+}
+
+#[test]
+fn sorts_by_keys_in_priority_order() {
+    let mut tracks = library(&[("b", "X", 1), ("A", "Y", 1), ("a", "X", 2)]);
+    tracks[2].track_number = Some(2);
+    tracks[3].track_number = Some(1);
+    let keys = ordering::parse_sort_keys("artist, album,track").unwrap();
+    assert_eq!(keys, [SortKey::Artist, SortKey::Album, SortKey::Track]);
+    let mut entries: Vec<usize> = (0..tracks.len()).collect();
+    ordering::sort_entries(&mut entries, &tracks, &keys);
+    assert_eq!(entries, [3, 2, 1, 0]);
+    assert!(ordering::parse_sort_keys("artist,colour").is_err());
+}
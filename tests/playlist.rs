@@ -0,0 +1,26 @@
+mod common;
+
+use common::{temp_dir, track};
+use psp_playlist_maker::playlist;
+use std::fs;
+
+#[test]
+fn reads_entries_without_comments_or_blank_lines() {
+    let path = temp_dir("playlist", "read").join("list.m3u8");
+    fs::write(&path, "\u{feff}#EXTM3U\r\n#EXTINF:215,Daft Punk - One More Time\r\n/MUSIC/Daft Punk/01.mp3\r\n\r\n  /MUSIC/Air/02.mp3  \n").unwrap();
+    let entries = playlist::read_m3u8(&path.to_string_lossy()).unwrap();
+    assert_eq!(entries, ["/MUSIC/Daft Punk/01.mp3", "/MUSIC/Air/02.mp3"]);
+}
+
+#[test]
+fn matches_entries_like_fat_does() {
+    let tracks = vec![
+        track("/mnt/psp/MUSIC/Daft Punk/01.mp3", "Daft Punk", "Discovery", "One More Time"),
+        track("/mnt/psp/MUSIC/Air/02.mp3", "Air", "Moon Safari", "Sexy Boy"),
+    ];
+    let entries: Vec<String> = ["\\music\\air\\02.MP3", "/MUSIC/Missing.mp3", "/MUSIC/Daft Punk/01.mp3"]
+        .iter()
+        .map(|e| e.to_string())
+        .collect();
+    assert_eq!(playlist::match_entries(&entries, &tracks), [1, 0]);
+}
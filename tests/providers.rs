@@ -0,0 +1,312 @@
+use psp_playlist_maker::album_art::{self, ArtCandidate, ArtProvider, Deezer, Itunes, LastFm, MusicBrainz};
+use psp_playlist_maker::config::Config;
+use psp_playlist_maker::http::ProviderError;
+use psp_playlist_maker::itunes_art::fetch_itunes_art;
+use psp_playlist_maker::metadata::fetch_metadata;
+use reqwest::StatusCode;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A local stand-in for the web services: answers each request with the first route whose
+/// path prefix matches, 404 otherwise, and remembers the request lines it saw and when. A prefix listed
+/// more than once gets its answers in turn, the last one repeating; a 503 asks to retry after a second.
+struct MockServer {
+    base_url: String,
+    requests: Arc<Mutex<Vec<(String, Instant)>>>,
+}
+
+impl MockServer {
+    fn start(mut routes: Vec<(&'static str, u16, &'static str)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                if reader.read_line(&mut request_line).is_err() {
+                    continue;
+                }
+                loop {
+                    let mut header = String::new();
+                    if reader.read_line(&mut header).unwrap_or(0) == 0 || header == "\r\n" {
+                        break;
+                    }
+                }
+                let request_line = request_line.trim_end().to_string();
+                let mut parts = request_line.split(' ');
+                let method = parts.next().unwrap_or("").to_string();
+                let path = parts.next().unwrap_or("").to_string();
+                seen.lock().unwrap().push((request_line.clone(), Instant::now()));
+
+                let (status, body) = match routes.iter().position(|(prefix, _, _)| path.starts_with(prefix)) {
+                    Some(i) => {
+                        let (prefix, status, body) = routes[i];
+                        if routes[i + 1..].iter().any(|(p, _, _)| *p == prefix) {
+                            routes.remove(i);
+                        }
+                        (status, body)
+                    }
+                    None => (404, ""),
+                };
+                let retry_after = if status == 503 { "Retry-After: 1\r\n" } else { "" };
+                let head = format!(
+                    "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+                    status,
+                    body.len(),
+                    retry_after
+                );
+                let _ = stream.write_all(head.as_bytes());
+                if method != "HEAD" {
+                    let _ = stream.write_all(body.as_bytes());
+                }
+            }
+        });
+        MockServer { base_url, requests }
+    }
+
+    fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().iter().map(|(line, _)| line.clone()).collect()
+    }
+
+    fn arrivals(&self) -> Vec<Instant> {
+        self.requests.lock().unwrap().iter().map(|(_, at)| *at).collect()
+    }
+}
+
+fn candidate(url: &str, artist: &str, album: &str) -> ArtCandidate {
+    ArtCandidate { url: url.to_string(), artist: artist.to_string(), album: album.to_string() }
+}
+
+#[tokio::test]
+async fn deezer_lists_albums_with_covers() {
+    let server = MockServer::start(vec![("/search/album", 200, include_str!("fixtures/deezer_search.json"))]);
+    let deezer = Deezer { base_url: server.base_url.clone() };
+
+    let candidates = deezer.fetch("Daft Punk", "Discovery").await.unwrap();
+    assert_eq!(candidates.len(), 2);
+    assert_eq!(candidates[1], candidate(
+        "https://e-cdns-images.dzcdn.net/images/cover/2e018122cb56986277102d2041a592c8/500x500-000000-80-0-0.jpg",
+        "Daft Punk",
+        "Discovery",
+    ));
+    assert_eq!(server.requests(), vec!["GET /search/album?q=Daft%20Punk%20Discovery HTTP/1.1"]);
+}
+
+#[tokio::test]
+async fn itunes_asks_for_larger_artwork() {
+    let server = MockServer::start(vec![("/search", 200, include_str!("fixtures/itunes_search.json"))]);
+
+    let candidates = fetch_itunes_art(&format!("{}/", server.base_url), "Daft Punk", "Discovery").await.unwrap();
+    assert_eq!(candidates, vec![candidate(
+        "https://is1-ssl.mzstatic.com/image/thumb/Music/v4/cf/2d/14/cf2d1478.jpg/600x600bb.jpg",
+        "Daft Punk",
+        "Discovery",
+    )]);
+    // A trailing slash on the base URL doesn't double up
+    assert!(server.requests()[0].starts_with("GET /search?term=Daft%20Punk%20Discovery&entity=album"));
+}
+
+#[tokio::test]
+async fn musicbrainz_skips_releases_without_cover() {
+    let server = MockServer::start(vec![
+        ("/ws/2/release/", 200, include_str!("fixtures/musicbrainz_releases.json")),
+        ("/release/48117b82-8a5a-4f8c-9d4e-000000000002/front-500", 200, ""),
+    ]);
+    let musicbrainz = MusicBrainz { base_url: server.base_url.clone(), cover_base_url: server.base_url.clone() };
+
+    let candidates = musicbrainz.fetch("Daft Punk", "Discovery").await.unwrap();
+    assert_eq!(candidates, vec![candidate(
+        &format!("{}/release/48117b82-8a5a-4f8c-9d4e-000000000002/front-500", server.base_url),
+        "Daft Punk & Romanthony",
+        "Discovery (Deluxe Edition)",
+    )]);
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert!(requests[1].starts_with("HEAD /release/0e4d3b0d-3f4f-4a6b-9bde-000000000001/front-500"));
+}
+
+#[tokio::test]
+async fn lastfm_needs_a_key() {
+    let server = MockServer::start(vec![("/2.0/", 200, include_str!("fixtures/lastfm_search.json"))]);
+    let lastfm = LastFm { base_url: server.base_url.clone(), api_key: Some("k".to_string()) };
+
+    let candidates = lastfm.fetch("Daft Punk", "Discovery").await.unwrap();
+    assert_eq!(candidates.len(), 2);
+    assert_eq!(candidates[1].url, "https://lastfm.freetls.fastly.net/i/u/300x300/a.png");
+    assert!(server.requests()[0].contains("album=Discovery&artist=Daft%20Punk&"));
+
+    let keyless = LastFm { base_url: server.base_url.clone(), api_key: None };
+    assert!(keyless.fetch("Daft Punk", "Discovery").await.unwrap().is_empty());
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn picks_the_matching_album_not_the_first_hit() {
+    let server = MockServer::start(vec![
+        ("/search/album", 200, include_str!("fixtures/deezer_search.json")),
+        ("/2.0/", 200, include_str!("fixtures/lastfm_search.json")),
+    ]);
+    let deezer: Vec<Box<dyn ArtProvider>> = vec![Box::new(Deezer { base_url: server.base_url.clone() })];
+    let found = album_art::fetch_from(&deezer, "Daft Punk", "Discovery", 0.6).await.unwrap().unwrap();
+    assert!(found.url.contains("2e018122cb56986277102d2041a592c8"));
+
+    // Last.fm searches by album alone; the artist decides between the two "Discovery" albums
+    let lastfm: Vec<Box<dyn ArtProvider>> = vec![Box::new(LastFm { base_url: server.base_url.clone(), api_key: Some("k".to_string()) })];
+    let found = album_art::fetch_from(&lastfm, "daft punk", "discovery", 0.6).await.unwrap().unwrap();
+    assert_eq!(found.url, "https://lastfm.freetls.fastly.net/i/u/300x300/a.png");
+
+    // Nothing close enough is "not found" rather than the wrong cover
+    assert_eq!(album_art::fetch_from(&deezer, "Daft Punk", "Random Access Memories", 0.6).await, Ok(None));
+}
+
+#[test]
+fn scores_ignore_case_punctuation_and_editions() {
+    assert_eq!(album_art::similarity("The Wall [Remastered]", "wall"), 1.0);
+    assert_eq!(album_art::similarity("Don't Stop!", "dont stop"), 1.0);
+    assert!(album_art::similarity("Discovery", "Discovry") > 0.85);
+    assert!(album_art::similarity("Discovery", "Homework") < 0.3);
+
+    let found = candidate("u", "Pink Floyd", "The Dark Side of the Moon (2011 Remaster)");
+    assert_eq!(album_art::match_score("pink floyd", "Dark Side of the Moon", &found), 1.0);
+    // An unknown artist is judged on the album alone
+    assert_eq!(album_art::match_score("", "Dark Side of the Moon", &found), 1.0);
+    assert!(album_art::match_score("Tame Impala", "Dark Side of the Moon", &found) < 0.6);
+
+    let candidates = [candidate("a", "Pink Floyd", "Animals"), candidate("b", "Pink Floyd", "The Wall")];
+    let (best, score) = album_art::best_match("Pink Floyd", "Wall", &candidates, 0.6).unwrap();
+    assert_eq!((best.url.as_str(), score), ("b", 1.0));
+    assert!(album_art::best_match("Pink Floyd", "Meddle", &candidates, 0.6).is_none());
+}
+
+#[tokio::test]
+async fn falls_back_to_next_provider() {
+    let server = MockServer::start(vec![
+        ("/search/album", 500, "{}"),
+        ("/search", 200, include_str!("fixtures/itunes_search.json")),
+    ]);
+    let providers: Vec<Box<dyn ArtProvider>> = vec![
+        Box::new(Deezer { base_url: server.base_url.clone() }),
+        Box::new(Itunes { base_url: server.base_url.clone() }),
+    ];
+
+    let result = album_art::fetch_from(&providers, "Daft Punk", "Discovery", 0.6).await.unwrap().unwrap();
+    assert_eq!(result.provider, "itunes");
+}
+
+#[tokio::test]
+async fn retries_a_busy_service_after_it_asks() {
+    let server = MockServer::start(vec![
+        ("/search/album", 503, ""),
+        ("/search/album", 200, include_str!("fixtures/deezer_search.json")),
+    ]);
+    let deezer = Deezer { base_url: server.base_url.clone() };
+
+    let started = Instant::now();
+    assert_eq!(deezer.fetch("Daft Punk", "Discovery").await.unwrap().len(), 2);
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn failures_say_what_went_wrong() {
+    let server = MockServer::start(vec![
+        ("/search/album", 200, r#"{"error": {"type": "DataException"}}"#),
+        ("/2.0/", 503, ""),
+        ("/ws/2/recording/", 500, "{}"),
+    ]);
+    // Nothing listens there once the listener is dropped
+    let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let providers: Vec<Box<dyn ArtProvider>> = vec![
+        Box::new(Deezer { base_url: server.base_url.clone() }),
+        Box::new(Itunes { base_url: format!("http://{}", closed) }),
+    ];
+
+    let failures = album_art::fetch_from(&providers, "Daft Punk", "Discovery", 0.6).await.unwrap_err();
+    assert_eq!(failures.iter().map(|(provider, _)| *provider).collect::<Vec<_>>(), ["deezer", "itunes"]);
+    assert!(matches!(failures[0].1, ProviderError::Unexpected(_)));
+    assert!(matches!(failures[1].1, ProviderError::Offline(_)));
+
+    // Still busy after the retries
+    let lastfm = LastFm { base_url: server.base_url.clone(), api_key: Some("k".to_string()) };
+    assert_eq!(lastfm.fetch("Daft Punk", "Discovery").await, Err(ProviderError::RateLimited(StatusCode::SERVICE_UNAVAILABLE)));
+    assert_eq!(
+        fetch_metadata(&server.base_url, "daft punk", "discovery", "one more time").await.err(),
+        Some(ProviderError::Http(StatusCode::INTERNAL_SERVER_ERROR))
+    );
+}
+
+#[test]
+fn a_busy_answer_holds_back_requests_already_waiting() {
+    let server = MockServer::start(vec![
+        ("/2.0/", 503, ""),
+        ("/2.0/", 200, include_str!("fixtures/lastfm_search.json")),
+    ]);
+
+    // Both start together; the second is waiting for its turn when the first is told to retry after a second
+    let lookups: Vec<_> = (0..2)
+        .map(|_| {
+            let lastfm = LastFm { base_url: server.base_url.clone(), api_key: Some("k".to_string()) };
+            thread::spawn(move || {
+                let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+                rt.block_on(lastfm.fetch("Daft Punk", "Discovery"))
+            })
+        })
+        .collect();
+    for lookup in lookups {
+        assert!(lookup.join().unwrap().is_ok());
+    }
+    let arrivals = server.arrivals();
+    assert_eq!(arrivals.len(), 3);
+    assert!(arrivals[1] - arrivals[0] >= Duration::from_secs(1));
+}
+
+#[test]
+fn musicbrainz_pace_holds_across_threads() {
+    let server = MockServer::start(vec![("/ws/2/recording/", 200, include_str!("fixtures/musicbrainz_recordings.json"))]);
+
+    // Like the GUI's lookups: each thread with a runtime of its own
+    let started = Instant::now();
+    let lookups: Vec<_> = (0..2)
+        .map(|_| {
+            let base_url = server.base_url.clone();
+            thread::spawn(move || {
+                let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+                rt.block_on(fetch_metadata(&base_url, "daft punk", "discovery", "one more time"))
+            })
+        })
+        .collect();
+    for lookup in lookups {
+        assert!(matches!(lookup.join().unwrap(), Ok(Some(_))));
+    }
+    assert!(started.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn configured_endpoints_reach_providers() {
+    let server = MockServer::start(vec![("/search/album", 200, include_str!("fixtures/deezer_search.json"))]);
+    let json = format!(r#"{{ "art_providers": [{{ "name": "deezer" }}], "endpoints": {{ "deezer": "{}" }} }}"#, server.base_url);
+    let config: Config = serde_json::from_str(&json).unwrap();
+    // Endpoints left out of the file keep their defaults
+    assert_eq!(config.endpoints.itunes, "https://itunes.apple.com");
+
+    let providers = album_art::providers_from_config(&config);
+    assert_eq!(providers[0].name(), "deezer");
+    assert!(!providers[0].fetch("Daft Punk", "Discovery").await.unwrap().is_empty());
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn metadata_from_recording_search() {
+    let server = MockServer::start(vec![("/ws/2/recording/", 200, include_str!("fixtures/musicbrainz_recordings.json"))]);
+
+    let track = fetch_metadata(&server.base_url, "daft punk", "discovery", "one more time").await.unwrap().unwrap();
+    assert_eq!(track.title, "One More Time");
+    assert_eq!(track.artist, "Daft Punk");
+    assert_eq!(track.album, "Discovery");
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use common::{temp_dir, track};
+use psp_playlist_maker::music::Track;
+use psp_playlist_maker::relink::{self, plan_relink, DEFAULT_MIN_CONFIDENCE};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn fake_stick(name: &str) -> PathBuf {
+    let root = temp_dir("relink", name);
+    fs::create_dir(root.join("MUSIC")).unwrap();
+    root
+}
+
+/// An indexed track whose file exists on the stick at `relative` (from MUSIC)
+fn on_stick(root: &Path, relative: &str, artist: &str, album: &str, title: &str, size: u64) -> Track {
+    let path = root.join("MUSIC").join(relative);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, vec![0u8; size as usize]).unwrap();
+    Track { size_bytes: size, ..track(&path.to_string_lossy(), artist, album, title) }
+}
+
+fn entries(list: &[&str]) -> Vec<String> {
+    list.iter().map(|e| e.to_string()).collect()
+}
+
+#[test]
+fn a_file_name_alone_is_not_enough_to_relink_unasked() {
+    let root = fake_stick("name_only");
+    let tracks = vec![on_stick(&root, "Elsewhere/Other/song.mp3", "Someone", "Something", "Different", 10)];
+
+    let report = plan_relink(&root, &root.join("MUSIC"), &entries(&["/MUSIC/Old Artist/Old Album/song.mp3"]), &tracks);
+    let proposal = &report.proposals[0];
+    assert_eq!(proposal.matched_on, ["file name"]);
+    assert_eq!(proposal.replacement, "/MUSIC/Elsewhere/Other/song.mp3");
+    // 0.5 of the 0.7 a path-only entry can reach, just under the threshold
+    assert!((proposal.confidence - 0.5 / 0.7).abs() < 1e-6);
+    assert!(proposal.confidence < DEFAULT_MIN_CONFIDENCE);
+    assert_eq!(report.confident(DEFAULT_MIN_CONFIDENCE).count(), 0);
+    assert_eq!(report.uncertain(DEFAULT_MIN_CONFIDENCE).count(), 1);
+}
+
+#[test]
+fn names_and_folders_together_are_certain() {
+    let root = fake_stick("path");
+    let tracks = vec![on_stick(&root, "Air/Moon Safari (Remaster)/02 Sexy Boy.mp3", "Air", "Moon Safari", "Sexy Boy", 10)];
+
+    // File name, title from "02 Sexy Boy", artist and album from the folders
+    let report = plan_relink(&root, &root.join("MUSIC"), &entries(&["/MUSIC/Air/Moon Safari/02 Sexy Boy.mp3"]), &tracks);
+    let proposal = &report.proposals[0];
+    assert_eq!(proposal.matched_on, ["file name", "title", "artist", "album"]);
+    assert!((proposal.confidence - 1.0).abs() < 1e-6);
+    assert_eq!(report.confident(DEFAULT_MIN_CONFIDENCE).count(), 1);
+}
+
+#[test]
+fn a_stale_index_row_adds_size_and_tags() {
+    let root = fake_stick("stale");
+    let moved = on_stick(&root, "Renamed/track.mp3", "Air", "Moon Safari", "Kelly Watch the Stars", 1234);
+    // The same file's row from before the move, still in the index
+    let old_path = root.join("MUSIC/Air/Moon Safari/03 Kelly.mp3");
+    let stale = Track { size_bytes: 1234, ..track(&old_path.to_string_lossy(), "Air", "Moon Safari", "Kelly Watch the Stars") };
+    let tracks = vec![moved, stale];
+
+    let report = plan_relink(&root, &root.join("MUSIC"), &entries(&["/MUSIC/Air/Moon Safari/03 Kelly.mp3"]), &tracks);
+    let proposal = &report.proposals[0];
+    assert_eq!(proposal.track, 0);
+    assert_eq!(proposal.matched_on, ["size", "title", "artist", "album"]);
+    // Everything but the file name, out of the full 1.0 now that the size is known
+    assert!((proposal.confidence - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn ties_halve_confidence_and_weak_matches_are_dropped() {
+    let root = fake_stick("ties");
+    let tracks = vec![
+        on_stick(&root, "A/One/intro.mp3", "A", "One", "Opening", 10),
+        on_stick(&root, "B/Two/intro.mp3", "B", "Two", "Opening", 10),
+        on_stick(&root, "C/Gone/other.mp3", "C", "Elsewhere", "Unrelated", 10),
+    ];
+    let list = entries(&["/MUSIC/X/Y/intro.mp3", "/MUSIC/Q/Gone/lost.mp3"]);
+    let report = plan_relink(&root, &root.join("MUSIC"), &list, &tracks);
+
+    assert_eq!(report.proposals.len(), 1);
+    assert!((report.proposals[0].confidence - 0.5 / 0.7 / 2.0).abs() < 1e-6);
+    // Only the album folder name would agree: 0.05 of 0.7 isn't worth proposing
+    assert_eq!(report.unmatched, ["/MUSIC/Q/Gone/lost.mp3"]);
+}
+
+#[test]
+fn applies_proposals_in_place() {
+    let root = fake_stick("apply");
+    let tracks = vec![on_stick(&root, "Air/Moon Safari (Remaster)/02 Sexy Boy.mp3", "Air", "Moon Safari", "Sexy Boy", 10)];
+    let playlist = root.join("MUSIC/mix.m3u8");
+    fs::write(&playlist, "#EXTM3U\n#EXTINF:298,Air - Sexy Boy\n/MUSIC/Air/Moon Safari/02 Sexy Boy.mp3\n").unwrap();
+
+    let report = relink::relink_playlist(&root, &playlist, &tracks).unwrap();
+    let confident: Vec<_> = report.confident(DEFAULT_MIN_CONFIDENCE).collect();
+    assert_eq!(relink::apply(&playlist, &confident).unwrap(), 1);
+    assert_eq!(
+        fs::read_to_string(&playlist).unwrap(),
+        "#EXTM3U\n#EXTINF:298,Air - Sexy Boy\n/MUSIC/Air/Moon Safari (Remaster)/02 Sexy Boy.mp3\n"
+    );
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use common::{temp_dir, track};
+use psp_playlist_maker::music::Track;
+use psp_playlist_maker::playlist;
+use psp_playlist_maker::sync::{plan_sync, sync_tracks, SyncAction, SyncOptions};
+use std::fs;
+use std::path::Path;
+
+/// A tagged track backed by a real file holding `content`
+fn source(dir: &Path, name: &str, title: &str, content: &[u8]) -> Track {
+    let path = dir.join(name);
+    fs::write(&path, content).unwrap();
+    let mut t = track(&path.to_string_lossy(), "Artist", "Album", title);
+    t.size_bytes = content.len() as u64;
+    t
+}
+
+#[test]
+fn plans_layout_paths_and_copies() {
+    let dir = temp_dir("sync", "plan");
+    let mut first = source(&dir, "a.mp3", "Intro", b"one");
+    first.track_number = Some(1);
+    let tracks = vec![first, source(&dir, "b.mp3", "Outro", b"two")];
+    let music = dir.join("psp/MUSIC");
+
+    let report = plan_sync(&tracks, &[1, 0], &music, &SyncOptions::default());
+    let dests: Vec<_> = report.items.iter().map(|i| i.dest.clone()).collect();
+    assert_eq!(dests, [music.join("Artist/Album/Outro.mp3"), music.join("Artist/Album/01 Intro.mp3")]);
+    assert_eq!(report.items[0].track, 1);
+    assert_eq!(report.copied(), 2);
+    assert_eq!(report.bytes_copied(), 6);
+    assert!(report.renamed.is_empty());
+}
+
+#[test]
+fn numbers_tracks_that_land_on_the_same_name() {
+    let dir = temp_dir("sync", "dupes");
+    let tracks = vec![
+        source(&dir, "a.mp3", "Song", b"a"),
+        source(&dir, "b.mp3", "song", b"b"),
+        source(&dir, "c.mp3", "Song", b"c"),
+    ];
+    let music = dir.join("psp/MUSIC");
+
+    let report = plan_sync(&tracks, &[0, 1, 2], &music, &SyncOptions::default());
+    let names: Vec<_> = report.items.iter().map(|i| i.dest.file_name().unwrap().to_string_lossy().to_string()).collect();
+    assert_eq!(names, ["Song.mp3", "song (2).mp3", "Song (3).mp3"]);
+
+    // Without an extension in the layout the number isn't followed by a dot
+    let options = SyncOptions { layout: "{title}".to_string(), ..SyncOptions::default() };
+    let report = plan_sync(&tracks, &[0, 2], &music, &options);
+    assert_eq!(report.items[0].dest, music.join("Song"));
+    assert_eq!(report.items[1].dest, music.join("Song (2)"));
+}
+
+#[test]
+fn skips_what_is_already_on_the_device() {
+    let dir = temp_dir("sync", "present");
+    let tracks = vec![source(&dir, "a.mp3", "Same", b"abcd"), source(&dir, "b.mp3", "Resized", b"abcd")];
+    let music = dir.join("psp/MUSIC");
+    let album = music.join("Artist/Album");
+    fs::create_dir_all(&album).unwrap();
+    fs::write(album.join("Same.mp3"), b"abcd").unwrap();
+    fs::write(album.join("Resized.mp3"), b"abc").unwrap();
+
+    let report = plan_sync(&tracks, &[0, 1], &music, &SyncOptions::default());
+    assert_eq!(report.items[0].action, SyncAction::Skip);
+    assert_eq!(report.items[1].action, SyncAction::Copy);
+
+    // Same size, different content: only a hash check notices
+    fs::write(album.join("Same.mp3"), b"abcX").unwrap();
+    let report = plan_sync(&tracks, &[0], &music, &SyncOptions::default());
+    assert_eq!(report.items[0].action, SyncAction::Skip);
+    let options = SyncOptions { verify_hash: true, ..SyncOptions::default() };
+    let report = plan_sync(&tracks, &[0], &music, &options);
+    assert_eq!(report.items[0].action, SyncAction::Copy);
+}
+
+#[test]
+fn copies_leave_no_partial_files() {
+    let dir = temp_dir("sync", "copy");
+    let tracks = vec![source(&dir, "a.mp3", "Song", b"mp3 data"), source(&dir, "b.mp3", "Song", b"more mp3 data")];
+    let music = dir.join("psp/MUSIC");
+
+    let report = sync_tracks(&tracks, &[0, 1], &music, &SyncOptions::default(), |_| {});
+    assert_eq!(report.copied(), 2);
+    let album = music.join("Artist/Album");
+    assert_eq!(fs::read(album.join("Song.mp3")).unwrap(), b"mp3 data");
+    assert_eq!(fs::read(album.join("Song (2).mp3")).unwrap(), b"more mp3 data");
+    assert_eq!(fs::read_dir(&album).unwrap().count(), 2);
+
+    // A dry run plans the same but writes nothing
+    let fresh = dir.join("fresh/MUSIC");
+    let options = SyncOptions { dry_run: true, ..SyncOptions::default() };
+    let report = sync_tracks(&tracks, &[0], &fresh, &options, |_| {});
+    assert_eq!(report.copied(), 1);
+    assert!(!fresh.exists());
+}
+
+#[test]
+fn playlists_follow_the_files_sync_renamed() {
+    let dir = temp_dir("sync", "renamed");
+    let tracks = vec![source(&dir, "a.mp3", "What?", b"a"), source(&dir, "b.mp3", "Fine", b"b")];
+    let music = dir.join("psp/MUSIC");
+
+    let report = plan_sync(&tracks, &[0, 1], &music, &SyncOptions::default());
+    assert_eq!(report.renamed.len(), 1);
+    assert_eq!(report.renamed[0].original, "MUSIC/Artist/Album/What?.mp3");
+    assert_eq!(report.renamed[0].sanitized, "MUSIC/Artist/Album/What_.mp3");
+
+    let on_device = report.device_tracks(&tracks, &[0, 1]);
+    let entries: Vec<String> = on_device.iter().map(|t| playlist::to_psp_path(&t.path)).collect();
+    assert_eq!(entries, ["/MUSIC/Artist/Album/What_.mp3", "/MUSIC/Artist/Album/Fine.mp3"]);
+    // Playlists written without a sync get the same names
+    assert_eq!(playlist::to_psp_path("/mnt/psp/MUSIC/Artist/Album/What?.mp3"), "/MUSIC/Artist/Album/What_.mp3");
+}
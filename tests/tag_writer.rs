@@ -0,0 +1,87 @@
+mod common;
+
+use common::{silent_mp3, temp_dir};
+use psp_playlist_maker::db;
+use psp_playlist_maker::tag_writer::{self, TagValues};
+use std::io;
+
+fn values(title: &str, artist: &str) -> TagValues {
+    TagValues {
+        title: title.to_string(),
+        artist: artist.to_string(),
+        album: "Discovery".to_string(),
+        track_number: Some(1),
+        year: Some(2001),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn writes_and_reads_back_tags() {
+    let dir = temp_dir("tags", "roundtrip");
+    let song = dir.join("song.mp3");
+    silent_mp3(&song);
+    assert_eq!(tag_writer::read_tags(&song).unwrap(), TagValues::default());
+
+    let tags = TagValues { album_artist: "Various".to_string(), disc_number: Some(2), genre: "House".to_string(), ..values("One More Time", "Daft Punk") };
+    tag_writer::write_tags(&song, &tags).unwrap();
+    assert_eq!(tag_writer::read_tags(&song).unwrap(), tags);
+}
+
+#[test]
+fn restores_the_file_when_the_edit_cant_be_logged() {
+    let dir = temp_dir("tags", "rollback");
+    let song = dir.join("song.mp3");
+    silent_mp3(&song);
+    let before = values("One More Time", "Daft Punk");
+    tag_writer::write_tags(&song, &before).unwrap();
+    let mut conn = db::init_db(&dir.join("index.db").to_string_lossy()).unwrap();
+
+    let id = tag_writer::apply_edit(&mut conn, &song, &values("Aerodynamic", "Daft Punk")).unwrap();
+    assert_eq!(tag_writer::read_tags(&song).unwrap().title, "Aerodynamic");
+    let logged = db::load_tag_edit(&conn, id).unwrap().unwrap();
+    assert_eq!(logged.before, before);
+
+    // Without its log the edit would be impossible to undo, so it doesn't happen at all
+    conn.execute("DROP TABLE tag_edits", []).unwrap();
+    let err = tag_writer::apply_edit(&mut conn, &song, &values("Digital Love", "Daft Punk")).unwrap_err();
+    assert!(err.to_string().contains("tags restored"), "{}", err);
+    assert_eq!(tag_writer::read_tags(&song).unwrap().title, "Aerodynamic");
+}
+
+#[test]
+fn undoes_edits_newest_first() {
+    let dir = temp_dir("tags", "undo");
+    let (song, other) = (dir.join("song.mp3"), dir.join("other.mp3"));
+    silent_mp3(&song);
+    silent_mp3(&other);
+    let original = values("One More Time", "Daft Punk");
+    tag_writer::write_tags(&song, &original).unwrap();
+    let mut conn = db::init_db(&dir.join("index.db").to_string_lossy()).unwrap();
+
+    let first = tag_writer::apply_edit(&mut conn, &song, &values("One More Time", "Daft Punk feat. Romanthony")).unwrap();
+    let second = tag_writer::apply_edit(&mut conn, &song, &values("One More Time (Radio Edit)", "Daft Punk feat. Romanthony")).unwrap();
+    let elsewhere = tag_writer::apply_edit(&mut conn, &other, &values("Voyager", "Daft Punk")).unwrap();
+
+    // Undoing the first would throw the second away with it
+    let refused = tag_writer::undo_edit(&mut conn, first).unwrap_err();
+    assert_eq!(refused.kind(), io::ErrorKind::InvalidInput);
+    assert!(refused.to_string().contains(&second.to_string()));
+    assert_eq!(tag_writer::read_tags(&song).unwrap().title, "One More Time (Radio Edit)");
+
+    // Edits of another file don't stand in the way
+    assert!(elsewhere > second);
+    tag_writer::undo_edit(&mut conn, second).unwrap();
+    tag_writer::undo_edit(&mut conn, first).unwrap();
+    assert_eq!(tag_writer::read_tags(&song).unwrap(), original);
+    assert_eq!(tag_writer::undo_edit(&mut conn, first).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(tag_writer::undo_edit(&mut conn, 999).unwrap_err().kind(), io::ErrorKind::NotFound);
+
+    // Newest first, all of them or only some
+    let all = db::load_tag_edits(&conn, None, None).unwrap();
+    assert_eq!(all.iter().map(|e| e.id).collect::<Vec<_>>(), [elsewhere, second, first]);
+    assert!(all[1].undone && all[2].undone && !all[0].undone);
+    let song_path = song.to_string_lossy();
+    assert_eq!(db::load_tag_edits(&conn, Some(&song_path), Some(1)).unwrap()[0].id, second);
+    assert_eq!(db::load_tag_edits(&conn, Some(&song_path), Some(usize::MAX)).unwrap().len(), 2);
+}
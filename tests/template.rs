@@ -0,0 +1,41 @@
+use psp_playlist_maker::template;
+
+fn lookup(field: &str) -> Option<String> {
+    let value = match field {
+        "track" => "3",
+        "disc" => "12",
+        "year" => "1999",
+        "title" => "One More Time",
+        "artist" => "",
+        "album" => "A",
+        _ => return None,
+    };
+    Some(value.to_string())
+}
+
+#[test]
+fn pads_numbers_to_the_given_width() {
+    assert_eq!(template::render("{track:02} {title}.mp3", lookup), "03 One More Time.mp3");
+    assert_eq!(template::render("{disc:02}-{track:3}", lookup), "12-003");
+    // Wider values are kept whole
+    assert_eq!(template::render("{year:02}", lookup), "1999");
+}
+
+#[test]
+fn leaves_unknown_fields_and_stray_braces() {
+    assert_eq!(template::render("{colour}/{title}", lookup), "{colour}/One More Time");
+    assert_eq!(template::render("{title", lookup), "{title");
+    assert_eq!(template::render("x}{track}", lookup), "x}3");
+}
+
+#[test]
+fn empty_and_text_values_ignore_padding() {
+    assert_eq!(template::render("[{artist}]", lookup), "[]");
+    assert_eq!(template::render("[{artist:02}]", lookup), "[]");
+    assert_eq!(template::render("{album:03}", lookup), "A");
+}
+
+#[test]
+fn components_lose_their_separators() {
+    assert_eq!(template::component(" AC/DC\\Live "), "AC-DC-Live");
+}
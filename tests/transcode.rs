@@ -0,0 +1,195 @@
+mod common;
+
+use common::temp_dir;
+use psp_playlist_maker::transcode::{Encoder, Transcoder, WavEncoder};
+use std::fs;
+use std::io;
+
+fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut out = id.to_vec();
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(body);
+    // Chunks are padded to an even length
+    if body.len() % 2 == 1 {
+        out.push(0);
+    }
+    out
+}
+
+fn fmt(tag: u16, channels: u16, rate: u32, bits: u16) -> Vec<u8> {
+    let align = channels * bits.div_ceil(8);
+    let mut out = Vec::new();
+    out.extend_from_slice(&tag.to_le_bytes());
+    out.extend_from_slice(&channels.to_le_bytes());
+    out.extend_from_slice(&rate.to_le_bytes());
+    out.extend_from_slice(&(rate * align as u32).to_le_bytes());
+    out.extend_from_slice(&align.to_le_bytes());
+    out.extend_from_slice(&bits.to_le_bytes());
+    out
+}
+
+fn riff(chunks: &[Vec<u8>]) -> Vec<u8> {
+    let body: Vec<u8> = chunks.concat();
+    let mut out = b"RIFF".to_vec();
+    out.extend_from_slice(&(4 + body.len() as u32).to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+    out.extend_from_slice(&body);
+    out
+}
+
+fn i16_samples(samples: &[i16]) -> Vec<u8> {
+    samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+}
+
+/// What came out of a conversion
+#[derive(Debug)]
+struct Converted {
+    rate: u32,
+    channels: u16,
+    bits: u16,
+    frames: Vec<(i16, i16)>,
+}
+
+/// Convert a WAV file's bytes and read the result back
+fn convert(name: &str, input: &[u8]) -> io::Result<Converted> {
+    let dir = temp_dir("transcode", name);
+    let (source, dest) = (dir.join("in.wav"), dir.join("out.wav"));
+    fs::write(&source, input)?;
+    WavEncoder.encode(&source, &dest)?;
+    let out = fs::read(&dest)?;
+    assert_eq!(&out[0..4], b"RIFF");
+    assert_eq!(&out[8..16], b"WAVEfmt ");
+    assert_eq!(u32::from_le_bytes(out[4..8].try_into().unwrap()) as usize, out.len() - 8);
+    let rate = u32::from_le_bytes(out[24..28].try_into().unwrap());
+    let channels = u16::from_le_bytes(out[22..24].try_into().unwrap());
+    let bits = u16::from_le_bytes(out[34..36].try_into().unwrap());
+    assert_eq!(&out[36..40], b"data");
+    let data_len = u32::from_le_bytes(out[40..44].try_into().unwrap()) as usize;
+    assert_eq!(data_len, out.len() - 44);
+    let frames = out[44..].chunks_exact(4)
+        .map(|f| (i16::from_le_bytes([f[0], f[1]]), i16::from_le_bytes([f[2], f[3]])))
+        .collect();
+    Ok(Converted { rate, channels, bits, frames })
+}
+
+#[test]
+fn cd_audio_comes_through_unchanged() {
+    let samples = [0, 0, 1000, -1000, i16::MAX, i16::MIN, -1, 1];
+    let input = riff(&[chunk(b"fmt ", &fmt(1, 2, 44_100, 16)), chunk(b"data", &i16_samples(&samples))]);
+    let out = convert("cd", &input).unwrap();
+    assert_eq!((out.rate, out.channels, out.bits), (44_100, 2, 16));
+    assert_eq!(out.frames, [(0, 0), (1000, -1000), (i16::MAX, i16::MIN), (-1, 1)]);
+}
+
+#[test]
+fn reads_every_sample_format() {
+    // Half scale in each format, mono so each comes out on both sides
+    let cases: [(&str, Vec<u8>, Vec<u8>); 5] = [
+        ("u8", fmt(1, 1, 44_100, 8), vec![192]),
+        ("s24", fmt(1, 1, 44_100, 24), vec![0x00, 0x00, 0x40]),
+        ("s32", fmt(1, 1, 44_100, 32), 0x4000_0000i32.to_le_bytes().to_vec()),
+        ("f32", fmt(3, 1, 44_100, 32), 0.5f32.to_le_bytes().to_vec()),
+        ("f64", fmt(3, 1, 44_100, 64), (-0.5f64).to_le_bytes().to_vec()),
+    ];
+    for (name, format, data) in cases {
+        let input = riff(&[chunk(b"fmt ", &format), chunk(b"data", &data)]);
+        let frames = convert(name, &input).unwrap().frames;
+        let expected = if name == "f64" { -16384 } else { 16384 };
+        assert_eq!(frames, [(expected, expected)], "{}", name);
+    }
+}
+
+#[test]
+fn reads_extensible_headers() {
+    let mut format = fmt(0xFFFE, 2, 44_100, 16);
+    format.extend_from_slice(&22u16.to_le_bytes());
+    format.extend_from_slice(&16u16.to_le_bytes());
+    format.extend_from_slice(&3u32.to_le_bytes());
+    // KSDATAFORMAT_SUBTYPE_PCM starts with the plain format tag
+    format.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0x10, 0, 0x80, 0, 0, 0xAA, 0, 0x38, 0x9B, 0x71]);
+    let input = riff(&[chunk(b"fmt ", &format), chunk(b"data", &i16_samples(&[5, -5]))]);
+    assert_eq!(convert("extensible", &input).unwrap().frames, [(5, -5)]);
+}
+
+#[test]
+fn skips_odd_sized_chunks_and_their_padding() {
+    let input = riff(&[
+        chunk(b"LIST", b"abc"),
+        chunk(b"fmt ", &fmt(1, 2, 44_100, 16)),
+        chunk(b"junk", b"x"),
+        chunk(b"data", &i16_samples(&[7, 8, 9, 10])),
+    ]);
+    assert_eq!(convert("odd", &input).unwrap().frames, [(7, 8), (9, 10)]);
+}
+
+#[test]
+fn reads_to_the_end_when_the_data_length_is_unknown() {
+    for (name, len) in [("zero", 0u32), ("max", u32::MAX)] {
+        let mut data = b"data".to_vec();
+        data.extend_from_slice(&len.to_le_bytes());
+        data.extend_from_slice(&i16_samples(&[1, 2, 3, 4]));
+        let input = riff(&[chunk(b"fmt ", &fmt(1, 2, 44_100, 16)), data]);
+        assert_eq!(convert(name, &input).unwrap().frames, [(1, 2), (3, 4)], "{}", name);
+    }
+}
+
+#[test]
+fn brings_channels_down_to_stereo() {
+    // 5.1: front left, front right, then centre, LFE and surrounds, which are dropped
+    let input = riff(&[chunk(b"fmt ", &fmt(1, 6, 44_100, 16)), chunk(b"data", &i16_samples(&[1, 2, 3, 4, 5, 6]))]);
+    assert_eq!(convert("surround", &input).unwrap().frames, [(1, 2)]);
+}
+
+#[test]
+fn resamples_to_44100() {
+    for (rate, frames, expected) in [(48_000, 4800, 4410), (22_050, 100, 200), (96_000, 1000, 459), (8_000, 3, 16)] {
+        let samples: Vec<i16> = (0..frames * 2).map(|i| (i % 100) as i16).collect();
+        let input = riff(&[chunk(b"fmt ", &fmt(1, 2, rate, 16)), chunk(b"data", &i16_samples(&samples))]);
+        let out = convert(&format!("rate{}", rate), &input).unwrap();
+        assert_eq!(out.rate, 44_100);
+        assert_eq!(out.frames.len(), expected, "from {} Hz", rate);
+    }
+
+    // Upsampling interpolates between neighbouring frames
+    let input = riff(&[chunk(b"fmt ", &fmt(1, 1, 22_050, 16)), chunk(b"data", &i16_samples(&[0, 1000]))]);
+    let left: Vec<i16> = convert("interpolate", &input).unwrap().frames.into_iter().map(|(l, _)| l).collect();
+    assert_eq!(left, [0, 500, 1000, 1000]);
+}
+
+#[test]
+fn rejects_what_it_cant_read() {
+    let kind = |name: &str, input: &[u8]| convert(name, input).unwrap_err().kind();
+    // Not a WAV at all: only FLAC is decoded with a helper tool
+    assert_eq!(kind("text", b"just some text"), io::ErrorKind::Unsupported);
+    let no_fmt = riff(&[chunk(b"data", &i16_samples(&[1, 2]))]);
+    assert_eq!(kind("no_fmt", &no_fmt), io::ErrorKind::InvalidData);
+    let no_data = riff(&[chunk(b"fmt ", &fmt(1, 2, 44_100, 16))]);
+    assert_eq!(kind("no_data", &no_data), io::ErrorKind::InvalidData);
+    let adpcm = riff(&[chunk(b"fmt ", &fmt(2, 2, 44_100, 4)), chunk(b"data", &[0, 0])]);
+    assert_eq!(kind("adpcm", &adpcm), io::ErrorKind::InvalidData);
+    let silent = riff(&[chunk(b"fmt ", &fmt(1, 0, 44_100, 16)), chunk(b"data", &[])]);
+    assert_eq!(kind("silent", &silent), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn finds_earlier_conversions_without_reading_the_source() {
+    let dir = temp_dir("transcode", "cache");
+    let source = dir.join("a.wav");
+    fs::write(&source, riff(&[chunk(b"fmt ", &fmt(1, 2, 48_000, 16)), chunk(b"data", &i16_samples(&[1, 2]))])).unwrap();
+    let transcoder = Transcoder::new(Box::new(WavEncoder), dir.join("cache"));
+
+    assert_eq!(transcoder.cached(&source), None);
+    let converted = transcoder.transcode(&source).unwrap();
+    assert!(converted.starts_with(dir.join("cache")));
+    assert_eq!(transcoder.cached(&source), Some(converted.clone()));
+    // Another run over the same cache finds it too
+    let again = Transcoder::new(Box::new(WavEncoder), dir.join("cache"));
+    assert_eq!(again.cached(&source), Some(converted.clone()));
+
+    // A changed source is converted afresh
+    fs::write(&source, riff(&[chunk(b"fmt ", &fmt(1, 2, 48_000, 16)), chunk(b"data", &i16_samples(&[1, 2, 3, 4]))])).unwrap();
+    assert_eq!(transcoder.cached(&source), None);
+    let changed = transcoder.transcode(&source).unwrap();
+    assert_ne!(changed, converted);
+    assert_eq!(transcoder.cached(&source), Some(changed));
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::temp_dir;
+use psp_playlist_maker::verify::{check_entry, resolve_entry, verify_entries, EntryStatus};
+use std::fs;
+use std::path::PathBuf;
+
+/// A fake memory stick with MUSIC/Air/Moon Safari/01 La Femme d'Argent.MP3 and a few oddities
+fn fake_stick(name: &str) -> PathBuf {
+    let root = temp_dir("verify", name);
+    let album = root.join("MUSIC/Air/Moon Safari");
+    fs::create_dir_all(&album).unwrap();
+    fs::write(album.join("01 La Femme d'Argent.MP3"), b"ID3").unwrap();
+    fs::write(album.join("02 Sexy Boy.flac"), b"fLaC").unwrap();
+    fs::write(album.join("03 Kelly.mp3"), b"").unwrap();
+    fs::write(album.join("notes"), b"text").unwrap();
+    root
+}
+
+#[test]
+fn resolves_absolute_and_relative_entries() {
+    let root = fake_stick("resolve");
+    let music = root.join("MUSIC");
+    let file = music.join("Air/Moon Safari/01 La Femme d'Argent.MP3");
+
+    assert_eq!(resolve_entry(&root, &music, "/MUSIC/Air/Moon Safari/01 La Femme d'Argent.MP3"), Some(file.clone()));
+    assert_eq!(resolve_entry(&root, &music, "ms0:/MUSIC/Air/Moon Safari/01 La Femme d'Argent.MP3"), Some(file.clone()));
+    assert_eq!(resolve_entry(&root, &music, "Air\\Moon Safari\\01 La Femme d'Argent.MP3"), Some(file.clone()));
+    assert_eq!(resolve_entry(&root, &music.join("Air"), "../Air/Moon Safari/01 La Femme d'Argent.MP3"), Some(file));
+}
+
+#[test]
+fn resolves_entries_whatever_their_case() {
+    let root = fake_stick("case");
+    let music = root.join("MUSIC");
+    // Each component is found under the name it really has on the stick
+    assert_eq!(
+        resolve_entry(&root, &music, "ms0:/music/AIR/moon safari/01 la femme d'argent.mp3"),
+        Some(music.join("Air/Moon Safari/01 La Femme d'Argent.MP3"))
+    );
+    assert_eq!(resolve_entry(&root, &music, "/MUSIC/Air/Moon Safari/04 Remember.mp3"), None);
+    // A folder is not a track
+    assert_eq!(resolve_entry(&root, &music, "/MUSIC/Air/Moon Safari"), None);
+}
+
+#[test]
+fn checks_what_the_psp_would_play() {
+    let root = fake_stick("check");
+    let music = root.join("MUSIC");
+
+    let ok = check_entry(&root, &music, "/MUSIC/Air/Moon Safari/01 La Femme d'Argent.MP3");
+    assert_eq!(ok.status, EntryStatus::Ok(music.join("Air/Moon Safari/01 La Femme d'Argent.MP3")));
+    assert_eq!(ok.entry, "/MUSIC/Air/Moon Safari/01 La Femme d'Argent.MP3");
+
+    let flac = check_entry(&root, &music, "/MUSIC/Air/Moon Safari/02 Sexy Boy.flac");
+    assert_eq!(flac.status, EntryStatus::Unsupported(".flac files aren't supported".to_string()));
+    let bare = check_entry(&root, &music, "/MUSIC/Air/Moon Safari/notes");
+    assert_eq!(bare.status, EntryStatus::Unsupported("file has no extension".to_string()));
+    let empty = check_entry(&root, &music, "/MUSIC/Air/Moon Safari/03 Kelly.mp3");
+    assert_eq!(empty.status, EntryStatus::Unreadable("empty file".to_string()));
+    let missing = check_entry(&root, &music, "/MUSIC/Air/Moon Safari/04 Remember.mp3");
+    assert_eq!(missing.status, EntryStatus::Missing);
+    assert_eq!(missing.status.label(), "missing");
+
+    let entries: Vec<String> = ["/MUSIC/Air/Moon Safari/04 Remember.mp3", "Air/Moon Safari/01 La Femme d'Argent.MP3"]
+        .iter()
+        .map(|e| e.to_string())
+        .collect();
+    let report = verify_entries(&root, &music, &entries);
+    assert_eq!(report.ok_count(), 1);
+    assert_eq!(report.problems().map(|c| c.entry.as_str()).collect::<Vec<_>>(), ["/MUSIC/Air/Moon Safari/04 Remember.mp3"]);
+}